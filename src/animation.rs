@@ -0,0 +1,215 @@
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+// Node hierarchy, skins and keyframes as they come out of the importer.
+// Everything here is evaluated on the CPU
+
+#[derive(Clone)]
+pub struct NodeData {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    // rest pose, animation channels override these
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+pub struct SkinData {
+    pub name: String,
+    // node index of every joint
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    // every key stores in-tangent, value, out-tangent
+    CubicSpline,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    // one float per morph target
+    Weights,
+}
+
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    // seconds, ascending
+    pub times: Vec<f32>,
+    // flattened, `components` floats per value
+    pub values: Vec<f32>,
+    pub components: usize,
+}
+
+impl Channel {
+    // Samples the channel at `time`, clamped to the first and last key.
+    // Writes `components` floats into `out`
+    pub fn sample_into(&self, time: f32, out: &mut [f32]) {
+        let n = self.components;
+        let last = self.times.len() - 1;
+
+        // in cubic spline mode the value sits between the two tangents
+        let value = |key: usize, part: usize| -> &[f32] {
+            let start = match self.interpolation {
+                Interpolation::CubicSpline => (key * 3 + part) * n,
+                _ => key * n,
+            };
+            &self.values[start..start + n]
+        };
+
+        if time <= self.times[0] {
+            out.copy_from_slice(value(0, 1));
+            return;
+        }
+        if time >= self.times[last] {
+            out.copy_from_slice(value(last, 1));
+            return;
+        }
+
+        let next = self.times.partition_point(|&t| t <= time);
+        let prev = next - 1;
+        let dt = self.times[next] - self.times[prev];
+        let t = (time - self.times[prev]) / dt;
+
+        match self.interpolation {
+            Interpolation::Step => out.copy_from_slice(value(prev, 1)),
+            Interpolation::Linear => {
+                let a = value(prev, 1);
+                let b = value(next, 1);
+                if self.property == Property::Rotation {
+                    let a = Quat::from_slice(a);
+                    let b = Quat::from_slice(b);
+                    a.slerp(b, t).write_to_slice(out);
+                } else {
+                    for i in 0..n {
+                        out[i] = a[i] + (b[i] - a[i]) * t;
+                    }
+                }
+            }
+            Interpolation::CubicSpline => {
+                // hermite, see the glTF spec appendix
+                let t2 = t * t;
+                let t3 = t2 * t;
+                let (p0, m0) = (value(prev, 1), value(prev, 2));
+                let (p1, m1) = (value(next, 1), value(next, 0));
+                for i in 0..n {
+                    out[i] = p0[i] * (2.0 * t3 - 3.0 * t2 + 1.0)
+                        + m0[i] * dt * (t3 - 2.0 * t2 + t)
+                        + p1[i] * (-2.0 * t3 + 3.0 * t2)
+                        + m1[i] * dt * (t3 - t2);
+                }
+                if self.property == Property::Rotation {
+                    Quat::from_slice(out).normalize().write_to_slice(out);
+                }
+            }
+        }
+    }
+
+    pub fn sample(&self, time: f32) -> Vec4 {
+        let mut v = [0.0; 4];
+        self.sample_into(time, &mut v[..self.components.min(4)]);
+        Vec4::from(v)
+    }
+}
+
+pub struct AnimationData {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    // time of the last key of any channel
+    pub duration: f32,
+}
+
+// Local and world transforms of every node
+#[derive(Clone)]
+pub struct Pose {
+    pub translations: Vec<Vec3>,
+    pub rotations: Vec<Quat>,
+    pub scales: Vec<Vec3>,
+    // morph target weights, empty for nodes that aren't animated
+    pub weights: Vec<Vec<f32>>,
+    pub world: Vec<Mat4>,
+}
+
+impl Pose {
+    pub fn rest(nodes: &[NodeData]) -> Self {
+        let mut pose = Self {
+            translations: nodes.iter().map(|n| n.translation).collect(),
+            rotations: nodes.iter().map(|n| n.rotation).collect(),
+            scales: nodes.iter().map(|n| n.scale).collect(),
+            weights: vec![Vec::new(); nodes.len()],
+            world: vec![Mat4::IDENTITY; nodes.len()],
+        };
+        pose.update_world(nodes);
+        pose
+    }
+
+    // Rest pose with the animation applied at `time`
+    pub fn sample(nodes: &[NodeData], animation: &AnimationData, time: f32) -> Self {
+        let mut pose = Self::rest(nodes);
+        pose.apply(animation, time);
+        pose.update_world(nodes);
+        pose
+    }
+
+    // Overwrites the local transforms the animation touches. World
+    // transforms are stale until `update_world`
+    pub fn apply(&mut self, animation: &AnimationData, time: f32) {
+        for channel in &animation.channels {
+            let node = channel.node;
+            match channel.property {
+                Property::Translation => self.translations[node] = channel.sample(time).truncate(),
+                Property::Rotation => self.rotations[node] = Quat::from_vec4(channel.sample(time)),
+                Property::Scale => self.scales[node] = channel.sample(time).truncate(),
+                Property::Weights => {
+                    self.weights[node].resize(channel.components, 0.0);
+                    channel.sample_into(time, &mut self.weights[node]);
+                }
+            }
+        }
+    }
+
+    // Moves the local transforms towards `other` by `t`, 1 is all `other`
+    pub fn blend(&mut self, other: &Pose, t: f32) {
+        for i in 0..self.translations.len() {
+            self.translations[i] = self.translations[i].lerp(other.translations[i], t);
+            self.rotations[i] = self.rotations[i].slerp(other.rotations[i], t);
+            self.scales[i] = self.scales[i].lerp(other.scales[i], t);
+
+            let weights = &other.weights[i];
+            if weights.is_empty() {
+                continue;
+            }
+            self.weights[i].resize(weights.len(), 0.0);
+            for (a, b) in self.weights[i].iter_mut().zip(weights) {
+                *a += (b - *a) * t;
+            }
+        }
+    }
+
+    pub fn update_world(&mut self, nodes: &[NodeData]) {
+        // roots first, then walk down so parents are always done
+        let mut stack: Vec<usize> = (0..nodes.len())
+            .filter(|&i| nodes[i].parent.is_none())
+            .collect();
+        while let Some(i) = stack.pop() {
+            let local = Mat4::from_scale_rotation_translation(
+                self.scales[i],
+                self.rotations[i],
+                self.translations[i],
+            );
+            self.world[i] = match nodes[i].parent {
+                Some(parent) => self.world[parent] * local,
+                None => local,
+            };
+            stack.extend_from_slice(&nodes[i].children);
+        }
+    }
+}
@@ -0,0 +1,371 @@
+use basis_universal::{TranscodeParameters, Transcoder, TranscoderTextureFormat};
+use ktx2::{ColorModel, DfdBlockBasic, SupercompressionScheme, TransferFunction};
+use std::io::Read;
+
+// Transcoding of the Basis Universal ktx2 files KHR_texture_basisu points
+// at. basis-universal only reads .basis files, so the ktx2 is repacked into
+// one first: ETC1S (BasisLZ) keeps its codebooks and per level slices,
+// UASTC gets one slice per level after zstd. Everything comes out as BC7,
+// which keeps the alpha and samples on every GPU the renderer runs on.
+// Only single 2D images, arrays and cube maps are refused before this
+
+pub type Result<T> = std::result::Result<T, String>;
+
+pub struct Transcoded {
+    pub srgb: bool,
+    // one BC7 level per ktx2 level, largest first
+    pub levels: Vec<Vec<u8>>,
+}
+
+// `levels` caps how many mips are transcoded, starting at the largest
+pub fn transcode_bc7(reader: &ktx2::Reader<&[u8]>, levels: usize) -> Result<Transcoded> {
+    transcode(reader, TranscoderTextureFormat::BC7_RGBA, levels)
+}
+
+fn transcode(
+    reader: &ktx2::Reader<&[u8]>,
+    format: TranscoderTextureFormat,
+    levels: usize,
+) -> Result<Transcoded> {
+    let (basis, srgb) = to_basis(reader, levels)?;
+
+    let mut transcoder = Transcoder::new();
+    transcoder
+        .prepare_transcoding(&basis)
+        .map_err(|()| String::from("corrupt codebooks or tables"))?;
+    let levels = (0..levels.min(reader.levels().len()))
+        .map(|level| {
+            let parameters = TranscodeParameters {
+                level_index: level as u32,
+                ..Default::default()
+            };
+            transcoder
+                .transcode_image_level(&basis, format, parameters)
+                .map_err(|e| format!("level {}: {:?}", level, e))
+        })
+        .collect::<Result<Vec<_>>>();
+    transcoder.end_transcoding();
+
+    Ok(Transcoded {
+        srgb,
+        levels: levels?,
+    })
+}
+
+// basis_file_header and basis_slice_desc, both packed
+const HEADER_SIZE: usize = 77;
+const SLICE_DESC_SIZE: usize = 23;
+// the only .basis version the transcoder reads
+const VERSION: u32 = 0x13;
+
+// header flags
+const ETC1S: u32 = 1;
+const HAS_ALPHA_SLICES: u32 = 4;
+const SRGB: u32 = 16;
+// slice flag
+const ALPHA_SLICE: u32 = 1;
+
+// sample channel ids from the KTX2 spec's Basis section
+const UASTC_RGBA: u8 = 3;
+const UASTC_RRRG: u8 = 5;
+
+struct Slice {
+    level: usize,
+    alpha: bool,
+    data: Vec<u8>,
+}
+
+// Returns the .basis file and whether the texels are sRGB
+fn to_basis(reader: &ktx2::Reader<&[u8]>, levels: usize) -> Result<(Vec<u8>, bool)> {
+    let header = reader.header();
+    let block = reader
+        .dfd_blocks()
+        .next()
+        .ok_or("no data format descriptor")?;
+    let dfd = DfdBlockBasic::parse(block.data).map_err(|e| format!("{:?}", e))?;
+    let srgb = dfd.header.transfer_function == Some(TransferFunction::SRGB);
+    let levels = levels.min(header.level_count.max(1) as usize);
+
+    let mut flags = if srgb { SRGB } else { 0 };
+    let mut codebooks = Codebooks::default();
+    let mut slices = Vec::new();
+    match dfd.header.color_model {
+        Some(ColorModel::ETC1S) => {
+            if header.supercompression_scheme != Some(SupercompressionScheme::BasisLZ) {
+                return Err(String::from("ETC1S without BasisLZ"));
+            }
+            flags |= ETC1S;
+            let (global, images) = etc1s_global_data(
+                reader.supercompression_global_data(),
+                header.level_count.max(1) as usize,
+            )?;
+            codebooks = global;
+            for (level, (data, image)) in reader.levels().zip(images).take(levels).enumerate() {
+                let slice = |offset: u32, length: u32| {
+                    data.data
+                        .get(offset as usize..offset as usize + length as usize)
+                        .map(<[u8]>::to_vec)
+                        .ok_or_else(|| format!("level {} slice is out of bounds", level))
+                };
+                slices.push(Slice {
+                    level,
+                    alpha: false,
+                    data: slice(image.rgb_offset, image.rgb_length)?,
+                });
+                // two samples in the descriptor mean every level has both
+                if dfd.sample_information().count() == 2 {
+                    flags |= HAS_ALPHA_SLICES;
+                    slices.push(Slice {
+                        level,
+                        alpha: true,
+                        data: slice(image.alpha_offset, image.alpha_length)?,
+                    });
+                }
+            }
+        }
+        Some(ColorModel::UASTC) => {
+            let channel = dfd.sample_information().next().map(|s| s.channel_type);
+            if matches!(channel, Some(UASTC_RGBA | UASTC_RRRG)) {
+                flags |= HAS_ALPHA_SLICES;
+            }
+            for (level, data) in reader.levels().take(levels).enumerate() {
+                let data = match header.supercompression_scheme {
+                    None => data.data.to_vec(),
+                    Some(SupercompressionScheme::Zstandard) => {
+                        let mut decoded = Vec::new();
+                        ruzstd::decoding::StreamingDecoder::new(data.data)
+                            .map_err(|e| e.to_string())?
+                            .read_to_end(&mut decoded)
+                            .map_err(|e| e.to_string())?;
+                        decoded
+                    }
+                    Some(scheme) => return Err(format!("UASTC with {:?}", scheme)),
+                };
+                slices.push(Slice {
+                    level,
+                    alpha: false,
+                    data,
+                });
+            }
+        }
+        other => return Err(format!("color model {:?}", other)),
+    }
+
+    // header, slice descriptions, codebooks and tables, then the slices
+    let descs_size = slices.len() * SLICE_DESC_SIZE;
+    let mut data = Vec::new();
+    let mut offset = HEADER_SIZE + descs_size;
+    let mut descs = Vec::with_capacity(descs_size);
+    let mut section = |bytes: &[u8], data: &mut Vec<u8>| {
+        let at = offset;
+        data.extend_from_slice(bytes);
+        offset += bytes.len();
+        at as u32
+    };
+    let endpoints_offset = section(&codebooks.endpoints, &mut data);
+    let selectors_offset = section(&codebooks.selectors, &mut data);
+    let tables_offset = section(&codebooks.tables, &mut data);
+    for slice in &slices {
+        let width = (header.pixel_width >> slice.level).max(1);
+        let height = (header.pixel_height >> slice.level).max(1);
+        let at = section(&slice.data, &mut data);
+        put(&mut descs, 0, 3);
+        put(&mut descs, slice.level as u32, 1);
+        put(&mut descs, if slice.alpha { ALPHA_SLICE } else { 0 }, 1);
+        put(&mut descs, width, 2);
+        put(&mut descs, height, 2);
+        put(&mut descs, width.div_ceil(4), 2);
+        put(&mut descs, height.div_ceil(4), 2);
+        put(&mut descs, at, 4);
+        put(&mut descs, slice.data.len() as u32, 4);
+        put(&mut descs, crc16(&slice.data) as u32, 2);
+    }
+    let mut body = descs;
+    body.extend_from_slice(&data);
+
+    let mut file = Vec::with_capacity(HEADER_SIZE + body.len());
+    put(&mut file, u32::from(u16::from_be_bytes(*b"Bs")), 2);
+    put(&mut file, VERSION, 2);
+    put(&mut file, HEADER_SIZE as u32, 2);
+    // the header's crc covers what follows it, filled in last
+    put(&mut file, 0, 2);
+    put(&mut file, body.len() as u32, 4);
+    put(&mut file, crc16(&body) as u32, 2);
+    put(&mut file, slices.len() as u32, 3);
+    put(&mut file, 1, 3);
+    let tex_format = if flags & ETC1S != 0 { 0 } else { 1 };
+    put(&mut file, tex_format, 1);
+    put(&mut file, flags, 2);
+    // 2D, no frame rate, reserved and user data
+    put(&mut file, 0, 1);
+    put(&mut file, 0, 3);
+    put(&mut file, 0, 12);
+    put(&mut file, codebooks.endpoint_count, 2);
+    put(&mut file, endpoints_offset, 4);
+    put(&mut file, codebooks.endpoints.len() as u32, 3);
+    put(&mut file, codebooks.selector_count, 2);
+    put(&mut file, selectors_offset, 4);
+    put(&mut file, codebooks.selectors.len() as u32, 3);
+    put(&mut file, tables_offset, 4);
+    put(&mut file, codebooks.tables.len() as u32, 4);
+    put(&mut file, HEADER_SIZE as u32, 4);
+    // no extended data
+    put(&mut file, 0, 8);
+    debug_assert_eq!(file.len(), HEADER_SIZE);
+    let header_crc = crc16(&file[8..]);
+    file[6..8].copy_from_slice(&header_crc.to_le_bytes());
+    file.extend_from_slice(&body);
+    Ok((file, srgb))
+}
+
+#[derive(Default)]
+struct Codebooks {
+    endpoint_count: u32,
+    selector_count: u32,
+    endpoints: Vec<u8>,
+    selectors: Vec<u8>,
+    tables: Vec<u8>,
+}
+
+// Offsets are relative to the start of the level's data
+struct ImageDesc {
+    rgb_offset: u32,
+    rgb_length: u32,
+    alpha_offset: u32,
+    alpha_length: u32,
+}
+
+// The BasisLZ global data: counts and sizes, one image description per
+// level, then the endpoint and selector codebooks, the Huffman tables and
+// extended data that isn't used
+fn etc1s_global_data(data: &[u8], levels: usize) -> Result<(Codebooks, Vec<ImageDesc>)> {
+    let read = |at: usize, size: usize| -> Result<u32> {
+        let bytes = data
+            .get(at..at + size)
+            .ok_or("BasisLZ global data is truncated")?;
+        Ok(bytes.iter().rev().fold(0, |v, &b| v << 8 | b as u32))
+    };
+    let endpoint_count = read(0, 2)?;
+    let selector_count = read(2, 2)?;
+    let endpoints_length = read(4, 4)? as usize;
+    let selectors_length = read(8, 4)? as usize;
+    let tables_length = read(12, 4)? as usize;
+    if endpoint_count == 0 || selector_count == 0 || tables_length == 0 {
+        return Err(String::from("BasisLZ global data has empty codebooks"));
+    }
+
+    // one description per level, arrays and cube maps are refused earlier
+    let descs_end = 20 + 20 * levels;
+    if data.len() < descs_end + endpoints_length + selectors_length + tables_length {
+        return Err(String::from("BasisLZ global data is truncated"));
+    }
+    let images = (20..descs_end)
+        .step_by(20)
+        .map(|at| {
+            Ok(ImageDesc {
+                rgb_offset: read(at + 4, 4)?,
+                rgb_length: read(at + 8, 4)?,
+                alpha_offset: read(at + 12, 4)?,
+                alpha_length: read(at + 16, 4)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let codebooks = &data[descs_end..];
+    let (endpoints, rest) = codebooks.split_at(endpoints_length);
+    let (selectors, rest) = rest.split_at(selectors_length);
+    Ok((
+        Codebooks {
+            endpoint_count,
+            selector_count,
+            endpoints: endpoints.to_vec(),
+            selectors: selectors.to_vec(),
+            tables: rest[..tables_length].to_vec(),
+        },
+        images,
+    ))
+}
+
+// Little endian, `size` bytes
+fn put(out: &mut Vec<u8>, value: u32, size: usize) {
+    out.extend((0..size).map(|i| value.checked_shr(8 * i as u32).unwrap_or(0) as u8));
+}
+
+// The CRC-16 basisu uses for headers and slices
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = !0u16;
+    for &byte in data {
+        let q = byte as u16 ^ (crc >> 8);
+        let k = (q >> 4) ^ q;
+        crc = (crc << 8) ^ k ^ (k << 5) ^ (k << 12);
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Basis");
+
+    fn read(name: &str) -> Vec<u8> {
+        std::fs::read(format!("{}/{}", DIR, name)).unwrap()
+    }
+
+    // the largest difference in any channel against the source image
+    fn max_error(name: &str) -> (bool, u8) {
+        let bytes = read(name);
+        let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+        let transcoded = transcode(&reader, TranscoderTextureFormat::RGBA32, 1).unwrap();
+        let source = image::open(format!("{}/gradient.png", DIR))
+            .unwrap()
+            .into_rgba8();
+        let error = transcoded.levels[0]
+            .iter()
+            .zip(source.as_raw())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap();
+        (transcoded.srgb, error)
+    }
+
+    // ETC1S is the lossy one of the two
+    #[test]
+    fn etc1s_with_alpha() {
+        let (srgb, error) = max_error("gradient_etc1s.ktx2");
+        assert!(srgb);
+        assert!(error <= 32, "{}", error);
+    }
+
+    #[test]
+    fn uastc_with_alpha() {
+        let (srgb, error) = max_error("gradient_uastc.ktx2");
+        assert!(!srgb);
+        assert!(error <= 8, "{}", error);
+    }
+
+    #[test]
+    fn repacked_files_pass_the_checksums() {
+        for name in ["gradient_etc1s.ktx2", "gradient_uastc.ktx2"] {
+            let bytes = read(name);
+            let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+            let (basis, _) = to_basis(&reader, usize::MAX).unwrap();
+            assert!(
+                Transcoder::new().validate_file_checksums(&basis, true),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_global_data_is_an_error() {
+        let mut bytes = read("gradient_etc1s.ktx2");
+        let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+        let start = reader.header().index.sgd_byte_offset as usize;
+        // the codebook lengths point past the end
+        bytes[start + 4..start + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let reader = ktx2::Reader::new(bytes.as_slice()).unwrap();
+        assert!(transcode_bc7(&reader, usize::MAX).is_err());
+    }
+}
@@ -0,0 +1,373 @@
+// Block compression encoders for the offline step in compress.rs: BC1 for
+// opaque images, BC3 (BC1 color plus a BC4 alpha block) when there's alpha.
+// Color endpoints come from the principal axis of the block's colors and get
+// one least squares refinement, roughly what stb_dxt does at normal quality.
+// Colors are fitted on the stored values, sRGB ones included, which is what
+// other encoders do too
+
+use glam::Vec3;
+
+// Compresses one RGBA8 mip level. Blocks hanging over the edge repeat the
+// last row and column, those texels are never sampled
+pub fn compress(width: u32, height: u32, rgba: &[u8], alpha: bool) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let block_bytes = if alpha { 16 } else { 8 };
+    let mut out = Vec::with_capacity(width.div_ceil(4) * height.div_ceil(4) * block_bytes);
+
+    for by in (0..height).step_by(4) {
+        for bx in (0..width).step_by(4) {
+            let mut block = [[0u8; 4]; 16];
+            for (i, texel) in block.iter_mut().enumerate() {
+                let x = (bx + i % 4).min(width - 1);
+                let y = (by + i / 4).min(height - 1);
+                let offset = (y * width + x) * 4;
+                texel.copy_from_slice(&rgba[offset..offset + 4]);
+            }
+
+            if alpha {
+                out.extend_from_slice(&encode_bc3(&block));
+            } else {
+                out.extend_from_slice(&encode_bc1(&block));
+            }
+        }
+    }
+    out
+}
+
+// 4x4 texels, row major. Always the four color mode, the punch through
+// alpha of the three color mode is left to BC3
+pub fn encode_bc1(block: &[[u8; 4]; 16]) -> [u8; 8] {
+    let colors = block.map(|[r, g, b, _]| Vec3::new(r as f32, g as f32, b as f32));
+
+    let (start, end) = principal_endpoints(&colors);
+    let mut best = fit(&colors, quantize(start), quantize(end));
+
+    // least squares endpoints for the indices the first fit picked
+    if let Some((start, end)) = refine(&colors, &best.indices) {
+        let refined = fit(&colors, quantize(start), quantize(end));
+        if refined.error < best.error {
+            best = refined;
+        }
+    }
+
+    // a flat block rarely lands on a 565 color, the palette entries between
+    // the two nearest ones usually get closer
+    if best.endpoints[0] == best.endpoints[1] {
+        let around = fit(
+            &colors,
+            quantize_with(start, f32::ceil),
+            quantize_with(end, f32::floor),
+        );
+        if around.error < best.error {
+            best = around;
+        }
+    }
+
+    let mut out = [0u8; 8];
+    out[0..2].copy_from_slice(&best.endpoints[0].to_le_bytes());
+    out[2..4].copy_from_slice(&best.endpoints[1].to_le_bytes());
+    let bits = (0..16).fold(0u32, |bits, i| bits | (best.indices[i] as u32) << (i * 2));
+    out[4..8].copy_from_slice(&bits.to_le_bytes());
+    out
+}
+
+// BC4 alpha followed by a BC1 color block
+pub fn encode_bc3(block: &[[u8; 4]; 16]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    out[..8].copy_from_slice(&encode_bc4(&block.map(|texel| texel[3])));
+    out[8..].copy_from_slice(&encode_bc1(block));
+    out
+}
+
+// Single channel, in the eight value mode between the block's min and max
+pub fn encode_bc4(values: &[u8; 16]) -> [u8; 8] {
+    let max = *values.iter().max().unwrap();
+    let min = *values.iter().min().unwrap();
+
+    let mut out = [0u8; 8];
+    out[0] = max;
+    out[1] = min;
+    if max == min {
+        return out;
+    }
+
+    // index 0 is max, 1 is min, 2..8 step from max towards min
+    let palette: [f32; 8] = std::array::from_fn(|i| match i {
+        0 => max as f32,
+        1 => min as f32,
+        _ => ((8 - i) as f32 * max as f32 + (i - 1) as f32 * min as f32) / 7.0,
+    });
+
+    let bits = values.iter().enumerate().fold(0u64, |bits, (i, &value)| {
+        let index = nearest(palette.iter().map(|&p| (p - value as f32).abs()));
+        bits | (index as u64) << (i * 3)
+    });
+    out[2..8].copy_from_slice(&bits.to_le_bytes()[..6]);
+    out
+}
+
+struct Fit {
+    endpoints: [u16; 2],
+    indices: [u8; 16],
+    error: f32,
+}
+
+// The ends of the colors projected on the axis they vary most along
+fn principal_endpoints(colors: &[Vec3; 16]) -> (Vec3, Vec3) {
+    let mean = colors.iter().sum::<Vec3>() / 16.0;
+
+    let mut covariance = [0.0f32; 6];
+    for color in colors {
+        let d = *color - mean;
+        covariance[0] += d.x * d.x;
+        covariance[1] += d.x * d.y;
+        covariance[2] += d.x * d.z;
+        covariance[3] += d.y * d.y;
+        covariance[4] += d.y * d.z;
+        covariance[5] += d.z * d.z;
+    }
+    let [xx, xy, xz, yy, yz, zz] = covariance;
+
+    // power iteration, starting from the bounding box diagonal
+    let min = colors.iter().fold(Vec3::splat(255.0), |min, &c| min.min(c));
+    let max = colors.iter().fold(Vec3::ZERO, |max, &c| max.max(c));
+    let mut axis = max - min;
+    for _ in 0..8 {
+        axis = Vec3::new(
+            xx * axis.x + xy * axis.y + xz * axis.z,
+            xy * axis.x + yy * axis.y + yz * axis.z,
+            xz * axis.x + yz * axis.y + zz * axis.z,
+        );
+        let length = axis.length();
+        if length < 1e-6 {
+            // one color, or near enough
+            return (mean, mean);
+        }
+        axis /= length;
+    }
+
+    let projections = colors.map(|color| (color - mean).dot(axis));
+    let low = projections.iter().copied().fold(f32::MAX, f32::min);
+    let high = projections.iter().copied().fold(f32::MIN, f32::max);
+    (mean + axis * high, mean + axis * low)
+}
+
+// Solves for the endpoints that best reproduce the colors with the given
+// indices, None when every texel uses the same weight
+fn refine(colors: &[Vec3; 16], indices: &[u8; 16]) -> Option<(Vec3, Vec3)> {
+    const WEIGHTS: [f32; 4] = [1.0, 0.0, 2.0 / 3.0, 1.0 / 3.0];
+
+    let (mut aa, mut ab, mut bb) = (0.0, 0.0, 0.0);
+    let (mut ax, mut bx) = (Vec3::ZERO, Vec3::ZERO);
+    for (color, &index) in colors.iter().zip(indices) {
+        let a = WEIGHTS[index as usize];
+        let b = 1.0 - a;
+        aa += a * a;
+        ab += a * b;
+        bb += b * b;
+        ax += *color * a;
+        bx += *color * b;
+    }
+
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+    let start = (ax * bb - bx * ab) / determinant;
+    let end = (bx * aa - ax * ab) / determinant;
+    Some((start, end))
+}
+
+// Picks the nearest palette entry for every texel with these endpoints
+fn fit(colors: &[Vec3; 16], start: u16, end: u16) -> Fit {
+    // four colors need the first endpoint to be the larger one. Equal
+    // endpoints would mean three colors, those blocks use index 0 only
+    let (start, end) = if start < end {
+        (end, start)
+    } else {
+        (start, end)
+    };
+    if start == end {
+        let color = expand(start);
+        return Fit {
+            endpoints: [start, end],
+            indices: [0; 16],
+            error: colors.iter().map(|c| c.distance_squared(color)).sum(),
+        };
+    }
+
+    let (a, b) = (expand(start), expand(end));
+    let palette = [a, b, (a * 2.0 + b) / 3.0, (a + b * 2.0) / 3.0];
+
+    let mut indices = [0u8; 16];
+    let mut error = 0.0;
+    for (index, color) in indices.iter_mut().zip(colors) {
+        let distances = palette.map(|p| p.distance_squared(*color));
+        *index = nearest(distances.into_iter()) as u8;
+        error += distances[*index as usize];
+    }
+
+    Fit {
+        endpoints: [start, end],
+        indices,
+        error,
+    }
+}
+
+fn nearest(distances: impl Iterator<Item = f32>) -> usize {
+    distances
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+// RGB565
+fn quantize(color: Vec3) -> u16 {
+    quantize_with(color, f32::round)
+}
+
+fn quantize_with(color: Vec3, round: fn(f32) -> f32) -> u16 {
+    let channel = |v: f32, max: f32| round(v.clamp(0.0, 255.0) * max / 255.0) as u16;
+    channel(color.x, 31.0) << 11 | channel(color.y, 63.0) << 5 | channel(color.z, 31.0)
+}
+
+// Back to 8 bits per channel the way decoders do it, top bits repeated
+fn expand(color: u16) -> Vec3 {
+    let r = (color >> 11) & 31;
+    let g = (color >> 5) & 63;
+    let b = color & 31;
+    Vec3::new(
+        ((r << 3) | (r >> 2)) as f32,
+        ((g << 2) | (g >> 4)) as f32,
+        ((b << 3) | (b >> 2)) as f32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference decoders, both modes of each format
+    fn decode_bc1(block: &[u8]) -> [[u8; 3]; 16] {
+        let c0 = u16::from_le_bytes([block[0], block[1]]);
+        let c1 = u16::from_le_bytes([block[2], block[3]]);
+        let (a, b) = (expand(c0), expand(c1));
+        let palette = if c0 > c1 {
+            [a, b, (a * 2.0 + b) / 3.0, (a + b * 2.0) / 3.0]
+        } else {
+            [a, b, (a + b) / 2.0, Vec3::ZERO]
+        };
+        let bits = u32::from_le_bytes(block[4..8].try_into().unwrap());
+        std::array::from_fn(|i| {
+            let color = palette[(bits >> (i * 2) & 3) as usize].round();
+            [color.x as u8, color.y as u8, color.z as u8]
+        })
+    }
+
+    fn decode_bc4(block: &[u8]) -> [u8; 16] {
+        let (a0, a1) = (block[0] as f32, block[1] as f32);
+        let palette: [f32; 8] = std::array::from_fn(|i| match i {
+            0 => a0,
+            1 => a1,
+            _ if a0 > a1 => ((8 - i) as f32 * a0 + (i - 1) as f32 * a1) / 7.0,
+            6 => 0.0,
+            7 => 255.0,
+            _ => ((6 - i) as f32 * a0 + (i - 1) as f32 * a1) / 5.0,
+        });
+        let mut bytes = [0u8; 8];
+        bytes[..6].copy_from_slice(&block[2..8]);
+        let bits = u64::from_le_bytes(bytes);
+        std::array::from_fn(|i| palette[(bits >> (i * 3) & 7) as usize].round() as u8)
+    }
+
+    // the largest difference in any channel
+    fn bc1_error(block: &[[u8; 4]; 16]) -> u8 {
+        let decoded = decode_bc1(&encode_bc1(block));
+        (0..16)
+            .flat_map(|i| (0..3).map(move |c| (i, c)))
+            .map(|(i, c)| decoded[i][c].abs_diff(block[i][c]))
+            .max()
+            .unwrap()
+    }
+
+    fn bc4_error(values: &[u8; 16]) -> u8 {
+        let decoded = decode_bc4(&encode_bc4(values));
+        (0..16)
+            .map(|i| decoded[i].abs_diff(values[i]))
+            .max()
+            .unwrap()
+    }
+
+    fn opaque(colors: impl Fn(usize) -> [u8; 3]) -> [[u8; 4]; 16] {
+        std::array::from_fn(|i| {
+            let [r, g, b] = colors(i);
+            [r, g, b, 255]
+        })
+    }
+
+    #[test]
+    fn flat_blocks() {
+        // 565 can't hit most colors, the in between palette entries get
+        // within a couple of steps
+        for color in [[0, 0, 0], [255, 255, 255], [200, 100, 50], [13, 77, 201]] {
+            assert!(bc1_error(&opaque(|_| color)) <= 2, "{:?}", color);
+        }
+        for value in [0, 77, 255] {
+            assert_eq!(bc4_error(&[value; 16]), 0);
+        }
+    }
+
+    // A ramp is at most half a palette step off, plus rounding
+    #[test]
+    fn gradient_blocks() {
+        let grey = opaque(|i| [(i * 17) as u8; 3]);
+        assert!(bc1_error(&grey) <= 43, "{}", bc1_error(&grey));
+
+        // red spans 90, so the steps are 30 apart
+        let ramp = opaque(|i| [40 + i as u8 * 6, 200 - i as u8 * 4, 90 + i as u8 * 3]);
+        assert!(bc1_error(&ramp) <= 17, "{}", bc1_error(&ramp));
+
+        let values: [u8; 16] = std::array::from_fn(|i| (i * 17) as u8);
+        assert!(bc4_error(&values) <= 19, "{}", bc4_error(&values));
+        let narrow: [u8; 16] = std::array::from_fn(|i| 100 + i as u8);
+        assert!(bc4_error(&narrow) <= 1, "{}", bc4_error(&narrow));
+    }
+
+    #[test]
+    fn alpha_blocks() {
+        let block: [[u8; 4]; 16] = std::array::from_fn(|i| {
+            let a = if i % 2 == 0 { 0 } else { 255 };
+            [200, 100, 50, a]
+        });
+        let encoded = encode_bc3(&block);
+        // cutouts survive exactly, the color doesn't depend on the alpha
+        assert_eq!(decode_bc4(&encoded[..8]), block.map(|texel| texel[3]));
+        assert_eq!(&encoded[8..], &encode_bc1(&block));
+
+        let fade: [[u8; 4]; 16] = std::array::from_fn(|i| [255, 255, 255, (i * 17) as u8]);
+        let alpha = decode_bc4(&encode_bc3(&fade)[..8]);
+        let error = (0..16)
+            .map(|i| alpha[i].abs_diff(fade[i][3]))
+            .max()
+            .unwrap();
+        assert!(error <= 19, "{}", error);
+    }
+
+    #[test]
+    fn partial_blocks_repeat_the_edge() {
+        // 5x3 is two blocks, the second one only has its first column
+        let rgba: Vec<u8> = (0..15)
+            .flat_map(|i| {
+                if i % 5 == 4 {
+                    [0, 0, 255, 255]
+                } else {
+                    [255, 0, 0, 255]
+                }
+            })
+            .collect();
+        let bc1 = compress(5, 3, &rgba, false);
+        assert_eq!(bc1.len(), 16);
+        assert_eq!(decode_bc1(&bc1[8..]), [[0, 0, 255]; 16]);
+        assert_eq!(compress(5, 3, &rgba, true).len(), 32);
+    }
+}
@@ -0,0 +1,387 @@
+use glam::Vec3;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::camera::{Camera, Projection};
+
+// Everything needed to get back to a viewpoint. Orientation is yaw/pitch like
+// the free camera, fov is vertical and in degrees
+#[derive(Copy, Clone, Debug)]
+pub struct CameraPose {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl CameraPose {
+    pub fn from_camera(camera: &Camera) -> Self {
+        let fov = match camera.projection {
+            Projection::Perspective { yfov, .. } => yfov.to_degrees(),
+            // ortho cameras don't have one, fall back to the free camera default
+            Projection::Orthographic { .. } => 60.0,
+        };
+        Self {
+            position: camera.position,
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            fov,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.position;
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+        camera.up = Vec3::Y;
+
+        let (znear, zfar) = match camera.projection {
+            Projection::Perspective { znear, zfar, .. } => (znear, zfar),
+            Projection::Orthographic { znear, zfar, .. } => (znear, Some(zfar)),
+        };
+        camera.projection = Projection::Perspective {
+            yfov: self.fov.to_radians(),
+            znear,
+            zfar,
+        };
+    }
+
+    fn lerp(a: &Self, b: &Self, t: f32) -> Self {
+        Self {
+            position: a.position.lerp(b.position, t),
+            yaw: a.yaw + (b.yaw - a.yaw) * t,
+            pitch: a.pitch + (b.pitch - a.pitch) * t,
+            fov: a.fov + (b.fov - a.fov) * t,
+        }
+    }
+
+    // uniform Catmull-Rom through p1..p2, p0 and p3 shape the tangents
+    fn catmull_rom(p0: &Self, p1: &Self, p2: &Self, p3: &Self, t: f32) -> Self {
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let w0 = -0.5 * t3 + t2 - 0.5 * t;
+        let w1 = 1.5 * t3 - 2.5 * t2 + 1.0;
+        let w2 = -1.5 * t3 + 2.0 * t2 + 0.5 * t;
+        let w3 = 0.5 * t3 - 0.5 * t2;
+        let f = |a: f32, b: f32, c: f32, d: f32| a * w0 + b * w1 + c * w2 + d * w3;
+
+        Self {
+            position: p0.position * w0 + p1.position * w1 + p2.position * w2 + p3.position * w3,
+            yaw: f(p0.yaw, p1.yaw, p2.yaw, p3.yaw),
+            pitch: f(p0.pitch, p1.pitch, p2.pitch, p3.pitch),
+            fov: f(p0.fov, p1.fov, p2.fov, p3.fov),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PathKind {
+    // passes through every bookmark
+    CatmullRom,
+    // single curve using the bookmarks as control points, only passes
+    // through the first and last one
+    Bezier,
+}
+
+impl PathKind {
+    // as written in bookmark and level files
+    pub fn name(self) -> &'static str {
+        match self {
+            PathKind::CatmullRom => "catmull-rom",
+            PathKind::Bezier => "bezier",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "catmull-rom" => Some(PathKind::CatmullRom),
+            "bezier" => Some(PathKind::Bezier),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CameraPath {
+    pub name: String,
+    pub kind: PathKind,
+    // seconds from the first to the last pose
+    pub duration: f32,
+    pub poses: Vec<CameraPose>,
+}
+
+impl CameraPath {
+    // t is in seconds and clamped to the path
+    pub fn sample(&self, t: f32) -> CameraPose {
+        let n = self.poses.len();
+        if n == 1 || self.duration <= 0.0 {
+            return self.poses[0];
+        }
+        let u = (t / self.duration).clamp(0.0, 1.0);
+        let poses = self.unwrapped();
+
+        match self.kind {
+            PathKind::CatmullRom => {
+                let segments = n - 1;
+                let s = u * segments as f32;
+                let i = (s.floor() as usize).min(segments - 1);
+                let local = s - i as f32;
+
+                // end points are repeated so the curve starts and stops on them
+                let p0 = &poses[i.saturating_sub(1)];
+                let p1 = &poses[i];
+                let p2 = &poses[i + 1];
+                let p3 = &poses[(i + 2).min(n - 1)];
+                CameraPose::catmull_rom(p0, p1, p2, p3, local)
+            }
+            PathKind::Bezier => {
+                // de Casteljau
+                let mut points = poses;
+                for level in 1..n {
+                    for i in 0..n - level {
+                        points[i] = CameraPose::lerp(&points[i], &points[i + 1], u);
+                    }
+                }
+                points[0]
+            }
+        }
+    }
+
+    // Yaw wraps around, 350 and 10 degrees are 20 apart. Each pose's yaw is
+    // moved by whole turns to within 180 degrees of the one before, so the
+    // camera takes the short way round
+    fn unwrapped(&self) -> Vec<CameraPose> {
+        let mut poses = self.poses.clone();
+        for i in 1..poses.len() {
+            let previous = poses[i - 1].yaw;
+            let turns = ((poses[i].yaw - previous) / 360.0).round();
+            poses[i].yaw -= turns * 360.0;
+        }
+        poses
+    }
+}
+
+// Plays a path back on the free camera. Also counts frames, which makes it
+// usable as a repeatable performance capture
+pub struct PathPlayer {
+    pub path: CameraPath,
+    start: f64,
+    frames: u32,
+}
+
+impl PathPlayer {
+    pub fn new(path: CameraPath, now: f64) -> Self {
+        log::info!("playing camera path {:?}", path.name);
+        Self {
+            path,
+            start: now,
+            frames: 0,
+        }
+    }
+
+    // Returns false once the path is done
+    pub fn update(&mut self, camera: &mut Camera, now: f64) -> bool {
+        let elapsed = (now - self.start) as f32;
+        self.path.sample(elapsed).apply(camera);
+        self.frames += 1;
+
+        if elapsed >= self.path.duration {
+            log::info!(
+                "camera path {:?} finished: {} frames in {:.2}s ({:.2} ms/frame)",
+                self.path.name,
+                self.frames,
+                elapsed,
+                elapsed * 1000.0 / self.frames as f32,
+            );
+            return false;
+        }
+        true
+    }
+}
+
+// Bookmarks file, one entry per line:
+//   bookmark <name> <x> <y> <z> <yaw> <pitch> <fov>
+//   path <name> <catmull-rom|bezier> <seconds> <bookmark> <bookmark> ...
+// Names can't contain whitespace. Lines starting with # are ignored
+#[derive(Clone, Default)]
+pub struct Bookmarks {
+    pub bookmarks: Vec<(String, CameraPose)>,
+    // (name, kind, duration, bookmark names)
+    pub paths: Vec<(String, PathKind, f32, Vec<String>)>,
+}
+
+impl Bookmarks {
+    // A missing file just means there are no bookmarks yet
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut bookmarks = Self::default();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if bookmarks.parse_line(line).is_none() {
+                log::warn!("{}:{}: could not parse {:?}", path.display(), line_no + 1, line);
+            }
+        }
+
+        log::info!(
+            "loaded {} bookmarks and {} paths from {}",
+            bookmarks.bookmarks.len(),
+            bookmarks.paths.len(),
+            path.display()
+        );
+        Ok(bookmarks)
+    }
+
+    fn parse_line(&mut self, line: &str) -> Option<()> {
+        let mut tokens = line.split_whitespace();
+        match tokens.next()? {
+            "bookmark" => {
+                let name = tokens.next()?.to_string();
+                let mut numbers = [0.0f32; 6];
+                for n in &mut numbers {
+                    *n = tokens.next()?.parse().ok()?;
+                }
+                let pose = CameraPose {
+                    position: Vec3::new(numbers[0], numbers[1], numbers[2]),
+                    yaw: numbers[3],
+                    pitch: numbers[4],
+                    fov: numbers[5],
+                };
+                self.bookmarks.push((name, pose));
+            }
+            "path" => {
+                let name = tokens.next()?.to_string();
+                let kind = PathKind::from_name(tokens.next()?)?;
+                let duration = tokens.next()?.parse().ok()?;
+                let stops: Vec<String> = tokens.map(String::from).collect();
+                if stops.is_empty() {
+                    return None;
+                }
+                self.paths.push((name, kind, duration, stops));
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut text = String::from("# bookmark <name> <x> <y> <z> <yaw> <pitch> <fov>\n");
+        text += "# path <name> <catmull-rom|bezier> <seconds> <bookmark> ...\n";
+
+        for (name, pose) in &self.bookmarks {
+            text += &format!(
+                "bookmark {} {} {} {} {} {} {}\n",
+                name, pose.position.x, pose.position.y, pose.position.z, pose.yaw, pose.pitch, pose.fov
+            );
+        }
+        for (name, kind, duration, stops) in &self.paths {
+            text += &format!("path {} {} {} {}\n", name, kind.name(), duration, stops.join(" "));
+        }
+
+        fs::write(path, text)
+    }
+
+    pub fn add(&mut self, pose: CameraPose) -> &str {
+        let name = format!("view{}", self.bookmarks.len() + 1);
+        self.bookmarks.push((name, pose));
+        &self.bookmarks.last().unwrap().0
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CameraPose> {
+        self.bookmarks.iter().find(|(n, _)| n == name).map(|(_, pose)| pose)
+    }
+
+    // Resolves the path's bookmark names. Without any paths in the file this
+    // falls back to a Catmull-Rom through every bookmark, two seconds apart
+    pub fn path(&self, index: usize) -> Option<CameraPath> {
+        let Some((name, kind, duration, stops)) = self.paths.get(index) else {
+            if index != 0 || self.bookmarks.len() < 2 {
+                return None;
+            }
+            return Some(CameraPath {
+                name: "all bookmarks".to_string(),
+                kind: PathKind::CatmullRom,
+                duration: 2.0 * (self.bookmarks.len() - 1) as f32,
+                poses: self.bookmarks.iter().map(|(_, pose)| *pose).collect(),
+            });
+        };
+
+        let mut poses = Vec::new();
+        for stop in stops {
+            match self.get(stop) {
+                Some(pose) => poses.push(*pose),
+                None => {
+                    log::warn!("path {:?} references unknown bookmark {:?}", name, stop);
+                    return None;
+                }
+            }
+        }
+
+        Some(CameraPath {
+            name: name.clone(),
+            kind: *kind,
+            duration: *duration,
+            poses,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pose(yaw: f32) -> CameraPose {
+        CameraPose {
+            position: Vec3::ZERO,
+            yaw,
+            pitch: 0.0,
+            fov: 60.0,
+        }
+    }
+
+    fn path(kind: PathKind, yaws: &[f32]) -> CameraPath {
+        CameraPath {
+            name: String::from("test"),
+            kind,
+            duration: 1.0,
+            poses: yaws.iter().map(|&yaw| pose(yaw)).collect(),
+        }
+    }
+
+    #[test]
+    fn yaw_takes_the_short_way_round() {
+        for kind in [PathKind::CatmullRom, PathKind::Bezier] {
+            let halfway = path(kind, &[350.0, 10.0]).sample(0.5).yaw;
+            assert!((halfway - 360.0).abs() < 1e-3, "{:?}: {}", kind, halfway);
+
+            let halfway = path(kind, &[10.0, 350.0]).sample(0.5).yaw;
+            assert!(halfway.abs() < 1e-3, "{:?}: {}", kind, halfway);
+        }
+    }
+
+    #[test]
+    fn yaw_keeps_turning_across_several_bookmarks() {
+        // a full circle in 90 degree steps, stored wrapped
+        let path = path(PathKind::CatmullRom, &[0.0, 90.0, 180.0, 270.0, 0.0]);
+        let yaws: Vec<f32> = (0..=40).map(|i| path.sample(i as f32 / 40.0).yaw).collect();
+        assert!(yaws.windows(2).all(|w| w[1] >= w[0] - 1e-3), "{:?}", yaws);
+        assert!((yaws[40] - 360.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn path_ends_on_its_bookmarks() {
+        let path = path(PathKind::CatmullRom, &[30.0, 200.0, -100.0]);
+        assert_eq!(path.sample(0.0).yaw, 30.0);
+        // -100 is 260 once unwrapped, the same direction
+        assert!((path.sample(1.0).yaw - 260.0).abs() < 1e-3);
+    }
+}
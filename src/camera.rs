@@ -1,5 +1,51 @@
 use glam::{Mat4, Vec3};
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    Perspective {
+        yfov: f32,
+        znear: f32,
+        // None means an infinite far plane (allowed by glTF)
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+impl Projection {
+    // glTF cameras can carry their own aspect ratio, but we always use the
+    // viewport's so nothing gets stretched when the window is resized
+    pub fn matrix(&self, aspect_ratio: f32) -> Mat4 {
+        match *self {
+            Projection::Perspective { yfov, znear, zfar } => match zfar {
+                Some(zfar) => Mat4::perspective_rh(yfov, aspect_ratio, znear, zfar),
+                None => Mat4::perspective_infinite_rh(yfov, aspect_ratio, znear),
+            },
+            Projection::Orthographic {
+                xmag,
+                ymag,
+                znear,
+                zfar,
+            } => Mat4::orthographic_rh(-xmag, xmag, -ymag, ymag, znear, zfar),
+        }
+    }
+}
+
+impl Default for Projection {
+    fn default() -> Self {
+        Projection::Perspective {
+            yfov: f32::to_radians(60.0),
+            znear: 0.025,
+            zfar: Some(8000.0),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub position: Vec3,
     pub target: Vec3,
@@ -8,6 +54,7 @@ pub struct Camera {
     pub up: Vec3,
     pub yaw: f32,
     pub pitch: f32,
+    pub projection: Projection,
 }
 
 impl Camera {
@@ -28,6 +75,33 @@ impl Camera {
             up,
             yaw,
             pitch,
+            projection: Projection::default(),
         }
     }
+
+    // glTF cameras look down -Z with +Y up in node space
+    pub fn from_transform(world: Mat4, projection: Projection) -> Self {
+        let (_, rotation, position) = world.to_scale_rotation_translation();
+        let front = (rotation * Vec3::NEG_Z).normalize();
+        let up = (rotation * Vec3::Y).normalize();
+
+        // keep yaw/pitch in sync so the free camera can take over from here
+        let yaw = f32::atan2(front.z, front.x).to_degrees();
+        let pitch = f32::asin(front.y.clamp(-1.0, 1.0)).to_degrees();
+
+        Self {
+            position,
+            target: position + front,
+            direction: -front,
+            front,
+            up,
+            yaw,
+            pitch,
+            projection,
+        }
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position, self.position + self.front, self.up)
+    }
 }
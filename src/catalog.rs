@@ -0,0 +1,120 @@
+// What's under assets/, one entry per directory with a model in it. The
+// layout is the glTF sample models one: assets/<Name>/glTF/<Name>.gltf and a
+// metadata.json with the display name, a summary and tags
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const ASSETS_DIR: &str = "./assets";
+
+#[derive(Clone, Debug)]
+pub struct AssetEntry {
+    // from metadata.json, the directory name without one
+    pub name: String,
+    pub summary: String,
+    pub tags: Vec<String>,
+    // assets/<Name>, bookmarks are kept there
+    pub dir: PathBuf,
+    pub model: PathBuf,
+}
+
+impl AssetEntry {
+    // A model file outside the catalog. Its directory stands in for the
+    // asset directory
+    pub fn from_model(path: &Path) -> Self {
+        Self {
+            name: crate::model::name_from_path(path),
+            summary: String::new(),
+            tags: Vec::new(),
+            dir: path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            model: path.to_path_buf(),
+        }
+    }
+}
+
+// Sorted by directory name. Directories without a model are left out
+pub fn scan(root: &Path) -> Vec<AssetEntry> {
+    let dirs = match fs::read_dir(root) {
+        Ok(dirs) => dirs,
+        Err(e) => {
+            log::error!("could not list {}: {}", root.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut dirs: Vec<PathBuf> = dirs
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    dirs.sort();
+
+    dirs.into_iter()
+        .filter_map(|dir| {
+            let dir_name = dir.file_name()?.to_string_lossy().into_owned();
+            let model = model_path(&dir, &dir_name)?;
+            let mut entry = AssetEntry {
+                name: dir_name,
+                summary: String::new(),
+                tags: Vec::new(),
+                dir,
+                model,
+            };
+            read_metadata(&mut entry);
+            Some(entry)
+        })
+        .collect()
+}
+
+// An asset directory name (case insensitive) or the path of a model file
+pub fn find(catalog: &[AssetEntry], name: &str) -> Option<AssetEntry> {
+    let by_name = catalog.iter().find(|entry| {
+        entry
+            .dir
+            .file_name()
+            .is_some_and(|dir| dir.to_string_lossy().eq_ignore_ascii_case(name))
+    });
+    match by_name {
+        Some(entry) => Some(entry.clone()),
+        None if Path::new(name).is_file() => Some(AssetEntry::from_model(Path::new(name))),
+        None => None,
+    }
+}
+
+// glTF if the asset has one, then the binary glTF, otherwise an OBJ export
+fn model_path(dir: &Path, name: &str) -> Option<PathBuf> {
+    [
+        dir.join("glTF").join(format!("{}.gltf", name)),
+        dir.join("glTF-Binary").join(format!("{}.glb", name)),
+        dir.join("OBJ").join(format!("{}.obj", name)),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+// Missing or broken metadata just leaves the defaults
+fn read_metadata(entry: &mut AssetEntry) {
+    let path = entry.dir.join("metadata.json");
+    let Ok(text) = fs::read_to_string(&path) else {
+        return;
+    };
+    let metadata: serde_json::Value = match serde_json::from_str(&text) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            log::warn!("{}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    if let Some(name) = metadata["name"].as_str() {
+        entry.name = name.to_string();
+    }
+    if let Some(summary) = metadata["summary"].as_str() {
+        entry.summary = summary.to_string();
+    }
+    if let Some(tags) = metadata["tags"].as_array() {
+        entry.tags = tags
+            .iter()
+            .filter_map(|tag| Some(tag.as_str()?.to_string()))
+            .collect();
+    }
+}
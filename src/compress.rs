@@ -0,0 +1,80 @@
+// Offline texture compression, `bs compress <model>`. Every image the
+// model's materials use gets a BC1 (or BC3 with alpha) .dds written next to
+// it, mips included, in the color space the material uses it in.
+// texture::load then picks those up instead of decoding the PNG or JPEG
+
+use std::path::{Path, PathBuf};
+
+use crate::bc;
+use crate::model::{self, ColorSpace, LoadError, MaterialData};
+use crate::texture::{self, MipLevel, TextureData, TextureFormat, TextureOptions};
+
+// Returns how many textures were written, failures are logged and skipped
+pub fn compress_model(path: &Path) -> Result<usize, LoadError> {
+    let model = model::load(path)?;
+
+    let mut images: Vec<(PathBuf, ColorSpace)> = Vec::new();
+    for texture in model.materials.iter().flat_map(MaterialData::textures) {
+        // the .dds holds one color space, the first use wins
+        match images.iter().find(|(path, _)| *path == texture.path) {
+            None => images.push((texture.path.clone(), texture.color_space)),
+            Some((_, color_space)) if *color_space != texture.color_space => log::warn!(
+                "{} is used as both sRGB and linear, only the first is compressed",
+                texture.path.display()
+            ),
+            Some(_) => {}
+        }
+    }
+
+    let mut written = 0;
+    for (path, color_space) in images {
+        // already compressed
+        let Some(out) = texture::compressed_path(&path) else {
+            continue;
+        };
+        match compress_image(&path, &out, color_space) {
+            Ok(()) => {
+                log::info!("compressed {}", out.display());
+                written += 1;
+            }
+            Err(e) => log::error!("Failed to compress {}: {}", path.display(), e),
+        }
+    }
+    Ok(written)
+}
+
+fn compress_image(path: &Path, out: &Path, color_space: ColorSpace) -> texture::Result<()> {
+    let options = TextureOptions {
+        color_space,
+        ..TextureOptions::default()
+    };
+    // straight from the image, an older .dds next to it would be picked up
+    // by texture::load
+    let bytes = std::fs::read(path)?;
+    let source = texture::decode(&bytes, options)?;
+
+    let alpha = source
+        .levels
+        .iter()
+        .any(|level| level.data.chunks_exact(4).any(|texel| texel[3] != 255));
+    let levels = source
+        .levels
+        .iter()
+        .map(|level| MipLevel {
+            width: level.width,
+            height: level.height,
+            data: bc::compress(level.width, level.height, &level.data, alpha),
+        })
+        .collect();
+
+    let compressed = TextureData {
+        format: if alpha {
+            TextureFormat::Bc3
+        } else {
+            TextureFormat::Bc1
+        },
+        levels,
+        ..source
+    };
+    texture::write_dds(&compressed, out)
+}
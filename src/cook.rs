@@ -0,0 +1,875 @@
+// Cooked models, what `bs cook <model>` writes and the first load of a model
+// leaves behind. Everything the importers and the interleaving in
+// Mesh::from_data produce is stored ready to copy into buffers, so later
+// launches skip parsing the source entirely.
+//
+// Layout, little endian:
+//   header   magic, version, content hash, vertex and index blob offsets
+//   table    source files, name, materials, cameras, meshes
+//   vertices [f32; 14] per vertex, every mesh back to back, 16 byte aligned
+//   indices  u32, every mesh back to back, 16 byte aligned
+//
+// Only static models are cooked. Skinning, morphing and animation need the
+// per attribute MeshData and the node hierarchy, those keep loading from the
+// source
+
+use glam::{Mat3, Mat4, Vec3};
+use memmap2::Mmap;
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::camera::Projection;
+use crate::model::{
+    self, ColorSpace, Filter, LoadError, MaterialData, ModelData, SamplerData, TextureRef,
+    Topology, Wrap,
+};
+
+const MAGIC: [u8; 4] = *b"BSCK";
+// bump when the layout or what the importers produce changes, older files
+// are then re-cooked instead of read. That includes every change to
+// MaterialData, Writer::material and Reader::material list its fields in a
+// fixed order and MaterialData::texture_slots its textures
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 32;
+
+type Vertex = [f32; 14];
+
+#[derive(Debug)]
+pub enum CookError {
+    Io(io::Error),
+    Load(LoadError),
+    // the sources changed since it was cooked, or an older version wrote it
+    Stale,
+    Invalid(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for CookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CookError::Io(e) => write!(f, "{}", e),
+            CookError::Load(e) => write!(f, "{}", e),
+            CookError::Stale => write!(f, "out of date"),
+            CookError::Invalid(what) => write!(f, "invalid cooked file: {}", what),
+            CookError::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for CookError {}
+
+impl From<io::Error> for CookError {
+    fn from(e: io::Error) -> Self {
+        CookError::Io(e)
+    }
+}
+
+impl From<LoadError> for CookError {
+    fn from(e: LoadError) -> Self {
+        CookError::Load(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CookError>;
+
+// Axis aligned, in whatever space the points were in
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    // an empty mesh gets inverted bounds, they vanish in any union
+    fn from_points(points: &[[f32; 3]]) -> Self {
+        points.iter().fold(
+            Bounds {
+                min: Vec3::INFINITY,
+                max: Vec3::NEG_INFINITY,
+            },
+            |bounds, &point| Bounds {
+                min: bounds.min.min(Vec3::from(point)),
+                max: bounds.max.max(Vec3::from(point)),
+            },
+        )
+    }
+
+    pub fn union(self, other: Bounds) -> Bounds {
+        Bounds {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    // bounds of the eight transformed corners
+    pub fn transformed(self, transform: Mat4) -> Bounds {
+        if self.min.cmpgt(self.max).any() {
+            return self;
+        }
+        let corners: [[f32; 3]; 8] = std::array::from_fn(|i| {
+            let corner = Vec3::select(
+                glam::BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                self.max,
+                self.min,
+            );
+            transform.transform_point3(corner).to_array()
+        });
+        Bounds::from_points(&corners)
+    }
+}
+
+// One mesh's slices of the vertex and index blobs, plus everything the
+// renderer needs from its MeshData
+pub struct CookedMesh {
+    pub topology: Topology,
+    pub material: Option<usize>,
+    pub transform: Mat4,
+    // before the transform
+    pub bounds: Bounds,
+    vertices: Range<usize>,
+    indices: Range<usize>,
+}
+
+pub struct CookedModel {
+    // materials and cameras. Meshes live in the blobs below, nodes, skins
+    // and animations are always empty
+    pub data: ModelData,
+    pub meshes: Vec<CookedMesh>,
+    map: Mmap,
+    vertex_offset: usize,
+    index_offset: usize,
+}
+
+impl CookedModel {
+    pub fn vertices(&self, mesh: &CookedMesh) -> &[Vertex] {
+        let size = std::mem::size_of::<Vertex>();
+        let start = self.vertex_offset + mesh.vertices.start * size;
+        let end = self.vertex_offset + mesh.vertices.end * size;
+        bytemuck::cast_slice(&self.map[start..end])
+    }
+
+    pub fn indices(&self, mesh: &CookedMesh) -> &[u32] {
+        let start = self.index_offset + mesh.indices.start * 4;
+        let end = self.index_offset + mesh.indices.end * 4;
+        bytemuck::cast_slice(&self.map[start..end])
+    }
+
+    // every mesh in model space
+    pub fn bounds(&self) -> Bounds {
+        self.meshes
+            .iter()
+            .fold(Bounds::from_points(&[]), |bounds, mesh| {
+                bounds.union(mesh.bounds.transformed(mesh.transform))
+            })
+    }
+}
+
+// Next to the source, e.g. Sponza.gltf.cooked
+pub fn cooked_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".cooked");
+    path.with_file_name(name)
+}
+
+// The model's static parts can be cooked, see the top of the file
+pub fn is_cookable(model: &ModelData) -> bool {
+    model.skins.is_empty()
+        && model.animations.is_empty()
+        && model.meshes.iter().all(|mesh| mesh.targets.is_empty())
+}
+
+// Loads the source and writes its cooked file, for `bs cook`
+pub fn cook(path: &Path) -> Result<PathBuf> {
+    let model = model::load(path)?;
+    write(&model, path)
+}
+
+// `path` is the source the model was loaded from, its files get hashed
+pub fn write(model: &ModelData, path: &Path) -> Result<PathBuf> {
+    if !is_cookable(model) {
+        return Err(CookError::Unsupported(String::from(
+            "skinned, morphed or animated models",
+        )));
+    }
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let sources = sources(path)?;
+    let hash = content_hash(base_dir, &sources)?;
+
+    let mut table = Writer::new(base_dir);
+    table.u32(sources.len() as u32);
+    for source in &sources {
+        table.path(source);
+    }
+    table.string(&model.name);
+
+    table.u32(model.materials.len() as u32);
+    for material in &model.materials {
+        table.material(material);
+    }
+
+    table.u32(model.cameras.len() as u32);
+    for (world, projection) in &model.cameras {
+        table.floats(&world.to_cols_array());
+        table.projection(projection);
+    }
+
+    let mut vertices: Vec<Vertex> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+    table.u32(model.meshes.len() as u32);
+    for mesh in &model.meshes {
+        let bounds = Bounds::from_points(&mesh.positions);
+        table.u8(match mesh.topology {
+            Topology::Points => 0,
+            Topology::Lines => 1,
+            Topology::LineStrip => 2,
+            Topology::Triangles => 3,
+        });
+        table.u32(mesh.material.map_or(u32::MAX, |i| i as u32));
+        table.floats(&mesh.transform.to_cols_array());
+        table.floats(&bounds.min.to_array());
+        table.floats(&bounds.max.to_array());
+        table.u32(vertices.len() as u32);
+        table.u32(mesh.positions.len() as u32);
+        table.u32(indices.len() as u32);
+        table.u32(mesh.indices.len() as u32);
+
+        vertices.extend((0..mesh.positions.len()).map(|i| mesh.vertex(i)));
+        indices.extend_from_slice(&mesh.indices);
+    }
+
+    let vertex_offset = (HEADER_SIZE + table.bytes.len()).next_multiple_of(16);
+    let vertex_bytes: &[u8] = bytemuck::cast_slice(&vertices);
+    let index_offset = (vertex_offset + vertex_bytes.len()).next_multiple_of(16);
+    let index_bytes: &[u8] = bytemuck::cast_slice(&indices);
+
+    let mut out = Vec::with_capacity(index_offset + index_bytes.len());
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&hash.to_le_bytes());
+    out.extend_from_slice(&(vertex_offset as u64).to_le_bytes());
+    out.extend_from_slice(&(index_offset as u64).to_le_bytes());
+    out.extend_from_slice(&table.bytes);
+    out.resize(vertex_offset, 0);
+    out.extend_from_slice(vertex_bytes);
+    out.resize(index_offset, 0);
+    out.extend_from_slice(index_bytes);
+
+    // written aside and renamed over, a running instance may have the old
+    // one mapped
+    let cooked = cooked_path(path);
+    let temp = cooked.with_extension("cooked.tmp");
+    fs::write(&temp, &out)?;
+    fs::rename(&temp, &cooked)?;
+    Ok(cooked)
+}
+
+// Maps the cooked file of the model at `path`. Stale when any source file
+// hashes differently than when it was cooked
+pub fn load(path: &Path) -> Result<CookedModel> {
+    let file = File::open(cooked_path(path))?;
+    // safety: cooked files are only ever replaced whole by a rename (see
+    // write), never modified in place, so the mapping can't change under us
+    let map = unsafe { Mmap::map(&file)? };
+
+    if map.len() < HEADER_SIZE || map[0..4] != MAGIC {
+        return Err(CookError::Invalid(String::from("bad magic")));
+    }
+    let header = |at: usize| u64::from_le_bytes(map[at..at + 8].try_into().unwrap());
+    let version = u32::from_le_bytes(map[4..8].try_into().unwrap());
+    if version != VERSION {
+        return Err(CookError::Stale);
+    }
+    let hash = header(8);
+    let vertex_offset = header(16) as usize;
+    let index_offset = header(24) as usize;
+    if vertex_offset < HEADER_SIZE
+        || vertex_offset > index_offset
+        || index_offset > map.len()
+        || !vertex_offset.is_multiple_of(16)
+        || !index_offset.is_multiple_of(16)
+    {
+        return Err(CookError::Invalid(String::from("blob offsets")));
+    }
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut table = Reader {
+        bytes: &map[HEADER_SIZE..vertex_offset],
+        base_dir,
+    };
+
+    let sources = (0..table.u32()?)
+        .map(|_| table.path())
+        .collect::<Result<Vec<_>>>()?;
+    // a missing source counts as changed
+    match content_hash(base_dir, &sources) {
+        Ok(current) if current == hash => {}
+        _ => return Err(CookError::Stale),
+    }
+
+    let name = table.string()?;
+    let materials = (0..table.u32()?)
+        .map(|_| table.material())
+        .collect::<Result<Vec<_>>>()?;
+    let cameras = (0..table.u32()?)
+        .map(|_| Ok((Mat4::from_cols_array(&table.floats()?), table.projection()?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let vertex_count = (index_offset - vertex_offset) / std::mem::size_of::<Vertex>();
+    let index_count = (map.len() - index_offset) / 4;
+    let mut meshes = Vec::new();
+    for _ in 0..table.u32()? {
+        let topology = match table.u8()? {
+            0 => Topology::Points,
+            1 => Topology::Lines,
+            2 => Topology::LineStrip,
+            3 => Topology::Triangles,
+            other => return Err(CookError::Invalid(format!("topology {}", other))),
+        };
+        let material = match table.u32()? {
+            u32::MAX => None,
+            i => Some(i as usize),
+        };
+        let transform = Mat4::from_cols_array(&table.floats()?);
+        let bounds = Bounds {
+            min: Vec3::from_array(table.floats()?),
+            max: Vec3::from_array(table.floats()?),
+        };
+        let vertices = table.range(vertex_count)?;
+        let indices = table.range(index_count)?;
+
+        meshes.push(CookedMesh {
+            topology,
+            material,
+            transform,
+            bounds,
+            vertices,
+            indices,
+        });
+    }
+
+    Ok(CookedModel {
+        data: ModelData {
+            name,
+            meshes: Vec::new(),
+            materials,
+            cameras,
+            nodes: Vec::new(),
+            skins: Vec::new(),
+            animations: Vec::new(),
+        },
+        meshes,
+        map,
+        vertex_offset,
+        index_offset,
+    })
+}
+
+// The model file and everything it pulls geometry or materials from.
+// Textures aren't included, they're loaded from their paths either way
+pub fn sources(path: &Path) -> Result<Vec<PathBuf>> {
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    let mut sources = vec![path.to_path_buf()];
+
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("gltf") | Some("glb") => {
+            let bytes = fs::read(path)?;
+            let gltf = gltf::Gltf::from_slice_without_validation(&bytes)
+                .map_err(|e| CookError::Load(e.into()))?;
+            for buffer in gltf.buffers() {
+                if let gltf::buffer::Source::Uri(uri) = buffer.source()
+                    && !uri.starts_with("data:")
+                {
+                    sources.push(base_dir.join(uri));
+                }
+            }
+        }
+        Some("obj") => {
+            for line in fs::read_to_string(path)?.lines() {
+                if let Some(libraries) = line.trim_start().strip_prefix("mtllib ") {
+                    sources.extend(libraries.split_whitespace().map(|lib| base_dir.join(lib)));
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(sources)
+}
+
+// FNV-1a over every source's name and contents. Unlike std's hasher it's
+// the same across builds, so a file cooked by one stays valid for the next
+fn content_hash(base_dir: &Path, sources: &[PathBuf]) -> Result<u64> {
+    let fnv = |hash: u64, bytes: &[u8]| {
+        bytes.iter().fold(hash, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+    };
+
+    let mut hash = 0xcbf29ce484222325;
+    for source in sources {
+        let relative = source.strip_prefix(base_dir).unwrap_or(source);
+        hash = fnv(hash, relative.to_string_lossy().as_bytes());
+        hash = fnv(hash, &fs::read(source)?);
+    }
+    Ok(hash)
+}
+
+// Paths are stored relative to the model's directory, so the asset folder
+// can move with its cooked file
+struct Writer<'a> {
+    bytes: Vec<u8>,
+    base_dir: &'a Path,
+}
+
+impl<'a> Writer<'a> {
+    fn new(base_dir: &'a Path) -> Self {
+        Self {
+            bytes: Vec::new(),
+            base_dir,
+        }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn floats(&mut self, values: &[f32]) {
+        for value in values {
+            self.bytes.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn path(&mut self, path: &Path) {
+        let relative = path.strip_prefix(self.base_dir).unwrap_or(path);
+        self.string(&relative.to_string_lossy());
+    }
+
+    fn material(&mut self, material: &MaterialData) {
+        self.string(&material.name);
+        self.floats(&material.base_color);
+        self.floats(&material.emissive);
+        self.floats(&[material.occlusion_strength]);
+        for texture in material.texture_slots() {
+            match texture {
+                Some(texture) => {
+                    self.u8(1);
+                    self.texture(texture);
+                }
+                None => self.u8(0),
+            }
+        }
+    }
+
+    fn texture(&mut self, texture: &TextureRef) {
+        self.path(&texture.path);
+        match &texture.fallback {
+            Some(fallback) => {
+                self.u8(1);
+                self.path(fallback);
+            }
+            None => self.u8(0),
+        }
+        self.u32(texture.tex_coord as u32);
+        self.floats(&texture.transform.to_cols_array());
+
+        let wrap = |wrap| match wrap {
+            Wrap::Repeat => 0,
+            Wrap::MirroredRepeat => 1,
+            Wrap::ClampToEdge => 2,
+        };
+        let filter = |filter| match filter {
+            Filter::Nearest => 0,
+            Filter::Linear => 1,
+        };
+        let sampler = texture.sampler;
+        self.u8(wrap(sampler.wrap_s));
+        self.u8(wrap(sampler.wrap_t));
+        self.u8(filter(sampler.mag_filter));
+        self.u8(filter(sampler.min_filter));
+        // 2 for no mipmapping
+        self.u8(sampler.mip_filter.map_or(2, filter));
+
+        self.u8(match texture.color_space {
+            ColorSpace::Srgb => 0,
+            ColorSpace::Linear => 1,
+        });
+    }
+
+    fn projection(&mut self, projection: &Projection) {
+        match *projection {
+            Projection::Perspective { yfov, znear, zfar } => {
+                self.u8(0);
+                // infinite when there's no far plane
+                self.floats(&[yfov, znear, zfar.unwrap_or(f32::INFINITY)]);
+            }
+            Projection::Orthographic {
+                xmag,
+                ymag,
+                znear,
+                zfar,
+            } => {
+                self.u8(1);
+                self.floats(&[xmag, ymag, znear, zfar]);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    base_dir: &'a Path,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8]> {
+        if count > self.bytes.len() {
+            return Err(CookError::Invalid(String::from("truncated table")));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn floats<const N: usize>(&mut self) -> Result<[f32; N]> {
+        let bytes = self.take(N * 4)?;
+        Ok(std::array::from_fn(|i| {
+            f32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+        }))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| CookError::Invalid(String::from("string isn't UTF-8")))
+    }
+
+    fn path(&mut self) -> Result<PathBuf> {
+        Ok(self.base_dir.join(self.string()?))
+    }
+
+    // first and count, checked against what the blob holds
+    fn range(&mut self, len: usize) -> Result<Range<usize>> {
+        let start = self.u32()? as usize;
+        let end = start + self.u32()? as usize;
+        if end > len {
+            return Err(CookError::Invalid(String::from(
+                "mesh past the end of its blob",
+            )));
+        }
+        Ok(start..end)
+    }
+
+    fn material(&mut self) -> Result<MaterialData> {
+        let name = self.string()?;
+        let base_color = self.floats()?;
+        let emissive = self.floats()?;
+        let [occlusion_strength] = self.floats()?;
+        let mut material = MaterialData {
+            name,
+            base_color,
+            emissive,
+            occlusion_strength,
+            ..Default::default()
+        };
+        for slot in material.texture_slots_mut() {
+            *slot = match self.u8()? {
+                0 => None,
+                _ => Some(self.texture()?),
+            };
+        }
+        Ok(material)
+    }
+
+    fn texture(&mut self) -> Result<TextureRef> {
+        let path = self.path()?;
+        let fallback = match self.u8()? {
+            0 => None,
+            _ => Some(self.path()?),
+        };
+        let tex_coord = self.u32()? as usize;
+        let transform = Mat3::from_cols_array(&self.floats()?);
+
+        let wrap = |value| match value {
+            0 => Ok(Wrap::Repeat),
+            1 => Ok(Wrap::MirroredRepeat),
+            2 => Ok(Wrap::ClampToEdge),
+            _ => Err(CookError::Invalid(format!("wrap mode {}", value))),
+        };
+        let filter = |value| match value {
+            0 => Ok(Filter::Nearest),
+            1 => Ok(Filter::Linear),
+            _ => Err(CookError::Invalid(format!("filter {}", value))),
+        };
+        let sampler = SamplerData {
+            wrap_s: wrap(self.u8()?)?,
+            wrap_t: wrap(self.u8()?)?,
+            mag_filter: filter(self.u8()?)?,
+            min_filter: filter(self.u8()?)?,
+            mip_filter: match self.u8()? {
+                2 => None,
+                value => Some(filter(value)?),
+            },
+        };
+
+        let color_space = match self.u8()? {
+            0 => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        };
+
+        Ok(TextureRef {
+            path,
+            fallback,
+            tex_coord,
+            transform,
+            sampler,
+            color_space,
+        })
+    }
+
+    fn projection(&mut self) -> Result<Projection> {
+        match self.u8()? {
+            0 => {
+                let [yfov, znear, zfar] = self.floats()?;
+                Ok(Projection::Perspective {
+                    yfov,
+                    znear,
+                    zfar: zfar.is_finite().then_some(zfar),
+                })
+            }
+            _ => {
+                let [xmag, ymag, znear, zfar] = self.floats()?;
+                Ok(Projection::Orthographic {
+                    xmag,
+                    ymag,
+                    znear,
+                    zfar,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::MeshData;
+
+    // A scratch directory with an OBJ and its material library as the
+    // sources, their contents don't matter to write and load
+    fn sources_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bs-cook-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("model.obj"), "mtllib model.mtl\n").unwrap();
+        fs::write(dir.join("model.mtl"), "newmtl red\n").unwrap();
+        dir
+    }
+
+    fn mesh(offset: f32, material: Option<usize>) -> MeshData {
+        let positions = vec![
+            [offset, 0.0, 0.0],
+            [offset + 1.0, 0.0, -2.0],
+            [offset, 3.0, 0.5],
+            [offset - 1.0, -1.0, 0.0],
+        ];
+        MeshData {
+            name: format!("mesh {}", offset),
+            normals: vec![[0.0, 0.0, 1.0]; 4],
+            uvs: (0..4).map(|i| [i as f32 * 0.25, 1.0]).collect(),
+            extra_uvs: vec![(0..4).map(|i| [0.5, i as f32]).collect()],
+            tangents: vec![[1.0, 0.0, 0.0, -1.0]; 4],
+            positions,
+            indices: vec![0, 1, 2, 0, 2, 3],
+            topology: Topology::Triangles,
+            material,
+            transform: Mat4::from_translation(Vec3::new(0.0, offset, 0.0)),
+            node: None,
+            skin: None,
+            joints: Vec::new(),
+            weights: Vec::new(),
+            targets: Vec::new(),
+            morph_weights: Vec::new(),
+        }
+    }
+
+    fn model(dir: &Path) -> ModelData {
+        let texture = |name: &str, color_space| TextureRef {
+            path: dir.join(name),
+            fallback: Some(dir.join("fallback.png")),
+            tex_coord: 1,
+            transform: Mat3::from_scale(glam::Vec2::new(2.0, 0.5)),
+            sampler: SamplerData {
+                wrap_s: Wrap::ClampToEdge,
+                wrap_t: Wrap::MirroredRepeat,
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Linear,
+                mip_filter: None,
+            },
+            color_space,
+        };
+        let textured = MaterialData {
+            name: String::from("textured"),
+            base_color: [1.0, 0.5, 0.25, 0.5],
+            base_color_texture: Some(texture("color.ktx2", ColorSpace::Srgb)),
+            emissive: [0.1, 0.2, 0.3],
+            occlusion_strength: 0.75,
+            normal_texture: Some(texture("normal.png", ColorSpace::Linear)),
+            metallic_roughness_texture: Some(texture("orm.png", ColorSpace::Linear)),
+            ..Default::default()
+        };
+        ModelData {
+            name: String::from("synthetic"),
+            meshes: vec![mesh(2.0, Some(0)), mesh(-5.0, None)],
+            materials: vec![textured, MaterialData::default()],
+            cameras: vec![
+                (
+                    Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0)),
+                    Projection::Perspective {
+                        yfov: 0.8,
+                        znear: 0.1,
+                        zfar: None,
+                    },
+                ),
+                (
+                    Mat4::IDENTITY,
+                    Projection::Orthographic {
+                        xmag: 2.0,
+                        ymag: 1.0,
+                        znear: 0.5,
+                        zfar: 50.0,
+                    },
+                ),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let dir = sources_dir("round-trip");
+        let path = dir.join("model.obj");
+        let model = model(&dir);
+        write(&model, &path).unwrap();
+        let cooked = load(&path).unwrap();
+
+        assert_eq!(cooked.data.name, model.name);
+        assert_eq!(cooked.data.materials.len(), model.materials.len());
+        for (a, b) in cooked.data.materials.iter().zip(&model.materials) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.base_color, b.base_color);
+            assert_eq!(a.emissive, b.emissive);
+            assert_eq!(a.occlusion_strength, b.occlusion_strength);
+            for (a, b) in a.texture_slots().into_iter().zip(b.texture_slots()) {
+                assert_eq!(a.is_some(), b.is_some());
+                let (Some(a), Some(b)) = (a, b) else { continue };
+                assert_eq!(a.path, b.path);
+                assert_eq!(a.fallback, b.fallback);
+                assert_eq!(a.tex_coord, b.tex_coord);
+                assert_eq!(a.transform, b.transform);
+                assert_eq!(a.sampler, b.sampler);
+                assert_eq!(a.color_space, b.color_space);
+            }
+        }
+        assert_eq!(cooked.data.cameras, model.cameras);
+
+        assert_eq!(cooked.meshes.len(), model.meshes.len());
+        for (cooked_mesh, mesh) in cooked.meshes.iter().zip(&model.meshes) {
+            assert_eq!(cooked_mesh.topology, mesh.topology);
+            assert_eq!(cooked_mesh.material, mesh.material);
+            assert_eq!(cooked_mesh.transform, mesh.transform);
+            let offset = mesh.positions[0][0];
+            assert_eq!(cooked_mesh.bounds.min, Vec3::new(offset - 1.0, -1.0, -2.0));
+            assert_eq!(cooked_mesh.bounds.max, Vec3::new(offset + 1.0, 3.0, 0.5));
+
+            let vertices: Vec<Vertex> = (0..4).map(|i| mesh.vertex(i)).collect();
+            assert_eq!(cooked.vertices(cooked_mesh), vertices);
+            assert_eq!(cooked.indices(cooked_mesh), mesh.indices);
+        }
+        // both meshes moved by their transforms
+        let bounds = cooked.bounds();
+        assert_eq!(bounds.min, Vec3::new(-6.0, -6.0, -2.0));
+        assert_eq!(bounds.max, Vec3::new(3.0, 5.0, 0.5));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn touched_sources_are_stale() {
+        let dir = sources_dir("stale");
+        let path = dir.join("model.obj");
+        write(&model(&dir), &path).unwrap();
+        assert!(load(&path).is_ok());
+
+        // the material library counts as much as the model itself
+        for source in ["model.obj", "model.mtl"] {
+            let source = dir.join(source);
+            let original = fs::read(&source).unwrap();
+            fs::write(&source, [original.as_slice(), b"# edited\n"].concat()).unwrap();
+            assert!(matches!(load(&path), Err(CookError::Stale)));
+
+            fs::write(&source, &original).unwrap();
+            assert!(load(&path).is_ok());
+        }
+
+        fs::remove_file(dir.join("model.mtl")).unwrap();
+        assert!(matches!(load(&path), Err(CookError::Stale)));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bad_offsets_are_invalid() {
+        let dir = sources_dir("offsets");
+        let path = dir.join("model.obj");
+        write(&model(&dir), &path).unwrap();
+        let cooked = fs::read(cooked_path(&path)).unwrap();
+
+        // vertices inside the header or after the indices, indices past the
+        // end of the file, vertices unaligned
+        let index_offset = u64::from_le_bytes(cooked[24..32].try_into().unwrap());
+        let size = cooked.len() as u64;
+        for (vertex_offset, index_offset) in [
+            (0, index_offset),
+            (16, index_offset),
+            (index_offset + 16, index_offset),
+            (index_offset, size.next_multiple_of(16) + 16),
+            (index_offset - 4, index_offset),
+        ] {
+            let mut bytes = cooked.clone();
+            bytes[16..24].copy_from_slice(&vertex_offset.to_le_bytes());
+            bytes[24..32].copy_from_slice(&index_offset.to_le_bytes());
+            fs::write(cooked_path(&path), bytes).unwrap();
+            assert!(
+                matches!(load(&path), Err(CookError::Invalid(_))),
+                "{} {}",
+                vertex_offset,
+                index_offset
+            );
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,2356 @@
+use std::collections::HashMap;
+
+// Decoder for KHR_draco_mesh_compression buffers. Covers what the Draco
+// encoder writes for glTF: bitstream 2.2, sequential and edgebreaker
+// (standard and valence) connectivity, the generic, integer, quantized and
+// octahedral normal attribute codecs, and the difference, parallelogram,
+// constrained multi-parallelogram, portable tex coord and geometric normal
+// predictors.
+// Ported from the reference decoder, the comments point at the names used
+// there where it helps to compare
+
+const INVALID: u32 = u32::MAX;
+
+pub type Result<T> = std::result::Result<T, String>;
+
+pub struct Mesh {
+    // three points per triangle
+    pub indices: Vec<u32>,
+    pub num_points: usize,
+    pub attributes: Vec<Attribute>,
+}
+
+// One entry per point, `components` floats each. Quantized and normalized
+// values are already converted back
+pub struct Attribute {
+    pub unique_id: u32,
+    pub components: usize,
+    pub values: Vec<f32>,
+}
+
+impl Mesh {
+    // the ids glTF refers to from the extension's attribute map
+    pub fn attribute(&self, unique_id: u32) -> Option<&Attribute> {
+        self.attributes.iter().find(|a| a.unique_id == unique_id)
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<Mesh> {
+    let mut buf = Buffer::new(data);
+
+    if buf.bytes(5)? != b"DRACO" {
+        return Err("not a Draco buffer".into());
+    }
+    let (major, minor) = (buf.u8()?, buf.u8()?);
+    if (major, minor) != (2, 2) {
+        return Err(format!("bitstream version {}.{} is not supported", major, minor));
+    }
+    let encoder_type = buf.u8()?;
+    let method = buf.u8()?;
+    let flags = u16::from_le_bytes([buf.u8()?, buf.u8()?]);
+    if encoder_type != 1 {
+        return Err("only triangle meshes are supported".into());
+    }
+    if flags & 0x8000 != 0 {
+        return Err("metadata is not supported".into());
+    }
+
+    let connectivity = match method {
+        0 => decode_sequential(&mut buf)?,
+        1 => decode_edgebreaker(&mut buf)?,
+        _ => return Err(format!("unknown encoding method {}", method)),
+    };
+
+    if connectivity.indices.iter().any(|&i| i as usize >= connectivity.num_points) {
+        return Err("index out of range".into());
+    }
+
+    let attributes = decode_attributes(&mut buf, &connectivity)?
+        .into_iter()
+        .map(|att| att.expand())
+        .collect::<Result<_>>()?;
+
+    Ok(Mesh {
+        indices: connectivity.indices,
+        num_points: connectivity.num_points,
+        attributes,
+    })
+}
+
+// Byte cursor over the encoded data
+
+#[derive(Clone)]
+struct Buffer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Buffer<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|&end| end <= self.data.len())
+            .ok_or("unexpected end of data")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn remaining(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    // LEB128
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint too long".into())
+    }
+
+    fn varint_u32(&mut self) -> Result<u32> {
+        u32::try_from(self.varint()?).map_err(|_| "varint out of range".into())
+    }
+}
+
+// Least significant bit first, reads past the end return zeros without
+// advancing
+struct BitReader<'a> {
+    data: &'a [u8],
+    bit: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, bit: 0 }
+    }
+
+    fn bits(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for i in 0..count {
+            let Some(&byte) = self.data.get(self.bit / 8) else {
+                break;
+            };
+            value |= (((byte >> (self.bit % 8)) & 1) as u32) << i;
+            self.bit += 1;
+        }
+        value
+    }
+
+    fn bytes_read(&self) -> usize {
+        self.bit.div_ceil(8)
+    }
+}
+
+// Entropy coding. rANS is decoded back to front, the state is seeded from
+// the last bytes of the block
+
+const ANS_IO_BASE: u32 = 256;
+
+fn ans_init(data: &[u8], l_base: u32, allow_four_bytes: bool) -> Result<(usize, u32)> {
+    let n = data.len();
+    let &last = data.last().ok_or("empty rANS block")?;
+    let (offset, state) = match last >> 6 {
+        0 => (n - 1, (last & 0x3f) as u32),
+        1 if n >= 2 => (n - 2, u16::from_le_bytes([data[n - 2], last]) as u32 & 0x3fff),
+        2 if n >= 3 => (
+            n - 3,
+            u32::from_le_bytes([data[n - 3], data[n - 2], last, 0]) & 0x3f_ffff,
+        ),
+        3 if n >= 4 && allow_four_bytes => (
+            n - 4,
+            u32::from_le_bytes(data[n - 4..].try_into().unwrap()) & 0x3fff_ffff,
+        ),
+        _ => return Err("corrupt rANS block".into()),
+    };
+    let state = state + l_base;
+    if state >= l_base * ANS_IO_BASE {
+        return Err("corrupt rANS block".into());
+    }
+    Ok((offset, state))
+}
+
+// RAnsBitDecoder, one bit at a time with a fixed probability
+struct BitDecoder<'a> {
+    prob_zero: u32,
+    data: &'a [u8],
+    offset: usize,
+    state: u32,
+}
+
+impl<'a> BitDecoder<'a> {
+    const L_BASE: u32 = 4096;
+
+    fn start(buf: &mut Buffer<'a>) -> Result<Self> {
+        let prob_zero = buf.u8()? as u32;
+        let size = buf.varint_u32()? as usize;
+        let data = buf.bytes(size)?;
+        let (offset, state) = ans_init(data, Self::L_BASE, false)?;
+        Ok(Self {
+            prob_zero,
+            data,
+            offset,
+            state,
+        })
+    }
+
+    fn next(&mut self) -> bool {
+        let p = 256 - self.prob_zero;
+        if self.state < Self::L_BASE && self.offset > 0 {
+            self.offset -= 1;
+            self.state = self.state * ANS_IO_BASE + self.data[self.offset] as u32;
+        }
+        let x = self.state;
+        let quot = x / 256;
+        let rem = x % 256;
+        let xn = quot * p;
+        if rem < p {
+            self.state = xn + rem;
+            true
+        } else {
+            self.state = x - xn - p;
+            false
+        }
+    }
+}
+
+// RAnsSymbolDecoder, symbols with a probability table stored up front
+struct SymbolDecoder<'a> {
+    precision: u32,
+    probs: Vec<u32>,
+    cumulative: Vec<u32>,
+    lookup: Vec<u32>,
+    data: &'a [u8],
+    offset: usize,
+    state: u32,
+}
+
+impl<'a> SymbolDecoder<'a> {
+    fn start(buf: &mut Buffer<'a>, symbol_bits: u32) -> Result<Self> {
+        let precision_bits = (3 * symbol_bits / 2).clamp(12, 20);
+        let precision = 1u32 << precision_bits;
+
+        let num_symbols = buf.varint_u32()? as usize;
+        if num_symbols == 0 || num_symbols > precision as usize {
+            return Err("bad rANS symbol count".into());
+        }
+
+        // two low bits are the number of extra bytes, 3 means a run of zeros
+        let mut probs = vec![0u32; num_symbols];
+        let mut i = 0;
+        while i < num_symbols {
+            let first = buf.u8()?;
+            if first & 3 == 3 {
+                let run = (first >> 2) as usize;
+                if i + run >= num_symbols {
+                    return Err("bad rANS probability table".into());
+                }
+                i += run + 1;
+                continue;
+            }
+            let mut prob = (first >> 2) as u32;
+            for b in 0..(first & 3) as u32 {
+                prob |= (buf.u8()? as u32) << (8 * (b + 1) - 2);
+            }
+            probs[i] = prob;
+            i += 1;
+        }
+
+        let mut cumulative = Vec::with_capacity(num_symbols);
+        let mut lookup = Vec::with_capacity(precision as usize);
+        let mut total = 0u32;
+        for (symbol, &prob) in probs.iter().enumerate() {
+            cumulative.push(total);
+            total = total.checked_add(prob).ok_or("bad rANS probability table")?;
+            if total > precision {
+                return Err("bad rANS probability table".into());
+            }
+            lookup.resize(total as usize, symbol as u32);
+        }
+        if total != precision {
+            return Err("bad rANS probability table".into());
+        }
+
+        let size = usize::try_from(buf.varint()?).map_err(|_| "bad rANS block size")?;
+        let data = buf.bytes(size)?;
+        let (offset, state) = ans_init(data, precision * 4, true)?;
+
+        Ok(Self {
+            precision,
+            probs,
+            cumulative,
+            lookup,
+            data,
+            offset,
+            state,
+        })
+    }
+
+    fn next(&mut self) -> u32 {
+        while self.state < self.precision * 4 && self.offset > 0 {
+            self.offset -= 1;
+            self.state = self.state * ANS_IO_BASE + self.data[self.offset] as u32;
+        }
+        let quot = self.state / self.precision;
+        let rem = self.state % self.precision;
+        let symbol = self.lookup[rem as usize];
+        self.state = quot * self.probs[symbol as usize] + rem - self.cumulative[symbol as usize];
+        symbol
+    }
+}
+
+// DecodeSymbols, an entropy coded run of unsigned integers
+fn decode_symbols(buf: &mut Buffer, num_values: usize, num_components: usize) -> Result<Vec<u32>> {
+    let mut values = Vec::with_capacity(num_values);
+    if num_values == 0 {
+        return Ok(values);
+    }
+
+    match buf.u8()? {
+        // tagged: a bit length per entry, then the raw bits of its components
+        0 => {
+            let mut tags = SymbolDecoder::start(buf, 5)?;
+            let mut bits = BitReader::new(buf.remaining());
+            while values.len() < num_values {
+                let length = tags.next();
+                if length > 32 {
+                    return Err("bad tagged symbol length".into());
+                }
+                for _ in 0..num_components {
+                    values.push(bits.bits(length));
+                }
+            }
+            values.truncate(num_values);
+            buf.bytes(bits.bytes_read())?;
+        }
+        // raw: every value is a symbol
+        1 => {
+            let max_bit_length = buf.u8()? as u32;
+            if !(1..=18).contains(&max_bit_length) {
+                return Err("bad raw symbol length".into());
+            }
+            let mut symbols = SymbolDecoder::start(buf, max_bit_length)?;
+            values.extend((0..num_values).map(|_| symbols.next()));
+        }
+        scheme => return Err(format!("unknown symbol coding {}", scheme)),
+    }
+
+    Ok(values)
+}
+
+fn to_signed(value: u32) -> i32 {
+    ((value >> 1) as i32) ^ -((value & 1) as i32)
+}
+
+// Corner table: corner c belongs to face c / 3, opposite corners share the
+// edge facing them
+
+#[derive(Clone)]
+struct CornerTable {
+    vertices: Vec<u32>,
+    opposites: Vec<u32>,
+    // per vertex, the corner to start from when walking around it
+    left_most: Vec<u32>,
+}
+
+fn next(c: u32) -> u32 {
+    match c {
+        INVALID => INVALID,
+        c if c % 3 == 2 => c - 2,
+        c => c + 1,
+    }
+}
+
+fn previous(c: u32) -> u32 {
+    match c {
+        INVALID => INVALID,
+        c if c % 3 == 0 => c + 2,
+        c => c - 1,
+    }
+}
+
+impl CornerTable {
+    fn new(num_faces: usize) -> Self {
+        Self {
+            vertices: vec![INVALID; num_faces * 3],
+            opposites: vec![INVALID; num_faces * 3],
+            left_most: Vec::new(),
+        }
+    }
+
+    fn num_corners(&self) -> usize {
+        self.vertices.len()
+    }
+
+    fn num_faces(&self) -> usize {
+        self.vertices.len() / 3
+    }
+
+    fn num_vertices(&self) -> usize {
+        self.left_most.len()
+    }
+
+    fn vertex(&self, c: u32) -> u32 {
+        self.vertices.get(c as usize).copied().unwrap_or(INVALID)
+    }
+
+    fn opposite(&self, c: u32) -> u32 {
+        self.opposites.get(c as usize).copied().unwrap_or(INVALID)
+    }
+
+    fn left_most(&self, v: u32) -> u32 {
+        self.left_most.get(v as usize).copied().unwrap_or(INVALID)
+    }
+
+    fn set_left_most(&mut self, v: u32, c: u32) {
+        if let Some(slot) = self.left_most.get_mut(v as usize) {
+            *slot = c;
+        }
+    }
+
+    fn set_opposite(&mut self, a: u32, b: u32) -> Result<()> {
+        if a as usize >= self.num_corners() || b as usize >= self.num_corners() {
+            return Err("corrupt connectivity".into());
+        }
+        self.opposites[a as usize] = b;
+        self.opposites[b as usize] = a;
+        Ok(())
+    }
+
+    fn add_vertex(&mut self) -> u32 {
+        self.left_most.push(INVALID);
+        (self.left_most.len() - 1) as u32
+    }
+
+    fn swing_left(&self, c: u32) -> u32 {
+        next(self.opposite(next(c)))
+    }
+
+    fn swing_right(&self, c: u32) -> u32 {
+        previous(self.opposite(previous(c)))
+    }
+
+    fn is_on_boundary(&self, v: u32) -> bool {
+        self.swing_left(self.left_most(v)) == INVALID
+    }
+
+    // VertexCornersIterator: left until back at the start or a boundary,
+    // then right from the start
+    fn corners_around(&self, start: u32) -> Vec<u32> {
+        let mut corners = vec![start];
+        let mut c = self.swing_left(start);
+        while c != INVALID && c != start && corners.len() <= self.num_corners() {
+            corners.push(c);
+            c = self.swing_left(c);
+        }
+        if c == INVALID {
+            c = self.swing_right(start);
+            while c != INVALID && corners.len() <= self.num_corners() {
+                corners.push(c);
+                c = self.swing_right(c);
+            }
+        }
+        corners
+    }
+}
+
+// Connectivity and the tables the attribute decoders traverse
+
+struct Connectivity {
+    // point per corner
+    indices: Vec<u32>,
+    num_points: usize,
+    // None for sequential encoding, attributes are then stored in point order
+    edgebreaker: Option<Edgebreaker>,
+}
+
+struct Edgebreaker {
+    table: CornerTable,
+    // one per attribute with its own seams, usually normals and uvs
+    attribute_tables: Vec<CornerTable>,
+}
+
+fn decode_sequential(buf: &mut Buffer) -> Result<Connectivity> {
+    let num_faces = buf.varint_u32()? as usize;
+    let num_points = buf.varint_u32()? as usize;
+    if num_faces > buf.remaining().len() / 3 {
+        return Err("too many faces".into());
+    }
+    let num_indices = num_faces * 3;
+
+    let indices: Vec<u32> = match buf.u8()? {
+        // compressed deltas between consecutive indices
+        0 => {
+            let mut last = 0i32;
+            decode_symbols(buf, num_indices, 1)?
+                .into_iter()
+                .map(|encoded| {
+                    let delta = (encoded >> 1) as i32;
+                    last = last.wrapping_add(if encoded & 1 != 0 { -delta } else { delta });
+                    last as u32
+                })
+                .collect()
+        }
+        _ if num_points < 1 << 8 => (0..num_indices)
+            .map(|_| buf.u8().map(|i| i as u32))
+            .collect::<Result<_>>()?,
+        _ if num_points < 1 << 16 => (0..num_indices)
+            .map(|_| buf.bytes(2).map(|b| u16::from_le_bytes([b[0], b[1]]) as u32))
+            .collect::<Result<_>>()?,
+        _ if num_points < 1 << 21 => (0..num_indices)
+            .map(|_| buf.varint_u32())
+            .collect::<Result<_>>()?,
+        _ => (0..num_indices)
+            .map(|_| buf.i32().map(|i| i as u32))
+            .collect::<Result<_>>()?,
+    };
+
+    Ok(Connectivity {
+        indices,
+        num_points,
+        edgebreaker: None,
+    })
+}
+
+const TOPOLOGY_C: u32 = 0;
+const TOPOLOGY_S: u32 = 1;
+const TOPOLOGY_L: u32 = 3;
+const TOPOLOGY_R: u32 = 5;
+const TOPOLOGY_E: u32 = 7;
+
+struct TopologySplit {
+    source_symbol: usize,
+    split_symbol: usize,
+    right_edge: bool,
+}
+
+// Symbols, start face configurations and attribute seams
+struct Traversal<'a> {
+    symbols: BitReader<'a>,
+    start_faces: BitDecoder<'a>,
+    seams: Vec<BitDecoder<'a>>,
+    valence: Option<Valence>,
+}
+
+// The valence traversal predicts each symbol from the valence of the vertex
+// the next face grows from, one symbol stream per valence
+struct Valence {
+    contexts: Vec<Vec<u32>>,
+    active: Option<usize>,
+    valences: Vec<i32>,
+    last_symbol: u32,
+}
+
+impl<'a> Traversal<'a> {
+    fn start(
+        buf: &mut Buffer<'a>,
+        kind: u8,
+        num_vertices: usize,
+        num_attribute_data: usize,
+        num_faces: usize,
+    ) -> Result<Self> {
+        let symbols = match kind {
+            0 => {
+                let size = usize::try_from(buf.varint()?).map_err(|_| "bad traversal size")?;
+                BitReader::new(buf.bytes(size)?)
+            }
+            2 => BitReader::new(&[]),
+            _ => return Err(format!("edgebreaker traversal {} is not supported", kind)),
+        };
+
+        let start_faces = BitDecoder::start(buf)?;
+        let seams = (0..num_attribute_data)
+            .map(|_| BitDecoder::start(buf))
+            .collect::<Result<_>>()?;
+
+        let valence = if kind == 2 {
+            let num_split_symbols = buf.varint_u32()? as usize;
+            if num_split_symbols >= num_vertices {
+                return Err("bad split symbol count".into());
+            }
+            // only the 2..7 valence mode exists
+            if buf.i8()? != 0 {
+                return Err("unknown valence mode".into());
+            }
+            let mut contexts = Vec::with_capacity(VALENCE_CONTEXTS);
+            for _ in 0..VALENCE_CONTEXTS {
+                let count = buf.varint_u32()? as usize;
+                if count > num_faces {
+                    return Err("too many valence symbols".into());
+                }
+                contexts.push(decode_symbols(buf, count, 1)?);
+            }
+            Some(Valence {
+                contexts,
+                active: None,
+                valences: vec![0; num_vertices],
+                last_symbol: TOPOLOGY_E,
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            symbols,
+            start_faces,
+            seams,
+            valence,
+        })
+    }
+
+    fn symbol(&mut self) -> u32 {
+        let Some(valence) = self.valence.as_mut() else {
+            // C is a single bit, the rest have two more
+            let symbol = self.symbols.bits(1);
+            if symbol == TOPOLOGY_C {
+                return symbol;
+            }
+            return symbol | (self.symbols.bits(2) << 1);
+        };
+
+        // the first symbol is always E. Contexts are consumed from the back
+        valence.last_symbol = match valence.active {
+            None => TOPOLOGY_E,
+            Some(context) => match valence.contexts[context].pop() {
+                Some(0) => TOPOLOGY_C,
+                Some(1) => TOPOLOGY_S,
+                Some(2) => TOPOLOGY_L,
+                Some(3) => TOPOLOGY_R,
+                Some(4) => TOPOLOGY_E,
+                _ => INVALID,
+            },
+        };
+        valence.last_symbol
+    }
+
+    fn new_active_corner(&mut self, table: &CornerTable, corner: u32) {
+        let Some(valence) = self.valence.as_mut() else {
+            return;
+        };
+
+        let vertices = [
+            table.vertex(corner),
+            table.vertex(next(corner)),
+            table.vertex(previous(corner)),
+        ];
+        let added = match valence.last_symbol {
+            TOPOLOGY_C | TOPOLOGY_S => [0, 1, 1],
+            TOPOLOGY_R => [1, 1, 2],
+            TOPOLOGY_L => [1, 2, 1],
+            TOPOLOGY_E => [2, 2, 2],
+            _ => [0, 0, 0],
+        };
+        for (v, n) in vertices.into_iter().zip(added) {
+            if let Some(count) = valence.valences.get_mut(v as usize) {
+                *count += n;
+            }
+        }
+
+        let active = valence.valences.get(vertices[1] as usize).copied().unwrap_or(0);
+        valence.active = Some((active.clamp(MIN_VALENCE, MAX_VALENCE) - MIN_VALENCE) as usize);
+    }
+
+    fn merge_vertices(&mut self, dest: u32, source: u32) {
+        if let Some(valence) = self.valence.as_mut() {
+            let merged = valence.valences.get(source as usize).copied().unwrap_or(0);
+            if let Some(count) = valence.valences.get_mut(dest as usize) {
+                *count += merged;
+            }
+        }
+    }
+}
+
+const MIN_VALENCE: i32 = 2;
+const MAX_VALENCE: i32 = 7;
+const VALENCE_CONTEXTS: usize = (MAX_VALENCE - MIN_VALENCE + 1) as usize;
+
+// Rebuilds the faces by replaying the encoder's symbols in reverse, then
+// splits vertices along attribute seams into points
+fn decode_edgebreaker(buf: &mut Buffer) -> Result<Connectivity> {
+    let traversal_kind = buf.u8()?;
+    let num_encoded_vertices = buf.varint_u32()? as usize;
+    let num_faces = buf.varint_u32()? as usize;
+    let num_attribute_data = buf.u8()? as usize;
+    let num_symbols = buf.varint_u32()? as usize;
+    let num_split_symbols = buf.varint_u32()? as usize;
+
+    // every face takes at least a bit somewhere
+    if num_faces > buf.remaining().len() * 8 || num_symbols > num_faces {
+        return Err("too many faces".into());
+    }
+    let max_vertices = num_encoded_vertices
+        .checked_add(num_split_symbols)
+        .filter(|&n| n <= 3 * num_faces)
+        .ok_or("too many vertices")?;
+
+    let mut splits = Vec::new();
+    let num_splits = buf.varint_u32()? as usize;
+    if num_splits > num_symbols {
+        return Err("too many topology splits".into());
+    }
+    let mut last_source = 0usize;
+    for _ in 0..num_splits {
+        let source_symbol = last_source + buf.varint_u32()? as usize;
+        let split_symbol = source_symbol
+            .checked_sub(buf.varint_u32()? as usize)
+            .ok_or("bad topology split")?;
+        last_source = source_symbol;
+        splits.push(TopologySplit {
+            source_symbol,
+            split_symbol,
+            right_edge: false,
+        });
+    }
+    if num_splits > 0 {
+        let mut bits = BitReader::new(buf.remaining());
+        for split in &mut splits {
+            split.right_edge = bits.bits(1) == 1;
+        }
+        buf.bytes(bits.bytes_read())?;
+    }
+
+    let mut traversal = Traversal::start(
+        buf,
+        traversal_kind,
+        max_vertices,
+        num_attribute_data,
+        num_faces,
+    )?;
+
+    let corrupt = || String::from("corrupt connectivity");
+
+    let mut table = CornerTable::new(num_faces);
+    let mut is_hole = vec![true; max_vertices];
+    let mut active: Vec<u32> = Vec::new();
+    let mut split_corners: HashMap<usize, u32> = HashMap::new();
+    let mut invalid_vertices = Vec::new();
+    let remove_invalid_vertices = num_attribute_data == 0;
+    let mut num_decoded_faces = 0;
+
+    for symbol_id in 0..num_symbols {
+        let corner = 3 * num_decoded_faces as u32;
+        num_decoded_faces += 1;
+        let mut check_split = false;
+
+        match traversal.symbol() {
+            // closes the gap between the active edge and the one to its left
+            TOPOLOGY_C => {
+                let a = *active.last().ok_or_else(corrupt)?;
+                let x = table.vertex(next(a));
+                let b = next(table.left_most(x));
+                if a == b || table.opposite(a) != INVALID || table.opposite(b) != INVALID {
+                    return Err(corrupt());
+                }
+                table.set_opposite(a, corner + 1)?;
+                table.set_opposite(b, corner + 2)?;
+                let a_prev = table.vertex(previous(a));
+                let b_next = table.vertex(next(b));
+                if x == a_prev || x == b_next {
+                    return Err(corrupt());
+                }
+                table.vertices[corner as usize] = x;
+                table.vertices[corner as usize + 1] = b_next;
+                table.vertices[corner as usize + 2] = a_prev;
+                table.set_left_most(a_prev, corner + 2);
+                if let Some(hole) = is_hole.get_mut(x as usize) {
+                    *hole = false;
+                }
+                *active.last_mut().unwrap() = corner;
+            }
+            // a new vertex off the active edge
+            symbol @ (TOPOLOGY_R | TOPOLOGY_L) => {
+                let a = *active.last().ok_or_else(corrupt)?;
+                if table.opposite(a) != INVALID {
+                    return Err(corrupt());
+                }
+                let (opposite, left, right) = if symbol == TOPOLOGY_R {
+                    (corner + 2, corner + 1, corner)
+                } else {
+                    (corner + 1, corner, corner + 2)
+                };
+                table.set_opposite(opposite, a)?;
+                let new_vertex = table.add_vertex();
+                if table.num_vertices() > max_vertices {
+                    return Err(corrupt());
+                }
+                table.vertices[opposite as usize] = new_vertex;
+                table.set_left_most(new_vertex, opposite);
+
+                let vertex_r = table.vertex(previous(a));
+                table.vertices[right as usize] = vertex_r;
+                table.set_left_most(vertex_r, right);
+                table.vertices[left as usize] = table.vertex(next(a));
+
+                *active.last_mut().unwrap() = corner;
+                check_split = true;
+            }
+            // joins the two topmost active edges, merging their vertices
+            TOPOLOGY_S => {
+                let b = active.pop().ok_or_else(corrupt)?;
+                if let Some(&split) = split_corners.get(&symbol_id) {
+                    active.push(split);
+                }
+                let a = *active.last().ok_or_else(corrupt)?;
+                if a == b || table.opposite(a) != INVALID || table.opposite(b) != INVALID {
+                    return Err(corrupt());
+                }
+                table.set_opposite(a, corner + 2)?;
+                table.set_opposite(b, corner + 1)?;
+
+                let vertex_p = table.vertex(previous(a));
+                table.vertices[corner as usize] = vertex_p;
+                table.vertices[corner as usize + 1] = table.vertex(next(a));
+                let b_prev = table.vertex(previous(b));
+                table.vertices[corner as usize + 2] = b_prev;
+                table.set_left_most(b_prev, corner + 2);
+
+                let mut corner_n = next(b);
+                let vertex_n = table.vertex(corner_n);
+                traversal.merge_vertices(vertex_p, vertex_n);
+                table.set_left_most(vertex_p, table.left_most(vertex_n));
+
+                let first = corner_n;
+                while corner_n != INVALID {
+                    table.vertices[corner_n as usize] = vertex_p;
+                    corner_n = table.swing_left(corner_n);
+                    if corner_n == first {
+                        return Err(corrupt());
+                    }
+                }
+                table.set_left_most(vertex_n, INVALID);
+                if remove_invalid_vertices {
+                    invalid_vertices.push(vertex_n);
+                }
+                *active.last_mut().unwrap() = corner;
+            }
+            // a new, disconnected triangle
+            TOPOLOGY_E => {
+                for i in 0..3 {
+                    let vertex = table.add_vertex();
+                    table.vertices[(corner + i) as usize] = vertex;
+                    table.set_left_most(vertex, corner + i);
+                }
+                if table.num_vertices() > max_vertices {
+                    return Err(corrupt());
+                }
+                active.push(corner);
+                check_split = true;
+            }
+            _ => return Err(corrupt()),
+        }
+
+        let top = *active.last().unwrap();
+        traversal.new_active_corner(&table, top);
+
+        // faces that a later S symbol attaches to through a split event
+        // leave one of their free edges on the stack for it
+        if check_split {
+            let encoder_symbol = num_symbols - symbol_id - 1;
+            while let Some(split) = splits.last() {
+                if split.source_symbol > encoder_symbol {
+                    return Err(corrupt());
+                }
+                if split.source_symbol != encoder_symbol {
+                    break;
+                }
+                let split = splits.pop().unwrap();
+                if split.split_symbol >= num_symbols {
+                    return Err(corrupt());
+                }
+                let corner = if split.right_edge { next(top) } else { previous(top) };
+                split_corners.insert(num_symbols - split.split_symbol - 1, corner);
+            }
+        }
+    }
+
+    // what's left on the stack are the start faces of each component
+    while let Some(corner) = active.pop() {
+        if !traversal.start_faces.next() {
+            continue;
+        }
+        // interior start face, it still has to be added
+        if num_decoded_faces >= num_faces {
+            return Err(corrupt());
+        }
+        let vertex_n = table.vertex(next(corner));
+        let corner_b = next(table.left_most(vertex_n));
+        let vertex_x = table.vertex(next(corner_b));
+        let corner_c = next(table.left_most(vertex_x));
+        let vertex_p = table.vertex(next(corner_c));
+
+        let new_corner = 3 * num_decoded_faces as u32;
+        num_decoded_faces += 1;
+        table.set_opposite(new_corner, corner)?;
+        table.set_opposite(new_corner + 1, corner_b)?;
+        table.set_opposite(new_corner + 2, corner_c)?;
+        table.vertices[new_corner as usize] = vertex_x;
+        table.vertices[new_corner as usize + 1] = vertex_p;
+        table.vertices[new_corner as usize + 2] = vertex_n;
+        for v in [vertex_x, vertex_p, vertex_n] {
+            if let Some(hole) = is_hole.get_mut(v as usize) {
+                *hole = false;
+            }
+        }
+    }
+    if num_decoded_faces != num_faces {
+        return Err(corrupt());
+    }
+    if table.vertices.contains(&INVALID) {
+        return Err(corrupt());
+    }
+
+    // merged away vertices are filled from the end so the ids stay dense
+    let mut num_vertices = table.num_vertices();
+    for invalid in invalid_vertices {
+        let mut source = num_vertices.checked_sub(1).ok_or_else(corrupt)? as u32;
+        while table.left_most(source) == INVALID {
+            num_vertices -= 1;
+            source = num_vertices.checked_sub(1).ok_or_else(corrupt)? as u32;
+        }
+        if source < invalid {
+            continue;
+        }
+        for c in table.corners_around(table.left_most(source)) {
+            table.vertices[c as usize] = invalid;
+        }
+        table.set_left_most(invalid, table.left_most(source));
+        table.set_left_most(source, INVALID);
+        is_hole[invalid as usize] = is_hole[source as usize];
+        is_hole[source as usize] = false;
+        num_vertices -= 1;
+    }
+
+    // every boundary edge is a seam, interior edges carry one bit per
+    // attribute, visited once from the lower numbered face
+    let mut seams = vec![Vec::new(); num_attribute_data];
+    if num_attribute_data > 0 {
+        for face in 0..num_faces as u32 {
+            for c in [3 * face, 3 * face + 1, 3 * face + 2] {
+                let opposite = table.opposite(c);
+                if opposite == INVALID {
+                    for seam in &mut seams {
+                        seam.push(c);
+                    }
+                } else if opposite / 3 >= face {
+                    for (seam, decoder) in seams.iter_mut().zip(&mut traversal.seams) {
+                        if decoder.next() {
+                            seam.push(c);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut attribute_tables = Vec::with_capacity(num_attribute_data);
+    let mut on_seam = Vec::with_capacity(num_attribute_data);
+    for seam in &seams {
+        let (attribute_table, vertex_on_seam) = attribute_table(&table, seam)?;
+        attribute_tables.push(attribute_table);
+        on_seam.push(vertex_on_seam);
+    }
+
+    let (indices, num_points) = if attribute_tables.is_empty() {
+        (table.vertices.clone(), num_vertices)
+    } else {
+        assign_points(&table, &attribute_tables, &on_seam, &is_hole)?
+    };
+
+    Ok(Connectivity {
+        indices,
+        num_points,
+        edgebreaker: Some(Edgebreaker {
+            table,
+            attribute_tables,
+        }),
+    })
+}
+
+// MeshAttributeCornerTable: the base table cut open along the seams, with
+// vertices split wherever a seam passes through them
+fn attribute_table(base: &CornerTable, seams: &[u32]) -> Result<(CornerTable, Vec<bool>)> {
+    let mut edge_on_seam = vec![false; base.num_corners()];
+    let mut vertex_on_seam = vec![false; base.num_vertices()];
+    let mut mark = |c: u32, edge_on_seam: &mut Vec<bool>| {
+        edge_on_seam[c as usize] = true;
+        for v in [base.vertex(next(c)), base.vertex(previous(c))] {
+            if let Some(on_seam) = vertex_on_seam.get_mut(v as usize) {
+                *on_seam = true;
+            }
+        }
+    };
+    for &c in seams {
+        mark(c, &mut edge_on_seam);
+        let opposite = base.opposite(c);
+        if opposite != INVALID {
+            mark(opposite, &mut edge_on_seam);
+        }
+    }
+
+    let mut table = CornerTable {
+        vertices: vec![INVALID; base.num_corners()],
+        opposites: base
+            .opposites
+            .iter()
+            .zip(&edge_on_seam)
+            .map(|(&opposite, &seam)| if seam { INVALID } else { opposite })
+            .collect(),
+        left_most: Vec::new(),
+    };
+
+    for v in 0..base.num_vertices() as u32 {
+        let c = base.left_most(v);
+        if c == INVALID {
+            continue;
+        }
+
+        // start on the seam so each side gets one contiguous run of corners
+        let mut first = c;
+        if vertex_on_seam[v as usize] {
+            let mut act = table.swing_left(first);
+            while act != INVALID {
+                first = act;
+                act = table.swing_left(act);
+                if act == c {
+                    return Err("corrupt attribute seams".into());
+                }
+            }
+        }
+
+        let mut vertex = table.add_vertex();
+        table.vertices[first as usize] = vertex;
+        table.set_left_most(vertex, first);
+        let mut act = base.swing_right(first);
+        let mut steps = 0;
+        while act != INVALID && act != first {
+            if edge_on_seam[next(act) as usize] {
+                vertex = table.add_vertex();
+                table.set_left_most(vertex, act);
+            }
+            table.vertices[act as usize] = vertex;
+            act = base.swing_right(act);
+            steps += 1;
+            if steps > base.num_corners() {
+                return Err("corrupt attribute seams".into());
+            }
+        }
+    }
+
+    Ok((table, vertex_on_seam))
+}
+
+// AssignPointsToCorners: walks around every vertex and starts a new point
+// whenever any attribute changes value
+fn assign_points(
+    table: &CornerTable,
+    attribute_tables: &[CornerTable],
+    on_seam: &[Vec<bool>],
+    is_hole: &[bool],
+) -> Result<(Vec<u32>, usize)> {
+    let mut indices = vec![INVALID; table.num_corners()];
+    let mut num_points = 0u32;
+
+    for v in 0..table.num_vertices() as u32 {
+        let c = table.left_most(v);
+        if c == INVALID {
+            continue;
+        }
+
+        // boundary vertices already start on the boundary, interior ones
+        // start on the first seam found
+        let mut first = c;
+        if !is_hole.get(v as usize).copied().unwrap_or(false) {
+            'attributes: for (attribute, on_seam) in attribute_tables.iter().zip(on_seam) {
+                if !on_seam[v as usize] {
+                    continue;
+                }
+                let vertex = attribute.vertex(c);
+                let mut act = table.swing_right(c);
+                while act != c {
+                    if act == INVALID {
+                        return Err("corrupt attribute seams".into());
+                    }
+                    if attribute.vertex(act) != vertex {
+                        first = act;
+                        break 'attributes;
+                    }
+                    act = table.swing_right(act);
+                }
+            }
+        }
+
+        indices[first as usize] = num_points;
+        num_points += 1;
+        let mut previous = first;
+        let mut c = table.swing_right(first);
+        while c != INVALID && c != first {
+            let seam = attribute_tables
+                .iter()
+                .any(|attribute| attribute.vertex(c) != attribute.vertex(previous));
+            if seam {
+                indices[c as usize] = num_points;
+                num_points += 1;
+            } else {
+                indices[c as usize] = indices[previous as usize];
+            }
+            previous = c;
+            c = table.swing_right(c);
+        }
+    }
+
+    if indices.contains(&INVALID) {
+        return Err("corrupt connectivity".into());
+    }
+    Ok((indices, num_points as usize))
+}
+
+// Attributes
+
+const POSITION: u8 = 0;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Codec {
+    Generic,
+    Integer,
+    Quantization,
+    Normals,
+}
+
+struct DecodedAttribute {
+    unique_id: u32,
+    kind: u8,
+    data_type: u8,
+    components: usize,
+    normalized: bool,
+    codec: Codec,
+    // what the predictors work on: quantized ints, octahedral coords for
+    // normals
+    portable: Vec<i32>,
+    portable_components: usize,
+    point_to_value: Vec<u32>,
+    // final values, one entry per decoded value
+    values: Vec<f32>,
+}
+
+impl DecodedAttribute {
+    fn portable_value(&self, point: u32) -> [i64; 3] {
+        let value = self.point_to_value[point as usize] as usize * self.portable_components;
+        let mut out = [0; 3];
+        for (o, &v) in out.iter_mut().zip(&self.portable[value..value + 3]) {
+            *o = v as i64;
+        }
+        out
+    }
+
+    // to one entry per point
+    fn expand(self) -> Result<Attribute> {
+        let mut values = Vec::with_capacity(self.point_to_value.len() * self.components);
+        for &value in &self.point_to_value {
+            let start = value as usize * self.components;
+            let entry = self
+                .values
+                .get(start..start + self.components)
+                .ok_or("attribute value out of range")?;
+            values.extend_from_slice(entry);
+        }
+        Ok(Attribute {
+            unique_id: self.unique_id,
+            components: self.components,
+            values,
+        })
+    }
+}
+
+// The order values are stored in, and what predictors need to find already
+// decoded neighbours
+struct Sequence<'c> {
+    point_ids: Vec<u32>,
+    mesh: Option<Traversed<'c>>,
+}
+
+struct Traversed<'c> {
+    table: &'c CornerTable,
+    vertex_to_value: Vec<u32>,
+    value_to_corner: Vec<u32>,
+}
+
+impl Traversed<'_> {
+    fn value_at(&self, corner: u32) -> u32 {
+        self.vertex_to_value
+            .get(self.table.vertex(corner) as usize)
+            .copied()
+            .unwrap_or(INVALID)
+    }
+}
+
+fn decode_attributes(buf: &mut Buffer, connectivity: &Connectivity) -> Result<Vec<DecodedAttribute>> {
+    let num_decoders = buf.u8()? as usize;
+
+    // which table each decoder traverses
+    let mut tables = Vec::with_capacity(num_decoders);
+    if let Some(edgebreaker) = &connectivity.edgebreaker {
+        for _ in 0..num_decoders {
+            let data_id = buf.i8()?;
+            let per_corner = buf.u8()? == 1;
+            // depth first is the only traversal the encoder uses for glTF
+            // sized meshes, prediction degree needs speed 0
+            if buf.u8()? != 0 {
+                return Err("only depth first attribute traversal is supported".into());
+            }
+            let table = match (data_id, per_corner) {
+                (id, true) if id >= 0 => edgebreaker
+                    .attribute_tables
+                    .get(id as usize)
+                    .ok_or("bad attribute data id")?,
+                (_, false) => &edgebreaker.table,
+                _ => return Err("bad attribute decoder".into()),
+            };
+            tables.push(Some(table));
+        }
+    } else {
+        tables.resize(num_decoders, None);
+    }
+
+    struct Descriptor {
+        unique_id: u32,
+        kind: u8,
+        data_type: u8,
+        components: usize,
+        normalized: bool,
+        codec: Codec,
+    }
+
+    let mut descriptors = Vec::with_capacity(num_decoders);
+    for _ in 0..num_decoders {
+        let count = buf.varint_u32()? as usize;
+        if count == 0 || count > buf.remaining().len() {
+            return Err("bad attribute count".into());
+        }
+        let mut attributes = Vec::with_capacity(count);
+        for _ in 0..count {
+            let kind = buf.u8()?;
+            let data_type = buf.u8()?;
+            let components = buf.u8()? as usize;
+            let normalized = buf.u8()? != 0;
+            let unique_id = buf.varint_u32()?;
+            if kind > 4 || data_type_size(data_type).is_none() || components == 0 {
+                return Err("bad attribute descriptor".into());
+            }
+            attributes.push(Descriptor {
+                unique_id,
+                kind,
+                data_type,
+                components,
+                normalized,
+                codec: Codec::Generic,
+            });
+        }
+        for attribute in &mut attributes {
+            attribute.codec = match buf.u8()? {
+                0 => Codec::Generic,
+                1 => Codec::Integer,
+                2 => Codec::Quantization,
+                3 => Codec::Normals,
+                codec => return Err(format!("unknown attribute codec {}", codec)),
+            };
+            if attribute.codec == Codec::Normals
+                && (attribute.components != 3 || attribute.data_type != DT_FLOAT32)
+            {
+                return Err("normal codec needs three floats".into());
+            }
+        }
+        descriptors.push(attributes);
+    }
+
+    let mut decoded: Vec<DecodedAttribute> = Vec::new();
+    for (attributes, table) in descriptors.into_iter().zip(tables) {
+        let sequence = match table {
+            Some(table) => depth_first(table, &connectivity.indices)?,
+            None => Sequence {
+                point_ids: (0..connectivity.num_points as u32).collect(),
+                mesh: None,
+            },
+        };
+
+        let point_to_value = match &sequence.mesh {
+            Some(mesh) => {
+                let mut point_to_value = vec![INVALID; connectivity.num_points];
+                for (c, &point) in connectivity.indices.iter().enumerate() {
+                    let slot = point_to_value
+                        .get_mut(point as usize)
+                        .ok_or("point out of range")?;
+                    *slot = mesh.value_at(c as u32);
+                }
+                if point_to_value.contains(&INVALID) {
+                    return Err("point without attribute value".into());
+                }
+                point_to_value
+            }
+            None => sequence.point_ids.clone(),
+        };
+
+        // values and prediction data for every attribute of this decoder,
+        // then whatever the transforms back to the original format need
+        let first = decoded.len();
+        for descriptor in attributes {
+            let mut attribute = DecodedAttribute {
+                unique_id: descriptor.unique_id,
+                kind: descriptor.kind,
+                data_type: descriptor.data_type,
+                components: descriptor.components,
+                normalized: descriptor.normalized,
+                codec: descriptor.codec,
+                portable: Vec::new(),
+                portable_components: 0,
+                point_to_value: point_to_value.clone(),
+                values: Vec::new(),
+            };
+            decode_portable(buf, &mut attribute, &sequence, &decoded)?;
+            decoded.push(attribute);
+        }
+        for attribute in &mut decoded[first..] {
+            decode_original(buf, attribute)?;
+        }
+    }
+
+    Ok(decoded)
+}
+
+// DepthFirstTraverser: values are numbered in the order vertices are first
+// reached, so both sides agree without storing the order
+fn depth_first<'c>(table: &'c CornerTable, indices: &[u32]) -> Result<Sequence<'c>> {
+    struct State<'i> {
+        indices: &'i [u32],
+        visited_vertices: Vec<bool>,
+        point_ids: Vec<u32>,
+        vertex_to_value: Vec<u32>,
+        value_to_corner: Vec<u32>,
+    }
+
+    impl State<'_> {
+        fn visit(&mut self, vertex: u32, corner: u32) -> Result<()> {
+            let visited = self
+                .visited_vertices
+                .get_mut(vertex as usize)
+                .ok_or("vertex out of range")?;
+            if !*visited {
+                *visited = true;
+                self.vertex_to_value[vertex as usize] = self.point_ids.len() as u32;
+                self.point_ids.push(self.indices[corner as usize]);
+                self.value_to_corner.push(corner);
+            }
+            Ok(())
+        }
+    }
+
+    let mut state = State {
+        indices,
+        visited_vertices: vec![false; table.num_vertices()],
+        point_ids: Vec::with_capacity(table.num_vertices()),
+        vertex_to_value: vec![INVALID; table.num_vertices()],
+        value_to_corner: Vec::with_capacity(table.num_vertices()),
+    };
+    let mut visited_faces = vec![false; table.num_faces()];
+    let face_visited = |visited_faces: &[bool], c: u32| c == INVALID || visited_faces[c as usize / 3];
+
+    let mut stack = Vec::new();
+    for face in 0..table.num_faces() as u32 {
+        let start = 3 * face;
+        if visited_faces[face as usize] {
+            continue;
+        }
+        state.visit(table.vertex(next(start)), next(start))?;
+        state.visit(table.vertex(previous(start)), previous(start))?;
+
+        stack.push(start);
+        while let Some(&top) = stack.last() {
+            if face_visited(&visited_faces, top) {
+                stack.pop();
+                continue;
+            }
+            let mut corner = top;
+            loop {
+                visited_faces[corner as usize / 3] = true;
+                let vertex = table.vertex(corner);
+                let visited = *state
+                    .visited_vertices
+                    .get(vertex as usize)
+                    .ok_or("vertex out of range")?;
+                if !visited {
+                    let on_boundary = table.is_on_boundary(vertex);
+                    state.visit(vertex, corner)?;
+                    if !on_boundary {
+                        corner = table.opposite(next(corner));
+                        continue;
+                    }
+                }
+
+                let right = table.opposite(next(corner));
+                let left = table.opposite(previous(corner));
+                match (face_visited(&visited_faces, right), face_visited(&visited_faces, left)) {
+                    (true, true) => {
+                        stack.pop();
+                        break;
+                    }
+                    (true, false) => corner = left,
+                    (false, true) => corner = right,
+                    (false, false) => {
+                        *stack.last_mut().unwrap() = left;
+                        stack.push(right);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(Sequence {
+        point_ids: state.point_ids,
+        mesh: Some(Traversed {
+            table,
+            vertex_to_value: state.vertex_to_value,
+            value_to_corner: state.value_to_corner,
+        }),
+    })
+}
+
+const DT_FLOAT32: u8 = 9;
+
+fn data_type_size(data_type: u8) -> Option<usize> {
+    match data_type {
+        1 | 2 | 11 => Some(1),
+        3 | 4 => Some(2),
+        5 | 6 | 9 => Some(4),
+        7 | 8 | 10 => Some(8),
+        _ => None,
+    }
+}
+
+// little endian value of the given type, widened to f64
+fn read_value(bytes: &[u8], data_type: u8) -> f64 {
+    match data_type {
+        1 => bytes[0] as i8 as f64,
+        2 | 11 => bytes[0] as f64,
+        3 => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        4 => u16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        5 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        6 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        7 => i64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        8 => u64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        9 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        _ => f64::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+// scale of a normalized integer type
+fn normalized_scale(data_type: u8) -> f32 {
+    match data_type {
+        1 => i8::MAX as f32,
+        2 => u8::MAX as f32,
+        3 => i16::MAX as f32,
+        4 => u16::MAX as f32,
+        5 => i32::MAX as f32,
+        6 => u32::MAX as f32,
+        _ => 1.0,
+    }
+}
+
+fn decode_portable<'a>(
+    buf: &mut Buffer<'a>,
+    attribute: &mut DecodedAttribute,
+    sequence: &Sequence,
+    decoded: &[DecodedAttribute],
+) -> Result<()> {
+    let num_entries = sequence.point_ids.len();
+
+    if attribute.codec == Codec::Generic {
+        let size = data_type_size(attribute.data_type).unwrap();
+        let count = num_entries * attribute.components;
+        let bytes = buf.bytes(count.checked_mul(size).ok_or("attribute too large")?)?;
+        let scale = if attribute.normalized { normalized_scale(attribute.data_type) } else { 1.0 };
+        attribute.values = bytes
+            .chunks_exact(size)
+            .map(|b| read_value(b, attribute.data_type) as f32 / scale)
+            .collect();
+        // lets a raw position still act as a parent for prediction
+        attribute.portable = attribute.values.iter().map(|&v| v as i32).collect();
+        attribute.portable_components = attribute.components;
+        return Ok(());
+    }
+
+    let components = if attribute.codec == Codec::Normals { 2 } else { attribute.components };
+    attribute.portable_components = components;
+
+    let mut scheme = match buf.i8()? {
+        -2 => None,
+        method => {
+            let transform = buf.i8()?;
+            let position = decoded.iter().find(|a| a.kind == POSITION);
+            Some(Scheme::new(method, transform, attribute.codec, sequence, position)?)
+        }
+    };
+
+    let num_values = num_entries * components;
+    let mut values: Vec<i32> = if buf.u8()? > 0 {
+        decode_symbols(buf, num_values, components)?
+            .into_iter()
+            .map(|v| v as i32)
+            .collect()
+    } else {
+        let size = buf.u8()? as usize;
+        if !(1..=4).contains(&size) {
+            return Err("bad integer size".into());
+        }
+        let bytes = buf.bytes(num_values.checked_mul(size).ok_or("attribute too large")?)?;
+        bytes
+            .chunks_exact(size)
+            .map(|b| {
+                let mut word = [0u8; 4];
+                word[..size].copy_from_slice(b);
+                i32::from_le_bytes(word)
+            })
+            .collect()
+    };
+
+    if !scheme.as_ref().is_some_and(|s| s.transform.corrections_positive()) {
+        for value in &mut values {
+            *value = to_signed(*value as u32);
+        }
+    }
+
+    if let Some(scheme) = scheme.as_mut() {
+        scheme.decode_data(buf, sequence)?;
+        scheme.compute_original(&mut values, components, sequence)?;
+    }
+
+    attribute.portable = values;
+    Ok(())
+}
+
+// the transform data stored after the values, then back to floats
+fn decode_original(buf: &mut Buffer, attribute: &mut DecodedAttribute) -> Result<()> {
+    match attribute.codec {
+        Codec::Generic => {}
+        Codec::Integer => {
+            let scale = if attribute.normalized { normalized_scale(attribute.data_type) } else { 1.0 };
+            attribute.values = attribute.portable.iter().map(|&v| v as f32 / scale).collect();
+        }
+        Codec::Quantization => {
+            let min: Vec<f32> = (0..attribute.components)
+                .map(|_| buf.f32())
+                .collect::<Result<_>>()?;
+            let range = buf.f32()?;
+            let bits = buf.u8()?;
+            if !(1..=30).contains(&bits) {
+                return Err("bad quantization bits".into());
+            }
+            let delta = range / ((1u32 << bits) - 1) as f32;
+            attribute.values = attribute
+                .portable
+                .iter()
+                .enumerate()
+                .map(|(i, &q)| q as f32 * delta + min[i % attribute.components])
+                .collect();
+        }
+        Codec::Normals => {
+            let octahedron = Octahedron::new(buf.u8()? as u32)?;
+            attribute.values = attribute
+                .portable
+                .chunks_exact(2)
+                .flat_map(|st| octahedron.unit_vector(st[0], st[1]))
+                .collect();
+        }
+    }
+    Ok(())
+}
+
+// OctahedronToolBox, normals folded onto a square of quantized coords
+
+#[derive(Copy, Clone)]
+struct Octahedron {
+    max_quantized: i32,
+    max_value: i32,
+    center: i32,
+}
+
+impl Octahedron {
+    fn new(bits: u32) -> Result<Self> {
+        if !(2..=30).contains(&bits) {
+            return Err("bad octahedron quantization bits".into());
+        }
+        let max_quantized = (1 << bits) - 1;
+        let max_value = max_quantized - 1;
+        Ok(Self {
+            max_quantized,
+            max_value,
+            center: max_value / 2,
+        })
+    }
+
+    fn unit_vector(&self, s: i32, t: i32) -> [f32; 3] {
+        let scale = 2.0 / self.max_value as f32;
+        let mut y = s as f32 * scale - 1.0;
+        let mut z = t as f32 * scale - 1.0;
+        let x = 1.0 - y.abs() - z.abs();
+        let offset = (-x).max(0.0);
+        y += if y < 0.0 { offset } else { -offset };
+        z += if z < 0.0 { offset } else { -offset };
+        let norm_squared = x * x + y * y + z * z;
+        if norm_squared < 1e-6 {
+            return [0.0; 3];
+        }
+        let d = 1.0 / norm_squared.sqrt();
+        [x * d, y * d, z * d]
+    }
+
+    // scales onto the octahedron, |x| + |y| + |z| == center
+    fn canonicalize_vector(&self, v: &mut [i32; 3]) {
+        let abs_sum = v.iter().map(|&c| (c as i64).abs()).sum::<i64>();
+        if abs_sum == 0 {
+            v[0] = self.center;
+            return;
+        }
+        let center = self.center as i64;
+        v[0] = (v[0] as i64 * center / abs_sum) as i32;
+        v[1] = (v[1] as i64 * center / abs_sum) as i32;
+        let rest = self.center - v[0].abs() - v[1].abs();
+        v[2] = if v[2] >= 0 { rest } else { -rest };
+    }
+
+    fn vector_to_coords(&self, v: [i32; 3]) -> [i32; 2] {
+        let (s, t) = if v[0] >= 0 {
+            (v[1] + self.center, v[2] + self.center)
+        } else {
+            let s = if v[1] < 0 { v[2].abs() } else { self.max_value - v[2].abs() };
+            let t = if v[2] < 0 { v[1].abs() } else { self.max_value - v[1].abs() };
+            (s, t)
+        };
+        self.canonicalize_coords(s, t)
+    }
+
+    fn canonicalize_coords(&self, mut s: i32, mut t: i32) -> [i32; 2] {
+        let (max, center) = (self.max_value, self.center);
+        if (s == 0 && (t == 0 || t == max)) || (s == max && t == 0) {
+            s = max;
+            t = max;
+        } else if s == 0 && t > center {
+            t = center - (t - center);
+        } else if s == max && t < center {
+            t = center + (center - t);
+        } else if t == max && s < center {
+            s = center - (s - center);
+        } else if t == 0 && s > center {
+            s = center + (center - s);
+        }
+        [s, t]
+    }
+
+    fn is_in_diamond(&self, s: i32, t: i32) -> bool {
+        s.abs() + t.abs() <= self.center
+    }
+
+    fn invert_diamond(&self, s: &mut i32, t: &mut i32) {
+        let (sign_s, sign_t) = if *s >= 0 && *t >= 0 {
+            (1, 1)
+        } else if *s <= 0 && *t <= 0 {
+            (-1, -1)
+        } else {
+            (if *s > 0 { 1 } else { -1 }, if *t > 0 { 1 } else { -1 })
+        };
+        let corner_s = sign_s * self.center;
+        let corner_t = sign_t * self.center;
+        let mut us = 2 * *s - corner_s;
+        let mut ut = 2 * *t - corner_t;
+        if sign_s * sign_t >= 0 {
+            (us, ut) = (-ut, -us);
+        } else {
+            std::mem::swap(&mut us, &mut ut);
+        }
+        *s = (us + corner_s) / 2;
+        *t = (ut + corner_t) / 2;
+    }
+
+    fn mod_max(&self, x: i32) -> i32 {
+        if x > self.center {
+            x - self.max_quantized
+        } else if x < -self.center {
+            x + self.max_quantized
+        } else {
+            x
+        }
+    }
+}
+
+// Prediction schemes: a predictor guesses each value from ones already
+// decoded, the transform combines the guess with the stored correction
+
+enum Transform {
+    Wrap { min: i32, max: i32 },
+    Octahedron(Option<Octahedron>),
+    Canonicalized(Option<Octahedron>),
+}
+
+impl Transform {
+    fn corrections_positive(&self) -> bool {
+        !matches!(self, Transform::Wrap { .. })
+    }
+
+    fn decode_data(&mut self, buf: &mut Buffer) -> Result<()> {
+        let canonicalized = matches!(self, Transform::Canonicalized(_));
+        match self {
+            Transform::Wrap { min, max } => {
+                *min = buf.i32()?;
+                *max = buf.i32()?;
+                if *min > *max || (*max as i64 - *min as i64) >= i32::MAX as i64 {
+                    return Err("bad wrap transform".into());
+                }
+            }
+            Transform::Octahedron(octahedron) | Transform::Canonicalized(octahedron) => {
+                let max_quantized = buf.i32()?;
+                // the center is implied by the above
+                if canonicalized {
+                    buf.i32()?;
+                }
+                if max_quantized <= 0 || max_quantized % 2 == 0 {
+                    return Err("bad octahedron transform".into());
+                }
+                *octahedron = Some(Octahedron::new(32 - max_quantized.leading_zeros())?);
+            }
+        }
+        Ok(())
+    }
+
+    fn octahedron(&self) -> Result<Octahedron> {
+        match self {
+            Transform::Octahedron(Some(o)) | Transform::Canonicalized(Some(o)) => Ok(*o),
+            _ => Err("normal prediction needs an octahedron transform".into()),
+        }
+    }
+
+    // `values` holds the corrections on the way in
+    fn apply(&self, predicted: &[i32], values: &mut [i32]) -> Result<()> {
+        match self {
+            Transform::Wrap { min, max } => {
+                let max_dif = max - min + 1;
+                for (value, &p) in values.iter_mut().zip(predicted) {
+                    let mut original = p.clamp(*min, *max).wrapping_add(*value);
+                    if original > *max {
+                        original -= max_dif;
+                    } else if original < *min {
+                        original += max_dif;
+                    }
+                    *value = original;
+                }
+            }
+            Transform::Octahedron(_) => {
+                let o = self.octahedron()?;
+                let (mut s, mut t) = (predicted[0] - o.center, predicted[1] - o.center);
+                let in_diamond = o.is_in_diamond(s, t);
+                if !in_diamond {
+                    o.invert_diamond(&mut s, &mut t);
+                }
+                let mut os = o.mod_max(s.wrapping_add(values[0]));
+                let mut ot = o.mod_max(t.wrapping_add(values[1]));
+                if !in_diamond {
+                    o.invert_diamond(&mut os, &mut ot);
+                }
+                values[0] = os + o.center;
+                values[1] = ot + o.center;
+            }
+            // predictions are rotated into the bottom left quadrant first so
+            // the corrections stay small and positive
+            Transform::Canonicalized(_) => {
+                let o = self.octahedron()?;
+                let (mut s, mut t) = (predicted[0] - o.center, predicted[1] - o.center);
+                let in_diamond = o.is_in_diamond(s, t);
+                if !in_diamond {
+                    o.invert_diamond(&mut s, &mut t);
+                }
+                let in_bottom_left = (s == 0 && t == 0) || (s < 0 && t <= 0);
+                let rotation = match (s.signum(), t.signum()) {
+                    (0, 0) => 0,
+                    (0, 1) => 3,
+                    (0, _) => 1,
+                    (1, 0 | 1) => 2,
+                    (1, _) => 1,
+                    (_, 1) => 3,
+                    _ => 0,
+                };
+                if !in_bottom_left {
+                    (s, t) = rotate(s, t, rotation);
+                }
+                let mut os = o.mod_max(s.wrapping_add(values[0]));
+                let mut ot = o.mod_max(t.wrapping_add(values[1]));
+                if !in_bottom_left {
+                    (os, ot) = rotate(os, ot, (4 - rotation) % 4);
+                }
+                if !in_diamond {
+                    o.invert_diamond(&mut os, &mut ot);
+                }
+                values[0] = os + o.center;
+                values[1] = ot + o.center;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn rotate(s: i32, t: i32, count: i32) -> (i32, i32) {
+    match count {
+        1 => (t, -s),
+        2 => (-s, -t),
+        3 => (-t, s),
+        _ => (s, t),
+    }
+}
+
+const MAX_PARALLELOGRAMS: usize = 4;
+
+enum Predictor<'p, 'a> {
+    Difference,
+    Parallelogram,
+    // up to four parallelograms around the vertex, averaged unless flagged
+    // as crease edges. Flags are grouped by how many were available
+    MultiParallelogram {
+        creases: Vec<Vec<bool>>,
+    },
+    TexCoords {
+        position: &'p DecodedAttribute,
+        orientations: Vec<bool>,
+    },
+    GeometricNormal {
+        position: &'p DecodedAttribute,
+        flips: Option<BitDecoder<'a>>,
+    },
+}
+
+struct Scheme<'p, 'a> {
+    predictor: Predictor<'p, 'a>,
+    transform: Transform,
+}
+
+impl<'p, 'a> Scheme<'p, 'a> {
+    fn new(
+        method: i8,
+        transform: i8,
+        codec: Codec,
+        sequence: &Sequence,
+        position: Option<&'p DecodedAttribute>,
+    ) -> Result<Self> {
+        let transform = match (codec, transform) {
+            (Codec::Normals, 2) => Transform::Octahedron(None),
+            (Codec::Normals, 3) => Transform::Canonicalized(None),
+            (Codec::Integer | Codec::Quantization, 1) => Transform::Wrap { min: 0, max: 0 },
+            _ => return Err(format!("unsupported prediction transform {}", transform)),
+        };
+
+        let parent = || {
+            position
+                .filter(|p| p.portable_components == 3)
+                .ok_or_else(|| String::from("prediction needs a decoded position"))
+        };
+
+        // without mesh connectivity everything falls back to differences,
+        // same as the encoder
+        let predictor = match method {
+            _ if sequence.mesh.is_none() => Predictor::Difference,
+            0 => Predictor::Difference,
+            1 => Predictor::Parallelogram,
+            4 => Predictor::MultiParallelogram {
+                creases: Vec::new(),
+            },
+            5 => Predictor::TexCoords {
+                position: parent()?,
+                orientations: Vec::new(),
+            },
+            6 if codec == Codec::Normals => Predictor::GeometricNormal {
+                position: parent()?,
+                flips: None,
+            },
+            _ => return Err(format!("unsupported prediction method {}", method)),
+        };
+
+        Ok(Self {
+            predictor,
+            transform,
+        })
+    }
+
+    fn decode_data(&mut self, buf: &mut Buffer<'a>, sequence: &Sequence) -> Result<()> {
+        match &mut self.predictor {
+            Predictor::MultiParallelogram { creases } => {
+                let num_corners = sequence.mesh.as_ref().map_or(0, |m| m.table.num_corners());
+                for _ in 0..MAX_PARALLELOGRAMS {
+                    let count = buf.varint_u32()? as usize;
+                    if count > num_corners {
+                        return Err("bad crease edge count".into());
+                    }
+                    let mut flags = Vec::with_capacity(count);
+                    if count > 0 {
+                        let mut bits = BitDecoder::start(buf)?;
+                        flags.extend((0..count).map(|_| bits.next()));
+                    }
+                    creases.push(flags);
+                }
+                self.transform.decode_data(buf)
+            }
+            Predictor::TexCoords { orientations, .. } => {
+                let count = buf.i32()?;
+                if count < 0 || count as usize > buf.remaining().len() * 8 {
+                    return Err("bad orientation count".into());
+                }
+                // stored as changes from the previous orientation
+                let mut bits = BitDecoder::start(buf)?;
+                let mut last = true;
+                for _ in 0..count {
+                    if !bits.next() {
+                        last = !last;
+                    }
+                    orientations.push(last);
+                }
+                self.transform.decode_data(buf)
+            }
+            Predictor::GeometricNormal { flips, .. } => {
+                self.transform.decode_data(buf)?;
+                *flips = Some(BitDecoder::start(buf)?);
+                Ok(())
+            }
+            _ => self.transform.decode_data(buf),
+        }
+    }
+
+    fn compute_original(&mut self, values: &mut [i32], components: usize, sequence: &Sequence) -> Result<()> {
+        let num_entries = values.len() / components;
+        if num_entries == 0 {
+            return Ok(());
+        }
+
+        let mesh = sequence.mesh.as_ref();
+        let mut predicted = vec![0i32; components];
+
+        match &mut self.predictor {
+            Predictor::Difference => {
+                self.transform.apply(&predicted, &mut values[..components])?;
+                for i in 1..num_entries {
+                    predicted.copy_from_slice(&values[(i - 1) * components..i * components]);
+                    self.transform
+                        .apply(&predicted, &mut values[i * components..(i + 1) * components])?;
+                }
+            }
+            Predictor::Parallelogram => {
+                let mesh = mesh.unwrap();
+                self.transform.apply(&predicted, &mut values[..components])?;
+                for p in 1..num_entries {
+                    let corner = mesh.value_to_corner[p];
+                    if !parallelogram(mesh, values, components, corner, p, &mut predicted) {
+                        predicted.copy_from_slice(&values[(p - 1) * components..p * components]);
+                    }
+                    self.transform
+                        .apply(&predicted, &mut values[p * components..(p + 1) * components])?;
+                }
+            }
+            Predictor::MultiParallelogram { creases } => {
+                let mesh = mesh.unwrap();
+                let mut candidates = vec![vec![0i32; components]; MAX_PARALLELOGRAMS];
+                let mut crease_positions = [0usize; MAX_PARALLELOGRAMS];
+                self.transform.apply(&predicted, &mut values[..components])?;
+
+                for p in 1..num_entries {
+                    // left around the vertex, then right from the start once
+                    // a boundary is hit
+                    let start = mesh.value_to_corner[p];
+                    let mut corner = start;
+                    let mut count = 0;
+                    let mut first_pass = true;
+                    while corner != INVALID {
+                        if parallelogram(mesh, values, components, corner, p, &mut candidates[count]) {
+                            count += 1;
+                            if count == MAX_PARALLELOGRAMS {
+                                break;
+                            }
+                        }
+                        corner = if first_pass {
+                            mesh.table.swing_left(corner)
+                        } else {
+                            mesh.table.swing_right(corner)
+                        };
+                        if corner == start {
+                            break;
+                        }
+                        if corner == INVALID && first_pass {
+                            first_pass = false;
+                            corner = mesh.table.swing_right(start);
+                        }
+                    }
+
+                    let mut sum = vec![0i32; components];
+                    let mut used = 0;
+                    for candidate in &candidates[..count] {
+                        let context = count - 1;
+                        let position = crease_positions[context];
+                        crease_positions[context] += 1;
+                        let crease = *creases[context]
+                            .get(position)
+                            .ok_or("missing crease edge flag")?;
+                        if !crease {
+                            used += 1;
+                            for (s, &c) in sum.iter_mut().zip(candidate) {
+                                *s = s.wrapping_add(c);
+                            }
+                        }
+                    }
+
+                    if used == 0 {
+                        predicted.copy_from_slice(&values[(p - 1) * components..p * components]);
+                    } else {
+                        for (pr, s) in predicted.iter_mut().zip(&sum) {
+                            *pr = s / used;
+                        }
+                    }
+                    self.transform
+                        .apply(&predicted, &mut values[p * components..(p + 1) * components])?;
+                }
+            }
+            Predictor::TexCoords {
+                position,
+                orientations,
+            } => {
+                let mesh = mesh.unwrap();
+                if components != 2 {
+                    return Err("tex coord prediction needs two components".into());
+                }
+                for p in 0..num_entries {
+                    let corner = mesh.value_to_corner[p];
+                    let prediction = predict_tex_coord(
+                        mesh,
+                        &sequence.point_ids,
+                        position,
+                        orientations,
+                        values,
+                        corner,
+                        p,
+                    )?;
+                    self.transform.apply(&prediction, &mut values[p * 2..p * 2 + 2])?;
+                }
+            }
+            Predictor::GeometricNormal { position, flips } => {
+                let mesh = mesh.unwrap();
+                let flips = flips.as_mut().ok_or("missing normal flips")?;
+                let octahedron = self.transform.octahedron()?;
+                let position_of = |corner: u32| -> Result<[i64; 3]> {
+                    let value = mesh.value_at(corner);
+                    let point = *sequence
+                        .point_ids
+                        .get(value as usize)
+                        .ok_or("corrupt normal prediction")?;
+                    Ok(position.portable_value(point))
+                };
+
+                for p in 0..num_entries {
+                    let corner = mesh.value_to_corner[p];
+                    let center = position_of(corner)?;
+
+                    // area weighted sum of the face normals around the vertex
+                    let mut normal = [0i64; 3];
+                    for c in mesh.table.corners_around(corner) {
+                        let next_pos = position_of(next(c))?;
+                        let prev_pos = position_of(previous(c))?;
+                        let a: [i64; 3] = std::array::from_fn(|i| next_pos[i] - center[i]);
+                        let b: [i64; 3] = std::array::from_fn(|i| prev_pos[i] - center[i]);
+                        let cross = [
+                            a[1].wrapping_mul(b[2]).wrapping_sub(a[2].wrapping_mul(b[1])),
+                            a[2].wrapping_mul(b[0]).wrapping_sub(a[0].wrapping_mul(b[2])),
+                            a[0].wrapping_mul(b[1]).wrapping_sub(a[1].wrapping_mul(b[0])),
+                        ];
+                        for i in 0..3 {
+                            normal[i] = normal[i].wrapping_add(cross[i]);
+                        }
+                    }
+
+                    let upper_bound = 1i64 << 29;
+                    let abs_sum = normal.iter().fold(0i64, |sum, c| sum.wrapping_add(c.wrapping_abs()));
+                    if abs_sum > upper_bound {
+                        let quotient = abs_sum / upper_bound;
+                        normal = normal.map(|c| c / quotient);
+                    }
+
+                    let mut normal = normal.map(|c| c as i32);
+                    octahedron.canonicalize_vector(&mut normal);
+                    if flips.next() {
+                        normal = normal.map(|c| -c);
+                    }
+                    let prediction = octahedron.vector_to_coords(normal);
+                    self.transform.apply(&prediction, &mut values[p * 2..p * 2 + 2])?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Completes the parallelogram across the edge facing `corner`, if all three
+// of its other vertices are already decoded
+fn parallelogram(
+    mesh: &Traversed,
+    values: &[i32],
+    components: usize,
+    corner: u32,
+    p: usize,
+    out: &mut [i32],
+) -> bool {
+    let opposite = mesh.table.opposite(corner);
+    if opposite == INVALID {
+        return false;
+    }
+    let entries = [
+        mesh.value_at(opposite),
+        mesh.value_at(next(opposite)),
+        mesh.value_at(previous(opposite)),
+    ];
+    if entries.iter().any(|&e| e as usize >= p) {
+        return false;
+    }
+    let [opp, next, prev] = entries.map(|e| e as usize * components);
+    for (c, o) in out.iter_mut().enumerate() {
+        *o = (values[next + c] as i64 + values[prev + c] as i64 - values[opp + c] as i64) as i32;
+    }
+    true
+}
+
+// MeshPredictionSchemeTexCoordsPortablePredictor: places the new uv so the
+// uv triangle has the same shape as the position triangle, the stored
+// orientation says which side of the shared edge it goes
+fn predict_tex_coord(
+    mesh: &Traversed,
+    point_ids: &[u32],
+    position: &DecodedAttribute,
+    orientations: &mut Vec<bool>,
+    values: &[i32],
+    corner: u32,
+    p: usize,
+) -> Result<[i32; 2]> {
+    let next_entry = mesh.value_at(next(corner)) as usize;
+    let prev_entry = mesh.value_at(previous(corner)) as usize;
+    let uv = |entry: usize| [values[entry * 2] as i64, values[entry * 2 + 1] as i64];
+    let position_of = |entry: usize| -> Result<[i64; 3]> {
+        let point = *point_ids.get(entry).ok_or("corrupt tex coord prediction")?;
+        Ok(position.portable_value(point))
+    };
+
+    if prev_entry < p && next_entry < p {
+        let n_uv = uv(next_entry);
+        let p_uv = uv(prev_entry);
+        if p_uv == n_uv {
+            return Ok([p_uv[0] as i32, p_uv[1] as i32]);
+        }
+
+        let tip = position_of(p)?;
+        let next_pos = position_of(next_entry)?;
+        let prev_pos = position_of(prev_entry)?;
+        let pn: [i64; 3] = std::array::from_fn(|i| prev_pos[i] - next_pos[i]);
+        let pn_norm2 = pn.iter().fold(0i64, |sum, &c| sum.wrapping_add(c.wrapping_mul(c)));
+        if pn_norm2 != 0 {
+            let cn: [i64; 3] = std::array::from_fn(|i| tip[i] - next_pos[i]);
+            let cn_dot_pn = (0..3).fold(0i64, |sum, i| sum.wrapping_add(pn[i].wrapping_mul(cn[i])));
+            let pn_uv = [p_uv[0] - n_uv[0], p_uv[1] - n_uv[1]];
+
+            let overflow = || String::from("tex coord prediction overflow");
+            let n_uv_max = n_uv[0].abs().max(n_uv[1].abs());
+            if n_uv_max as u64 > i64::MAX as u64 / pn_norm2 as u64 {
+                return Err(overflow());
+            }
+            let pn_uv_max = pn_uv[0].abs().max(pn_uv[1].abs());
+            if cn_dot_pn > i64::MAX / pn_uv_max {
+                return Err(overflow());
+            }
+            let x_uv: [i64; 2] = std::array::from_fn(|i| {
+                n_uv[i]
+                    .wrapping_mul(pn_norm2)
+                    .wrapping_add(cn_dot_pn.wrapping_mul(pn_uv[i]))
+            });
+            let pn_max = pn.iter().map(|c| c.abs()).max().unwrap();
+            if cn_dot_pn > i64::MAX / pn_max {
+                return Err(overflow());
+            }
+            let x_pos: [i64; 3] =
+                std::array::from_fn(|i| next_pos[i].wrapping_add(cn_dot_pn.wrapping_mul(pn[i]) / pn_norm2));
+            let cx_norm2 = (0..3).fold(0i64, |sum, i| {
+                let d = tip[i].wrapping_sub(x_pos[i]);
+                sum.wrapping_add(d.wrapping_mul(d))
+            });
+
+            let norm = int_sqrt((cx_norm2 as u64).wrapping_mul(pn_norm2 as u64)) as i64;
+            let cx_uv = [pn_uv[1].wrapping_mul(norm), (-pn_uv[0]).wrapping_mul(norm)];
+
+            let orientation = orientations.pop().ok_or("missing tex coord orientation")?;
+            let predicted: [i64; 2] = if orientation {
+                std::array::from_fn(|i| x_uv[i].wrapping_add(cx_uv[i]) / pn_norm2)
+            } else {
+                std::array::from_fn(|i| x_uv[i].wrapping_sub(cx_uv[i]) / pn_norm2)
+            };
+            return Ok([predicted[0] as i32, predicted[1] as i32]);
+        }
+    }
+
+    // delta from whatever neighbour is available, as the reference does
+    let entry = if next_entry < p {
+        next_entry
+    } else if p > 0 {
+        p - 1
+    } else {
+        return Ok([0, 0]);
+    };
+    Ok([values[entry * 2], values[entry * 2 + 1]])
+}
+
+fn int_sqrt(number: u64) -> u64 {
+    if number == 0 {
+        return 0;
+    }
+    let mut act = number;
+    let mut root = 1u64;
+    while act >= 2 {
+        root *= 2;
+        act /= 4;
+    }
+    loop {
+        root = (root + number / root) / 2;
+        if root.wrapping_mul(root) <= number {
+            return root;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOX: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Box");
+
+    // the extension's buffer view, NORMAL is attribute 0, POSITION 1
+    fn draco_box() -> Vec<u8> {
+        let bin = std::fs::read(format!("{}/glTF-Draco/Box.bin", BOX)).unwrap();
+        bin[..118].to_vec()
+    }
+
+    fn floats(bytes: &[u8]) -> Vec<[f32; 3]> {
+        bytes
+            .chunks_exact(12)
+            .map(|v| {
+                let f = |i: usize| f32::from_le_bytes(v[i..i + 4].try_into().unwrap());
+                [f(0), f(4), f(8)]
+            })
+            .collect()
+    }
+
+    // (position, normal) per corner of every triangle
+    type Triangle = [([f32; 3], [f32; 3]); 3];
+
+    fn close(a: [f32; 3], b: [f32; 3], tolerance: f32) -> bool {
+        (0..3).all(|k| (a[k] - b[k]).abs() <= tolerance)
+    }
+
+    // same corners in the same winding, starting anywhere
+    fn same_triangle(a: &Triangle, b: &Triangle) -> bool {
+        (0..3).any(|start| {
+            (0..3).all(|k| {
+                let (p, n) = a[k];
+                let (q, m) = b[(start + k) % 3];
+                // both are quantized
+                close(p, q, 1e-3) && close(n, m, 1e-2)
+            })
+        })
+    }
+
+    #[test]
+    fn decodes_the_box_like_the_uncompressed_one() {
+        let mesh = decode(&draco_box()).unwrap();
+        let normals = mesh.attribute(0).unwrap();
+        let positions = mesh.attribute(1).unwrap();
+        assert_eq!((normals.components, positions.components), (3, 3));
+        assert_eq!(positions.values.len(), mesh.num_points * 3);
+        assert_eq!(normals.values.len(), mesh.num_points * 3);
+        let corner = |i: u32| {
+            let i = i as usize * 3;
+            (
+                positions.values[i..i + 3].try_into().unwrap(),
+                normals.values[i..i + 3].try_into().unwrap(),
+            )
+        };
+        let decoded: Vec<Triangle> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| [corner(t[0]), corner(t[1]), corner(t[2])])
+            .collect();
+
+        // glTF/Box0.bin: normals, then positions, then u16 indices
+        let bin = std::fs::read(format!("{}/glTF/Box0.bin", BOX)).unwrap();
+        let ref_normals = floats(&bin[..288]);
+        let ref_positions = floats(&bin[288..576]);
+        let reference: Vec<Triangle> = bin[576..648]
+            .chunks_exact(6)
+            .map(|t| {
+                let corner = |k: usize| {
+                    let i = u16::from_le_bytes([t[k * 2], t[k * 2 + 1]]) as usize;
+                    (ref_positions[i], ref_normals[i])
+                };
+                [corner(0), corner(1), corner(2)]
+            })
+            .collect();
+
+        assert_eq!(decoded.len(), reference.len());
+        for triangle in &reference {
+            assert!(
+                decoded.iter().any(|d| same_triangle(d, triangle)),
+                "{:?} is missing",
+                triangle
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_buffers_are_errors() {
+        let data = draco_box();
+        for len in 0..data.len() {
+            assert!(decode(&data[..len]).is_err(), "{} bytes decoded", len);
+        }
+    }
+
+    #[test]
+    fn corrupt_buffers_dont_panic() {
+        let data = draco_box();
+        for i in 0..data.len() {
+            for flip in [0x01, 0x10, 0x80, 0xff] {
+                let mut corrupt = data.clone();
+                corrupt[i] ^= flip;
+                let _ = decode(&corrupt);
+            }
+        }
+    }
+}
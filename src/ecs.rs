@@ -0,0 +1,289 @@
+// A small entity-component store. An entity is just an id, components are
+// any 'static type, kept in one storage per type indexed by entity. Systems
+// get the whole world every tick and query what they need
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+// The generation tells a despawned entity from a new one reusing its slot
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+// Runs once per simulation tick, `dt` is the tick length in seconds.
+// Plain functions taking the same arguments are systems too
+pub trait System {
+    fn run(&mut self, world: &mut World, dt: f32);
+}
+
+impl<F: FnMut(&mut World, f32)> System for F {
+    fn run(&mut self, world: &mut World, dt: f32) {
+        self(world, dt)
+    }
+}
+
+// Type erased so every storage can drop the components of a despawned entity
+trait AnyStorage {
+    fn remove(&mut self, index: usize);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+struct Storage<T> {
+    items: Vec<Option<T>>,
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+    fn remove(&mut self, index: usize) {
+        if let Some(item) = self.items.get_mut(index) {
+            *item = None;
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct World {
+    // per slot, bumped when the entity in it is despawned
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+}
+
+impl World {
+    pub fn spawn(&mut self) -> Entity {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                self.generations.len() as u32 - 1
+            }
+        };
+        self.alive[index as usize] = true;
+        Entity {
+            index,
+            generation: self.generations[index as usize],
+        }
+    }
+
+    // Drops all its components. Despawning twice does nothing
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.is_alive(entity) {
+            return;
+        }
+        let index = entity.index as usize;
+        for storage in self.storages.values_mut() {
+            storage.remove(index);
+        }
+        self.alive[index] = false;
+        self.generations[index] += 1;
+        self.free.push(entity.index);
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index) == Some(&true) && self.generations[index] == entity.generation
+    }
+
+    // Replaces the entity's component of that type if it had one
+    pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) {
+        if !self.is_alive(entity) {
+            log::warn!("{:?} was despawned, not adding a component", entity);
+            return;
+        }
+        let storage = self
+            .storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T> { items: Vec::new() }))
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+            .unwrap();
+        let index = entity.index as usize;
+        if storage.items.len() <= index {
+            storage.items.resize_with(index + 1, || None);
+        }
+        storage.items[index] = Some(component);
+    }
+
+    pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage_mut::<T>()?
+            .items
+            .get_mut(entity.index as usize)?
+            .take()
+    }
+
+    pub fn get<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage::<T>()?
+            .items
+            .get(entity.index as usize)?
+            .as_ref()
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage_mut::<T>()?
+            .items
+            .get_mut(entity.index as usize)?
+            .as_mut()
+    }
+
+    // Every entity with a T, oldest slot first
+    pub fn query<T: 'static>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        let items = self
+            .storage::<T>()
+            .map_or(&[][..], |storage| &storage.items);
+        items.iter().enumerate().filter_map(|(index, item)| {
+            let entity = Entity {
+                index: index as u32,
+                generation: self.generations[index],
+            };
+            Some((entity, item.as_ref()?))
+        })
+    }
+
+    pub fn query_mut<T: 'static>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        let generations = &self.generations;
+        let items: &mut [Option<T>] = match self.storages.get_mut(&TypeId::of::<T>()) {
+            Some(storage) => {
+                &mut storage
+                    .as_any_mut()
+                    .downcast_mut::<Storage<T>>()
+                    .unwrap()
+                    .items
+            }
+            None => &mut [],
+        };
+        items.iter_mut().enumerate().filter_map(|(index, item)| {
+            let entity = Entity {
+                index: index as u32,
+                generation: generations[index],
+            };
+            Some((entity, item.as_mut()?))
+        })
+    }
+
+    fn storage<T: 'static>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())?
+            .as_any()
+            .downcast_ref::<Storage<T>>()
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> Option<&mut Storage<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())?
+            .as_any_mut()
+            .downcast_mut::<Storage<T>>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Speed(f32);
+
+    #[test]
+    fn despawned_slots_are_reused_and_stale_handles_rejected() {
+        let mut world = World::default();
+        let old = world.spawn();
+        world.insert(old, Health(3));
+        world.despawn(old);
+        world.despawn(old);
+
+        let new = world.spawn();
+        assert_eq!(new.index, old.index);
+        assert_ne!(new, old);
+        assert!(!world.is_alive(old));
+        assert!(world.is_alive(new));
+        // the old component went with its entity
+        assert_eq!(world.get::<Health>(new), None);
+
+        world.insert(old, Health(5));
+        assert_eq!(world.get::<Health>(new), None);
+        world.insert(new, Health(7));
+        assert_eq!(world.get::<Health>(old), None);
+        assert_eq!(world.remove::<Health>(old), None);
+        assert_eq!(world.get::<Health>(new), Some(&Health(7)));
+    }
+
+    #[test]
+    fn removed_components_are_gone() {
+        let mut world = World::default();
+        let entity = world.spawn();
+        world.insert(entity, Health(3));
+        world.insert(entity, Speed(2.0));
+
+        assert_eq!(world.remove::<Health>(entity), Some(Health(3)));
+        assert_eq!(world.remove::<Health>(entity), None);
+        assert_eq!(world.get::<Health>(entity), None);
+        assert_eq!(world.query::<Health>().count(), 0);
+        assert_eq!(world.get::<Speed>(entity), Some(&Speed(2.0)));
+        assert!(world.is_alive(entity));
+    }
+
+    #[test]
+    fn query_mut_visits_only_matching_entities() {
+        let mut world = World::default();
+        let a = world.spawn();
+        let b = world.spawn();
+        let c = world.spawn();
+        world.insert(a, Speed(1.0));
+        world.insert(b, Health(1));
+        world.insert(c, Speed(3.0));
+
+        let mut visited = Vec::new();
+        for (entity, speed) in world.query_mut::<Speed>() {
+            speed.0 *= 2.0;
+            visited.push(entity);
+        }
+        assert_eq!(visited, [a, c]);
+        assert_eq!(world.get::<Speed>(a), Some(&Speed(2.0)));
+        assert_eq!(world.get::<Speed>(c), Some(&Speed(6.0)));
+        assert_eq!(world.query_mut::<u8>().count(), 0);
+
+        let healthy: Vec<_> = world.query::<Health>().map(|(entity, _)| entity).collect();
+        assert_eq!(healthy, [b]);
+    }
+
+    #[test]
+    fn closures_are_systems() {
+        let mut world = World::default();
+        let entity = world.spawn();
+        world.insert(entity, Speed(1.0));
+        let mut ticks = 0;
+        let mut system = |world: &mut World, dt: f32| {
+            for (_, speed) in world.query_mut::<Speed>() {
+                speed.0 += dt;
+            }
+            ticks += 1;
+        };
+        system.run(&mut world, 0.5);
+        system.run(&mut world, 0.5);
+        assert_eq!(ticks, 2);
+        assert_eq!(world.get::<Speed>(entity), Some(&Speed(2.0)));
+    }
+}
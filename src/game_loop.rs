@@ -0,0 +1,190 @@
+// Runs the simulation at a fixed rate, however fast frames are drawn. Real
+// time accumulates and is spent in whole ticks, what's left over says how
+// far between the last two ticks the frame is, to draw everything there.
+// Absolute times are f64, an f32 clock loses milliseconds after a few hours
+
+// 60 ticks per second
+pub const TICK: f32 = 1.0 / 60.0;
+
+// A frame that took longer than this many ticks (a breakpoint, a slow
+// load) doesn't get caught up with, the rest of the time is dropped.
+// Otherwise the ticks could take longer than the time they simulate
+pub const MAX_STEPS: u32 = 8;
+
+// a tick due this close counts as due, or float error in summing frame
+// times every so often leaves one for the next frame
+const SLACK: f32 = 1e-5;
+
+pub trait Simulation {
+    // One step of `dt` seconds, `time` is the simulated time after it
+    fn tick(&mut self, time: f64, dt: f32);
+}
+
+pub struct GameLoop {
+    step: f32,
+    max_steps: u32,
+    // real time not simulated yet, less than a step between frames
+    accumulator: f32,
+    last_frame: Option<f64>,
+    ticks: u64,
+    // total time the max steps safeguard threw away
+    dropped: f32,
+}
+
+impl Default for GameLoop {
+    fn default() -> Self {
+        Self::new(TICK, MAX_STEPS)
+    }
+}
+
+impl GameLoop {
+    pub fn new(step: f32, max_steps: u32) -> Self {
+        Self {
+            step,
+            max_steps,
+            accumulator: 0.0,
+            last_frame: None,
+            ticks: 0,
+            dropped: 0.0,
+        }
+    }
+
+    // Call once per frame with the real time in seconds. Runs as many ticks
+    // as fit and returns how far into the next one the frame is, 0..1. The
+    // first frame only starts the clock
+    pub fn advance(&mut self, now: f64, simulation: &mut impl Simulation) -> f32 {
+        let elapsed = self.last_frame.map_or(0.0, |last| (now - last).max(0.0));
+        self.last_frame = Some(now);
+        self.accumulator += elapsed as f32;
+
+        let mut steps = 0;
+        while self.accumulator + SLACK >= self.step {
+            if steps == self.max_steps {
+                log::debug!("simulation fell behind, dropping {:.3}s", self.accumulator);
+                self.dropped += self.accumulator;
+                self.accumulator = 0.0;
+                break;
+            }
+            self.ticks += 1;
+            simulation.tick(self.time(), self.step);
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        (self.accumulator / self.step).clamp(0.0, 1.0)
+    }
+
+    // Seconds simulated so far
+    pub fn time(&self) -> f64 {
+        self.ticks as f64 * self.step as f64
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn dropped(&self) -> f32 {
+        self.dropped
+    }
+}
+
+// Drives `simulation` without a window or a real clock, `frame_time`
+// apart for `seconds` of made up time. Returns the loop to look at
+pub fn run_headless(simulation: &mut impl Simulation, seconds: f64, frame_time: f64) -> GameLoop {
+    let mut game_loop = GameLoop::default();
+    let frames = (seconds / frame_time).round() as u64;
+    for frame in 0..=frames {
+        game_loop.advance(frame as f64 * frame_time, simulation);
+    }
+    game_loop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every tick's (time, dt)
+    #[derive(Default)]
+    struct Counter(Vec<(f64, f32)>);
+
+    impl Simulation for Counter {
+        fn tick(&mut self, time: f64, dt: f32) {
+            self.0.push((time, dt));
+        }
+    }
+
+    #[test]
+    fn ticks_at_the_same_rate_for_any_frame_rate() {
+        for fps in [30.0, 60.0, 144.0] {
+            let mut counter = Counter::default();
+            let game_loop = run_headless(&mut counter, 10.0, 1.0 / fps);
+            assert_eq!(game_loop.ticks(), 600, "{} fps", fps);
+            assert_eq!(counter.0.len(), 600, "{} fps", fps);
+            assert_eq!(game_loop.dropped(), 0.0, "{} fps", fps);
+            for (i, &(time, dt)) in counter.0.iter().enumerate() {
+                assert_eq!(dt, TICK);
+                let expected = (i + 1) as f64 * TICK as f64;
+                assert!((time - expected).abs() < 1e-4, "{} fps", fps);
+            }
+        }
+    }
+
+    #[test]
+    fn long_frames_drop_what_max_steps_cant_catch_up() {
+        let mut counter = Counter::default();
+        let mut game_loop = GameLoop::default();
+        game_loop.advance(0.0, &mut counter);
+        let alpha = game_loop.advance(1.0, &mut counter);
+        assert_eq!(game_loop.ticks(), MAX_STEPS as u64);
+        assert_eq!(alpha, 0.0);
+        let dropped = 1.0 - MAX_STEPS as f32 * TICK;
+        assert!((game_loop.dropped() - dropped).abs() < 1e-5);
+
+        // back to normal from the next frame on
+        game_loop.advance(1.0 + TICK as f64, &mut counter);
+        assert_eq!(game_loop.ticks(), MAX_STEPS as u64 + 1);
+        assert!((game_loop.dropped() - dropped).abs() < 1e-5);
+    }
+
+    #[test]
+    fn alpha_stays_between_ticks() {
+        let mut counter = Counter::default();
+        let mut game_loop = GameLoop::default();
+        let mut now = 0.0;
+        // frame times that don't line up with the ticks
+        for frame in 0..1000 {
+            now += [0.007, 0.011, 0.0213, 0.0004][frame % 4];
+            let alpha = game_loop.advance(now, &mut counter);
+            assert!((0.0..1.0).contains(&alpha), "{} at {}", alpha, now);
+        }
+        assert!((game_loop.time() - now).abs() < TICK as f64);
+    }
+
+    #[test]
+    fn ticks_stay_even_after_hours() {
+        let mut counter = Counter::default();
+        let mut game_loop = GameLoop::default();
+        // ten hours in, where an f32 clock only has ~4ms steps
+        let start = 10.0 * 60.0 * 60.0;
+        for frame in 0..=600 {
+            let before = game_loop.ticks();
+            game_loop.advance(start + frame as f64 * TICK as f64, &mut counter);
+            if frame > 0 {
+                assert_eq!(game_loop.ticks(), before + 1, "frame {}", frame);
+            }
+        }
+        assert_eq!(game_loop.dropped(), 0.0);
+    }
+
+    #[test]
+    fn first_frame_only_starts_the_clock() {
+        let mut counter = Counter::default();
+        let mut game_loop = GameLoop::default();
+        assert_eq!(game_loop.advance(5.0, &mut counter), 0.0);
+        assert_eq!(game_loop.ticks(), 0);
+        assert!(counter.0.is_empty());
+
+        game_loop.advance(5.0 + TICK as f64, &mut counter);
+        assert_eq!(game_loop.ticks(), 1);
+        assert_eq!(counter.0, [(TICK as f64, TICK)]);
+    }
+}
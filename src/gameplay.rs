@@ -0,0 +1,31 @@
+// Gameplay components and the systems driving them. Anything added here
+// goes into `systems`, which every scene runs each tick
+
+use glam::{Quat, Vec3};
+
+use crate::ecs::{System, World};
+use crate::transform::Transform;
+
+// Turns the entity around `axis` in its own space, radians per second
+#[derive(Copy, Clone, Debug)]
+pub struct Spin {
+    pub axis: Vec3,
+    pub speed: f32,
+}
+
+pub fn systems() -> Vec<Box<dyn System>> {
+    vec![Box::new(spin)]
+}
+
+fn spin(world: &mut World, dt: f32) {
+    let spinning: Vec<_> = world
+        .query::<Spin>()
+        .map(|(entity, spin)| (entity, *spin))
+        .collect();
+    for (entity, spin) in spinning {
+        if let Some(transform) = world.get_mut::<Transform>(entity) {
+            let turn = Quat::from_axis_angle(spin.axis, spin.speed * dt);
+            transform.set_rotation((transform.rotation() * turn).normalize());
+        }
+    }
+}
@@ -0,0 +1,367 @@
+use glam::Vec3;
+
+use std::collections::HashMap;
+
+use crate::model::{MeshData, MorphTarget, Topology};
+
+// Fills in every attribute the importer couldn't find, in dependency order:
+// indices, uvs, normals (flat, like the glTF spec asks for), then tangents.
+// Points and lines have no surface, they only get placeholders
+pub fn fill_missing(mesh: &mut MeshData) {
+    let triangles = mesh.topology == Topology::Triangles;
+
+    let mut generated = Vec::new();
+
+    if mesh.indices.is_empty() {
+        mesh.indices = (0..mesh.positions.len() as u32).collect();
+        generated.push("indices");
+    }
+
+    let missing_uvs = mesh.uvs.is_empty();
+    if missing_uvs {
+        mesh.uvs = vec![[0.0, 0.0]; mesh.positions.len()];
+        generated.push("zero uvs");
+    }
+
+    if mesh.normals.is_empty() {
+        if triangles {
+            let triangle_count = mesh.indices.len() / 3;
+            generate_normals(mesh, &vec![0; triangle_count]);
+            // target normals only make sense relative to the ones in the file
+            for target in &mut mesh.targets {
+                target.normals.clear();
+            }
+            generated.push("flat normals");
+        } else {
+            mesh.normals = vec![[0.0, 0.0, 0.0]; mesh.positions.len()];
+            generated.push("zero normals");
+        }
+    }
+
+    if mesh.tangents.is_empty() {
+        // without uvs there is no tangent space to speak of
+        if !triangles || missing_uvs || !generate_tangents(mesh) {
+            mesh.tangents = vec![[1.0, 0.0, 0.0, 1.0]; mesh.positions.len()];
+            generated.push("placeholder tangents");
+        } else {
+            generated.push("tangents");
+        }
+        for target in &mut mesh.targets {
+            target.tangents.clear();
+        }
+    }
+
+    if !generated.is_empty() {
+        log::info!("{}: generated {}", mesh.name, generated.join(", "));
+    }
+}
+
+// Faces in the same non-zero group get averaged normals where they share a
+// position, group 0 is flat shaded. Vertices are split as needed, so any
+// existing tangents (and morph target tangents) are dropped
+pub fn generate_normals(mesh: &mut MeshData, triangle_groups: &[u32]) {
+    let face_normals: Vec<Vec3> = mesh
+        .indices
+        .chunks_exact(3)
+        .map(|tri| {
+            let a = Vec3::from(mesh.positions[tri[0] as usize]);
+            let b = Vec3::from(mesh.positions[tri[1] as usize]);
+            let c = Vec3::from(mesh.positions[tri[2] as usize]);
+            // not normalized, so bigger faces weigh more
+            (b - a).cross(c - a)
+        })
+        .collect();
+
+    let position_key = |p: [f32; 3]| [p[0].to_bits(), p[1].to_bits(), p[2].to_bits()];
+
+    let mut smooth: HashMap<([u32; 3], u32), Vec3> = HashMap::new();
+    for (t, tri) in mesh.indices.chunks_exact(3).enumerate() {
+        let group = triangle_groups[t];
+        if group == 0 {
+            continue;
+        }
+        for &v in tri {
+            let key = (position_key(mesh.positions[v as usize]), group);
+            *smooth.entry(key).or_insert(Vec3::ZERO) += face_normals[t];
+        }
+    }
+
+    // (old vertex, group or flat face) -> new vertex
+    let mut remap: HashMap<(u32, u32, Option<usize>), u32> = HashMap::new();
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut extra_uvs = vec![Vec::new(); mesh.extra_uvs.len()];
+    let mut joints = Vec::new();
+    let mut weights = Vec::new();
+    let mut targets: Vec<MorphTarget> = vec![MorphTarget::default(); mesh.targets.len()];
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+
+    for (t, tri) in mesh.indices.chunks_exact(3).enumerate() {
+        let group = triangle_groups[t];
+        for &v in tri {
+            let flat_face = if group == 0 { Some(t) } else { None };
+            let index = *remap.entry((v, group, flat_face)).or_insert_with(|| {
+                let position = mesh.positions[v as usize];
+                let normal = match flat_face {
+                    Some(_) => face_normals[t],
+                    None => smooth[&(position_key(position), group)],
+                };
+                positions.push(position);
+                normals.push(normal.normalize_or_zero().to_array());
+                uvs.push(mesh.uvs[v as usize]);
+                for (new, old) in extra_uvs.iter_mut().zip(&mesh.extra_uvs) {
+                    new.push(old[v as usize]);
+                }
+                // the importer sets mesh.skin later, from the node
+                if !mesh.joints.is_empty() {
+                    joints.push(mesh.joints[v as usize]);
+                    weights.push(mesh.weights[v as usize]);
+                }
+                for (new, old) in targets.iter_mut().zip(&mesh.targets) {
+                    if !old.positions.is_empty() {
+                        new.positions.push(old.positions[v as usize]);
+                    }
+                    if !old.normals.is_empty() {
+                        new.normals.push(old.normals[v as usize]);
+                    }
+                }
+                (positions.len() - 1) as u32
+            });
+            indices.push(index);
+        }
+    }
+
+    mesh.positions = positions;
+    mesh.normals = normals;
+    mesh.uvs = uvs;
+    mesh.extra_uvs = extra_uvs;
+    mesh.joints = joints;
+    mesh.weights = weights;
+    mesh.targets = targets;
+    mesh.indices = indices;
+    mesh.tangents.clear();
+}
+
+// MikkTSpace, so normal maps baked by other tools line up. Tangents are
+// generated per triangle corner, vertices whose corners disagree (uv seams,
+// mirrored uvs) are split like generate_normals does
+pub fn generate_tangents(mesh: &mut MeshData) -> bool {
+    struct Geometry<'a> {
+        mesh: &'a MeshData,
+        // one per index
+        corners: Vec<[f32; 4]>,
+    }
+
+    impl Geometry<'_> {
+        fn vertex(&self, face: usize, vert: usize) -> usize {
+            self.mesh.indices[face * 3 + vert] as usize
+        }
+    }
+
+    impl bevy_mikktspace::Geometry for Geometry<'_> {
+        fn num_faces(&self) -> usize {
+            self.mesh.indices.len() / 3
+        }
+
+        fn num_vertices_of_face(&self, _face: usize) -> usize {
+            3
+        }
+
+        fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.mesh.positions[self.vertex(face, vert)]
+        }
+
+        fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+            self.mesh.normals[self.vertex(face, vert)]
+        }
+
+        fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+            self.mesh.uvs[self.vertex(face, vert)]
+        }
+
+        fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+            self.corners[face * 3 + vert] = tangent;
+        }
+    }
+
+    let mut geometry = Geometry {
+        mesh,
+        corners: vec![[1.0, 0.0, 0.0, 1.0]; mesh.indices.len()],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        return false;
+    }
+
+    let corners = geometry.corners;
+    split_by_tangent(mesh, &corners);
+    true
+}
+
+// The first tangent a vertex gets stays with it, every other one gets its
+// own copy of the vertex. MikkTSpace hands out bit for bit the same tangent
+// to corners it welded, so exact comparison is enough
+fn split_by_tangent(mesh: &mut MeshData, corners: &[[f32; 4]]) {
+    let key = |tangent: [f32; 4]| tangent.map(f32::to_bits);
+
+    let mut tangents: Vec<Option<[f32; 4]>> = vec![None; mesh.positions.len()];
+    let mut copies: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+    let mut split = 0;
+    for (corner, &tangent) in corners.iter().enumerate() {
+        let v = mesh.indices[corner];
+        let index = match tangents[v as usize] {
+            None => {
+                tangents[v as usize] = Some(tangent);
+                v
+            }
+            Some(first) if key(first) == key(tangent) => v,
+            Some(_) => *copies.entry((v, key(tangent))).or_insert_with(|| {
+                tangents.push(Some(tangent));
+                split += 1;
+                duplicate_vertex(mesh, v)
+            }),
+        };
+        mesh.indices[corner] = index;
+    }
+
+    if split > 0 {
+        log::debug!("{}: split {} vertices on tangent seams", mesh.name, split);
+    }
+    // vertices no triangle uses never got one
+    mesh.tangents = tangents
+        .into_iter()
+        .map(|tangent| tangent.unwrap_or([1.0, 0.0, 0.0, 1.0]))
+        .collect();
+}
+
+// Appends a copy of vertex `v` to every attribute but the tangents, returns
+// the copy's index
+fn duplicate_vertex(mesh: &mut MeshData, v: u32) -> u32 {
+    // empty attributes stay empty
+    fn copy<T: Copy>(values: &mut Vec<T>, v: usize) {
+        if let Some(&value) = values.get(v) {
+            values.push(value);
+        }
+    }
+
+    let v = v as usize;
+    copy(&mut mesh.positions, v);
+    copy(&mut mesh.normals, v);
+    copy(&mut mesh.uvs, v);
+    for uvs in &mut mesh.extra_uvs {
+        copy(uvs, v);
+    }
+    copy(&mut mesh.joints, v);
+    copy(&mut mesh.weights, v);
+    for target in &mut mesh.targets {
+        copy(&mut target.positions, v);
+        copy(&mut target.normals, v);
+        copy(&mut target.tangents, v);
+    }
+    (mesh.positions.len() - 1) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use glam::Mat4;
+
+    fn mesh(positions: Vec<[f32; 3]>, uvs: Vec<[f32; 2]>, indices: Vec<u32>) -> MeshData {
+        MeshData {
+            name: String::from("test"),
+            normals: vec![[0.0, 0.0, 1.0]; positions.len()],
+            positions,
+            uvs,
+            extra_uvs: Vec::new(),
+            tangents: Vec::new(),
+            indices,
+            topology: Topology::Triangles,
+            material: None,
+            transform: Mat4::IDENTITY,
+            node: None,
+            skin: None,
+            joints: Vec::new(),
+            weights: Vec::new(),
+            targets: Vec::new(),
+            morph_weights: Vec::new(),
+        }
+    }
+
+    fn tangent(mesh: &MeshData, corner: usize) -> Vec3 {
+        let [x, y, z, _] = mesh.tangents[mesh.indices[corner] as usize];
+        Vec3::new(x, y, z)
+    }
+
+    #[test]
+    fn mirrored_uvs_split_the_shared_vertices() {
+        // two triangles sharing the edge 0-2, u runs along +x on the right
+        // one and along -x on the left one
+        let mut mesh = mesh(
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [-1.0, 0.0, 0.0],
+            ],
+            vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 0.0]],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        assert!(generate_tangents(&mut mesh));
+
+        assert_eq!(mesh.positions.len(), 6);
+        assert_eq!(mesh.normals.len(), 6);
+        assert_eq!(mesh.uvs.len(), 6);
+        assert_eq!(mesh.tangents.len(), 6);
+        for corner in 0..3 {
+            assert!(tangent(&mesh, corner).abs_diff_eq(Vec3::X, 1e-5));
+        }
+        for corner in 3..6 {
+            assert!(tangent(&mesh, corner).abs_diff_eq(-Vec3::X, 1e-5));
+        }
+        // the copies sit where the originals are
+        assert_eq!(mesh.positions[mesh.indices[3] as usize], [0.0, 0.0, 0.0]);
+        assert_eq!(mesh.positions[mesh.indices[4] as usize], [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn continuous_uvs_keep_the_vertices_shared() {
+        let mut mesh = mesh(
+            vec![
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.0],
+            ],
+            vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]],
+            vec![0, 1, 2, 0, 2, 3],
+        );
+        assert!(generate_tangents(&mut mesh));
+
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3]);
+        for corner in 0..6 {
+            assert!(tangent(&mesh, corner).abs_diff_eq(Vec3::X, 1e-5));
+        }
+    }
+
+    #[test]
+    fn split_normals_keep_joints_before_the_skin_is_known() {
+        // what the glTF importer hands over: joints but no skin yet
+        let mut mesh = mesh(
+            vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            vec![[0.0; 2]; 3],
+            vec![0, 1, 2],
+        );
+        mesh.joints = vec![[0, 1, 0, 0], [1, 0, 0, 0], [2, 0, 0, 0]];
+        mesh.weights = vec![
+            [0.5, 0.5, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0],
+        ];
+        generate_normals(&mut mesh, &[0]);
+
+        assert_eq!(mesh.joints, [[0, 1, 0, 0], [1, 0, 0, 0], [2, 0, 0, 0]]);
+        assert_eq!(mesh.weights.len(), 3);
+    }
+}
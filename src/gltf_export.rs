@@ -0,0 +1,576 @@
+// Writes a ModelData back out as glTF, .gltf with a .bin next to it or a
+// single .glb. One node per mesh and camera, with its world transform. Images
+// are copied next to the output and referenced by name, the importer
+// doesn't read embedded ones. Skins and animations aren't written
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glam::{Affine2, Mat3, Mat4};
+use serde_json::{Value, json};
+
+use crate::camera::Projection;
+use crate::model::{
+    Filter, MaterialData, MeshData, ModelData, SamplerData, TextureRef, Topology, Wrap,
+};
+
+const TRANSFORM: &str = "KHR_texture_transform";
+const BASISU: &str = "KHR_texture_basisu";
+const DDS: &str = "MSFT_texture_dds";
+
+const GLB_MAGIC: u32 = 0x4654_6c67; // "glTF"
+const GLB_JSON: u32 = 0x4e4f_534a; // "JSON"
+const GLB_BIN: u32 = 0x004e_4942; // "BIN\0"
+
+// Binary glTF when the extension is .glb, text otherwise
+pub fn write(model: &ModelData, path: &Path) -> io::Result<()> {
+    let glb = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("glb"));
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    let mut writer = Writer::new(dir);
+    let mut nodes = Vec::new();
+    let mut meshes = Vec::new();
+    for mesh in &model.meshes {
+        if mesh.positions.is_empty() {
+            log::warn!("{}: no vertices, left out", mesh.name);
+            continue;
+        }
+        nodes.push(with_matrix(json!({ "mesh": meshes.len() }), mesh.transform));
+        meshes.push(writer.mesh(mesh));
+    }
+
+    let mut cameras = Vec::new();
+    for (world, projection) in &model.cameras {
+        nodes.push(with_matrix(json!({ "camera": cameras.len() }), *world));
+        cameras.push(camera(projection));
+    }
+
+    let materials: Vec<Value> = model
+        .materials
+        .iter()
+        .map(|material| writer.material(material))
+        .collect();
+
+    let mut root = json!({
+        "asset": { "version": "2.0", "generator": "bs" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+    });
+    let mut set = |key: &str, items: Vec<Value>| {
+        if !items.is_empty() {
+            root[key] = Value::Array(items);
+        }
+    };
+    set("meshes", meshes);
+    set("cameras", cameras);
+    set("materials", materials);
+    set("textures", writer.textures);
+    set("images", writer.images);
+    set("samplers", writer.samplers);
+    set("accessors", writer.accessors);
+    set("bufferViews", writer.views);
+
+    for (key, mut names) in [
+        ("extensionsUsed", writer.extensions),
+        ("extensionsRequired", writer.required),
+    ] {
+        names.sort();
+        names.dedup();
+        if !names.is_empty() {
+            root[key] = json!(names);
+        }
+    }
+
+    let bin = writer.bin;
+    if glb {
+        if !bin.is_empty() {
+            root["buffers"] = json!([{ "byteLength": bin.len() }]);
+        }
+        let json = serde_json::to_vec(&root).map_err(io::Error::other)?;
+        fs::write(path, glb_bytes(json, bin))
+    } else {
+        if !bin.is_empty() {
+            let bin_path = path.with_extension("bin");
+            let uri = bin_path.file_name().unwrap_or_default().to_string_lossy();
+            root["buffers"] = json!([{ "uri": uri, "byteLength": bin.len() }]);
+            fs::write(&bin_path, &bin)?;
+        }
+        let mut text = serde_json::to_string_pretty(&root).map_err(io::Error::other)?;
+        text.push('\n');
+        fs::write(path, text)
+    }
+}
+
+// Everything that gets shared or indexed while writing
+struct Writer<'a> {
+    dir: &'a Path,
+    bin: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+    images: Vec<Value>,
+    samplers: Vec<Value>,
+    textures: Vec<Value>,
+    // source image -> index into `images`
+    image_indices: HashMap<PathBuf, usize>,
+    sampler_indices: HashMap<SamplerData, usize>,
+    texture_indices: HashMap<(usize, Option<usize>, usize), usize>,
+    extensions: Vec<&'static str>,
+    // the subset a reader can't do without
+    required: Vec<&'static str>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(dir: &'a Path) -> Self {
+        Self {
+            dir,
+            bin: Vec::new(),
+            views: Vec::new(),
+            accessors: Vec::new(),
+            images: Vec::new(),
+            samplers: Vec::new(),
+            textures: Vec::new(),
+            image_indices: HashMap::new(),
+            sampler_indices: HashMap::new(),
+            texture_indices: HashMap::new(),
+            extensions: Vec::new(),
+            required: Vec::new(),
+        }
+    }
+
+    fn mesh(&mut self, mesh: &MeshData) -> Value {
+        let mut attributes = json!({
+            "POSITION": self.floats(&mesh.positions, true),
+            "NORMAL": self.floats(&mesh.normals, false),
+            "TEXCOORD_0": self.floats(&mesh.uvs, false),
+            "TANGENT": self.floats(&mesh.tangents, false),
+        });
+        for (set, uvs) in mesh.extra_uvs.iter().enumerate() {
+            attributes[format!("TEXCOORD_{}", set + 1)] = json!(self.floats(uvs, false));
+        }
+
+        let mode = match mesh.topology {
+            Topology::Points => 0,
+            Topology::Lines => 1,
+            Topology::LineStrip => 3,
+            Topology::Triangles => 4,
+        };
+        let mut primitive = json!({
+            "attributes": attributes,
+            "indices": self.indices(&mesh.indices),
+            "mode": mode,
+        });
+        if let Some(material) = mesh.material {
+            primitive["material"] = json!(material);
+        }
+
+        // attributes a target doesn't move are left out of it
+        if !mesh.targets.is_empty() {
+            let targets: Vec<Value> = mesh
+                .targets
+                .iter()
+                .map(|target| {
+                    let mut attributes = json!({});
+                    if !target.positions.is_empty() {
+                        attributes["POSITION"] = json!(self.floats(&target.positions, true));
+                    }
+                    if !target.normals.is_empty() {
+                        attributes["NORMAL"] = json!(self.floats(&target.normals, false));
+                    }
+                    if !target.tangents.is_empty() {
+                        attributes["TANGENT"] = json!(self.floats(&target.tangents, false));
+                    }
+                    attributes
+                })
+                .collect();
+            primitive["targets"] = json!(targets);
+        }
+
+        let mut value = json!({ "name": mesh.name, "primitives": [primitive] });
+        if !mesh.morph_weights.is_empty() {
+            value["weights"] = json!(mesh.morph_weights);
+        }
+        value
+    }
+
+    fn material(&mut self, material: &MaterialData) -> Value {
+        let mut pbr = json!({ "baseColorFactor": material.base_color });
+        if let Some(texture) = &material.base_color_texture {
+            pbr["baseColorTexture"] = self.texture_info(texture);
+        }
+        if let Some(texture) = &material.metallic_roughness_texture {
+            pbr["metallicRoughnessTexture"] = self.texture_info(texture);
+        }
+        let mut value = json!({
+            "name": material.name,
+            "pbrMetallicRoughness": pbr,
+            "emissiveFactor": material.emissive,
+        });
+        if let Some(texture) = &material.emissive_texture {
+            value["emissiveTexture"] = self.texture_info(texture);
+        }
+        if let Some(texture) = &material.occlusion_texture {
+            let mut info = self.texture_info(texture);
+            info["strength"] = json!(material.occlusion_strength);
+            value["occlusionTexture"] = info;
+        }
+        if let Some(texture) = &material.normal_texture {
+            value["normalTexture"] = self.texture_info(texture);
+        }
+        value
+    }
+
+    fn texture_info(&mut self, texture: &TextureRef) -> Value {
+        let mut info = json!({
+            "index": self.texture(texture),
+            "texCoord": texture.tex_coord,
+        });
+        if texture.transform != Mat3::IDENTITY {
+            // the inverse of the importer's uv_transform
+            let (scale, angle, offset) =
+                Affine2::from_mat3(texture.transform).to_scale_angle_translation();
+            info["extensions"] = json!({
+                TRANSFORM: {
+                    "offset": offset.to_array(),
+                    "rotation": -angle,
+                    "scale": scale.to_array(),
+                }
+            });
+            self.extensions.push(TRANSFORM);
+        }
+        info
+    }
+
+    // A Basis KTX2 or DDS image keeps its extension, with the PNG/JPEG
+    // fallback as the plain source. Without one, a reader that doesn't know
+    // the extension has no image, so it becomes required. Any other KTX2 is
+    // a plain source, glTF has no extension for it
+    fn texture(&mut self, texture: &TextureRef) -> usize {
+        let compressed = match extension(&texture.path).as_deref() {
+            Some("ktx2") if is_basis(&texture.path) => Some(BASISU),
+            Some("dds") => Some(DDS),
+            _ => None,
+        };
+        let image = self.image(&texture.path);
+        let fallback = match compressed {
+            Some(_) => texture.fallback.as_deref().map(|path| self.image(path)),
+            None => None,
+        };
+        let sampler = self.sampler(texture.sampler);
+
+        let key = (image, fallback, sampler);
+        if let Some(&index) = self.texture_indices.get(&key) {
+            return index;
+        }
+        let value = match compressed {
+            Some(name) => {
+                self.extensions.push(name);
+                let mut value = json!({
+                    "sampler": sampler,
+                    "extensions": { name: { "source": image } },
+                });
+                match fallback {
+                    Some(fallback) => value["source"] = json!(fallback),
+                    None => self.required.push(name),
+                }
+                value
+            }
+            None => json!({ "sampler": sampler, "source": image }),
+        };
+        self.textures.push(value);
+        self.texture_indices.insert(key, self.textures.len() - 1);
+        self.textures.len() - 1
+    }
+
+    // Copied next to the output unless it's already there. Names that clash
+    // get the image index in front
+    fn image(&mut self, source: &Path) -> usize {
+        if let Some(&index) = self.image_indices.get(source) {
+            return index;
+        }
+        let index = self.images.len();
+
+        let file_name = source.file_name().unwrap_or_default().to_string_lossy();
+        let mut name = file_name.to_string();
+        let taken = |name: &str| self.images.iter().any(|image| image["uri"] == name);
+        if taken(&name) {
+            name = format!("{}_{}", index, file_name);
+        }
+
+        let target = self.dir.join(&name);
+        let same = fs::canonicalize(source).ok() == fs::canonicalize(&target).ok();
+        if !same && let Err(e) = fs::copy(source, &target) {
+            log::warn!("could not copy {}: {}", source.display(), e);
+        }
+
+        self.images.push(json!({ "uri": name }));
+        self.image_indices.insert(source.to_path_buf(), index);
+        index
+    }
+
+    fn sampler(&mut self, sampler: SamplerData) -> usize {
+        if let Some(&index) = self.sampler_indices.get(&sampler) {
+            return index;
+        }
+        let filter = |filter| match filter {
+            Filter::Nearest => 9728,
+            Filter::Linear => 9729,
+        };
+        let wrap = |wrap| match wrap {
+            Wrap::Repeat => 10497,
+            Wrap::MirroredRepeat => 33648,
+            Wrap::ClampToEdge => 33071,
+        };
+        let min_filter = match (sampler.min_filter, sampler.mip_filter) {
+            (min, None) => filter(min),
+            (Filter::Nearest, Some(Filter::Nearest)) => 9984,
+            (Filter::Linear, Some(Filter::Nearest)) => 9985,
+            (Filter::Nearest, Some(Filter::Linear)) => 9986,
+            (Filter::Linear, Some(Filter::Linear)) => 9987,
+        };
+        self.samplers.push(json!({
+            "magFilter": filter(sampler.mag_filter),
+            "minFilter": min_filter,
+            "wrapS": wrap(sampler.wrap_s),
+            "wrapT": wrap(sampler.wrap_t),
+        }));
+        self.sampler_indices
+            .insert(sampler, self.samplers.len() - 1);
+        self.samplers.len() - 1
+    }
+
+    // Float VEC2/3/4 accessor. POSITION needs its bounds
+    fn floats<const N: usize>(&mut self, data: &[[f32; N]], bounds: bool) -> usize {
+        let view = self.view(bytemuck::cast_slice(data.as_flattened()), 34962);
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": 5126,
+            "count": data.len(),
+            "type": format!("VEC{}", N),
+        });
+        if bounds {
+            let mut min = [f32::INFINITY; N];
+            let mut max = [f32::NEG_INFINITY; N];
+            for item in data {
+                for i in 0..N {
+                    min[i] = min[i].min(item[i]);
+                    max[i] = max[i].max(item[i]);
+                }
+            }
+            accessor["min"] = json!(min.as_slice());
+            accessor["max"] = json!(max.as_slice());
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn indices(&mut self, indices: &[u32]) -> usize {
+        let view = self.view(bytemuck::cast_slice(indices), 34963);
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": 5125,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    // 4 byte aligned, which is all floats and u32 need
+    fn view(&mut self, bytes: &[u8], target: u32) -> usize {
+        self.bin.resize(self.bin.len().next_multiple_of(4), 0);
+        self.views.push(json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": bytes.len(),
+            "target": target,
+        }));
+        self.bin.extend_from_slice(bytes);
+        self.views.len() - 1
+    }
+}
+
+fn with_matrix(mut node: Value, matrix: Mat4) -> Value {
+    if matrix != Mat4::IDENTITY {
+        node["matrix"] = json!(matrix.to_cols_array());
+    }
+    node
+}
+
+fn camera(projection: &Projection) -> Value {
+    match *projection {
+        Projection::Perspective { yfov, znear, zfar } => {
+            let mut perspective = json!({ "yfov": yfov, "znear": znear });
+            if let Some(zfar) = zfar {
+                perspective["zfar"] = json!(zfar);
+            }
+            json!({ "type": "perspective", "perspective": perspective })
+        }
+        Projection::Orthographic {
+            xmag,
+            ymag,
+            znear,
+            zfar,
+        } => json!({
+            "type": "orthographic",
+            "orthographic": { "xmag": xmag, "ymag": ymag, "znear": znear, "zfar": zfar },
+        }),
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+// KTX2 leaves the format undefined for Basis Universal data, see
+// texture::load_ktx2. Unreadable files count as plain ones
+fn is_basis(path: &Path) -> bool {
+    let Ok(bytes) = fs::read(path) else {
+        return false;
+    };
+    ktx2::Reader::new(bytes.as_slice()).is_ok_and(|reader| reader.header().format.is_none())
+}
+
+// 12 byte header, then the JSON and BIN chunks, each padded to 4 bytes
+fn glb_bytes(mut json: Vec<u8>, mut bin: Vec<u8>) -> Vec<u8> {
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let mut length = 12 + 8 + json.len();
+    if !bin.is_empty() {
+        length += 8 + bin.len();
+    }
+
+    let mut bytes = Vec::with_capacity(length);
+    for word in [GLB_MAGIC, 2, length as u32, json.len() as u32, GLB_JSON] {
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    bytes.extend_from_slice(&json);
+    if !bin.is_empty() {
+        bytes.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&GLB_BIN.to_le_bytes());
+        bytes.extend_from_slice(&bin);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::gltf_loader;
+    use crate::model::ColorSpace;
+
+    const BOX: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Box/glTF/Box.gltf");
+    const BASIS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Basis");
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bs-export-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn texture(path: PathBuf, fallback: Option<PathBuf>) -> TextureRef {
+        TextureRef {
+            path,
+            fallback,
+            tex_coord: 0,
+            transform: Mat3::IDENTITY,
+            sampler: SamplerData::default(),
+            color_space: ColorSpace::Srgb,
+        }
+    }
+
+    fn file_name(path: &Path) -> &str {
+        path.file_name().unwrap().to_str().unwrap()
+    }
+
+    #[test]
+    fn round_trips_the_box() {
+        let model = gltf_loader::load(Path::new(BOX)).unwrap();
+        let dir = scratch_dir("box");
+        for name in ["Box.gltf", "Box.glb"] {
+            let path = dir.join(name);
+            write(&model, &path).unwrap();
+            let loaded = gltf_loader::load(&path).unwrap();
+
+            assert_eq!(loaded.meshes.len(), model.meshes.len(), "{}", name);
+            for (a, b) in loaded.meshes.iter().zip(&model.meshes) {
+                assert_eq!(a.positions, b.positions, "{}", name);
+                assert_eq!(a.normals, b.normals, "{}", name);
+                assert_eq!(a.indices, b.indices, "{}", name);
+                assert_eq!(a.topology, b.topology, "{}", name);
+                assert_eq!(a.material, b.material, "{}", name);
+                assert_eq!(a.transform, b.transform, "{}", name);
+            }
+
+            assert_eq!(loaded.materials.len(), model.materials.len(), "{}", name);
+            for (a, b) in loaded.materials.iter().zip(&model.materials) {
+                assert_eq!(a.name, b.name, "{}", name);
+                assert_eq!(a.base_color, b.base_color, "{}", name);
+                assert_eq!(a.emissive, b.emissive, "{}", name);
+                assert_eq!(a.occlusion_strength, b.occlusion_strength, "{}", name);
+                assert_eq!(a.textures().count(), b.textures().count(), "{}", name);
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn basis_without_a_fallback_is_required() {
+        let mut model = gltf_loader::load(Path::new(BOX)).unwrap();
+        let basis = Path::new(BASIS).join("gradient_etc1s.ktx2");
+        let png = Path::new(BASIS).join("gradient.png");
+        let material = &mut model.materials[0];
+        material.base_color_texture = Some(texture(basis.clone(), None));
+        material.emissive_texture = Some(texture(basis, Some(png)));
+
+        let dir = scratch_dir("basis");
+        let path = dir.join("Box.gltf");
+        write(&model, &path).unwrap();
+        let root: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(root["extensionsUsed"], json!([BASISU]));
+        assert_eq!(root["extensionsRequired"], json!([BASISU]));
+        assert_eq!(root["textures"][0].get("source"), None);
+        assert_eq!(root["textures"][1]["source"], json!(1));
+
+        let loaded = gltf_loader::load(&path).unwrap();
+        let material = &loaded.materials[0];
+        let base_color = material.base_color_texture.as_ref().unwrap();
+        assert_eq!(file_name(&base_color.path), "gradient_etc1s.ktx2");
+        assert_eq!(base_color.fallback, None);
+        let emissive = material.emissive_texture.as_ref().unwrap();
+        assert_eq!(file_name(&emissive.path), "gradient_etc1s.ktx2");
+        assert_eq!(
+            emissive.fallback.as_deref().map(file_name),
+            Some("gradient.png")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_ktx2_is_a_plain_source() {
+        let dir = scratch_dir("ktx2");
+        // the Basis file with a format set, its data doesn't matter here
+        let mut bytes = fs::read(Path::new(BASIS).join("gradient_uastc.ktx2")).unwrap();
+        bytes[12..16].copy_from_slice(&37u32.to_le_bytes()); // R8G8B8A8_UNORM
+        let ktx2 = dir.join("rgba.ktx2");
+        fs::write(&ktx2, bytes).unwrap();
+
+        let mut model = gltf_loader::load(Path::new(BOX)).unwrap();
+        let png = Path::new(BASIS).join("gradient.png");
+        model.materials[0].base_color_texture = Some(texture(ktx2, Some(png)));
+
+        let path = dir.join("Box.gltf");
+        write(&model, &path).unwrap();
+        let root: Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(root.get("extensionsUsed"), None);
+        assert_eq!(root.get("extensionsRequired"), None);
+        assert_eq!(root["textures"], json!([{ "sampler": 0, "source": 0 }]));
+        assert_eq!(root["images"], json!([{ "uri": "rgba.ktx2" }]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
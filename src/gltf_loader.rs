@@ -0,0 +1,1201 @@
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3};
+use gltf::Semantic;
+use gltf::accessor::DataType;
+use gltf::accessor::sparse::IndexType;
+use gltf::json::Value;
+use gltf::json::extensions::texture::TextureTransform;
+
+use std::path::{Path, PathBuf};
+
+use crate::animation::{AnimationData, Channel, Interpolation, NodeData, Property, SkinData};
+use crate::camera::Projection;
+use crate::model::{
+    ColorSpace, Filter, LoadError, MaterialData, MeshData, ModelData, MorphTarget, SamplerData,
+    TextureRef, Topology, Wrap, name_from_path,
+};
+use crate::{draco, geometry, meshopt};
+
+// Handled here rather than by the gltf crate, which refuses to open files
+// that list them in extensionsRequired
+const DRACO: &str = "KHR_draco_mesh_compression";
+const MESHOPT: &str = "EXT_meshopt_compression";
+const QUANTIZATION: &str = "KHR_mesh_quantization";
+
+// Texture extensions that point at a compressed image, loaded by
+// crate::texture. Also taken out of extensionsRequired
+const BASISU: &str = "KHR_texture_basisu";
+const DDS: &str = "MSFT_texture_dds";
+
+// the crate reads it for us on every texture but occlusion and normal
+// ones, see load_material
+const TRANSFORM: &str = "KHR_texture_transform";
+
+pub fn load(path: &Path) -> Result<ModelData, LoadError> {
+    let (document, buffers) = import(path)?;
+    assert_eq!(buffers.len(), document.buffers().count());
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    let materials = document
+        .materials()
+        .map(|material| load_material(&material, &document, base_dir))
+        .collect();
+
+    let mut model = ModelData {
+        name: name_from_path(path),
+        meshes: Vec::new(),
+        materials,
+        cameras: Vec::new(),
+        nodes: load_nodes(&document),
+        skins: document
+            .skins()
+            .map(|skin| load_skin(&skin, &buffers))
+            .collect(),
+        animations: document
+            .animations()
+            .filter_map(|animation| load_animation(&animation, &buffers))
+            .collect(),
+    };
+
+    // Only what's reachable from the scene gets loaded, that's where the
+    // transforms come from
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            load_node(&node, Mat4::IDENTITY, &document, &buffers, &mut model)?;
+        }
+    }
+
+    pack_uv_sets(&mut model);
+    Ok(model)
+}
+
+// The vertex only has room for one uv set after the first. A material that
+// samples a later one (and not TEXCOORD_1) gets it swapped into that place,
+// in every mesh that uses the material
+fn pack_uv_sets(model: &mut ModelData) {
+    for (index, material) in model.materials.iter_mut().enumerate() {
+        let mut sets: Vec<usize> = material
+            .textures()
+            .map(|texture| texture.tex_coord)
+            .collect();
+        sets.sort();
+        sets.dedup();
+        let &[.., set] = sets.as_slice() else {
+            continue;
+        };
+        // more than one set past the first doesn't fit, the renderer warns
+        if set < 2 || sets.iter().filter(|&&set| set > 0).count() > 1 {
+            continue;
+        }
+
+        for texture in material.texture_slots_mut().into_iter().flatten() {
+            if texture.tex_coord == set {
+                texture.tex_coord = 1;
+            }
+        }
+        for mesh in &mut model.meshes {
+            if mesh.material == Some(index) && set <= mesh.extra_uvs.len() {
+                mesh.extra_uvs.swap(0, set - 1);
+            }
+        }
+    }
+}
+
+// gltf::import, minus the check for required extensions we handle ourselves.
+// Images aren't decoded here, the renderer loads them from their paths
+fn import(path: &Path) -> Result<(gltf::Document, Vec<gltf::buffer::Data>), LoadError> {
+    let base = path.parent().unwrap_or(Path::new("."));
+    let bytes = std::fs::read(path).map_err(gltf::Error::Io)?;
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice_without_validation(&bytes)?;
+
+    let mut json = document.into_json();
+    json.extensions_required
+        .retain(|name| ![DRACO, MESHOPT, QUANTIZATION, BASISU, DDS].contains(&name.as_str()));
+    let document = validate(json)?;
+
+    let buffers = import_buffers(&document, base, blob)?;
+    Ok((document, buffers))
+}
+
+// Accessors of Draco primitives have no bufferView, their data only exists
+// once decoded. A primitive may also leave out POSITION, which the crate
+// calls missing, load_node skips those. Everything else still has to pass
+fn validate(json: gltf::json::Root) -> Result<gltf::Document, LoadError> {
+    let draco_views: Vec<String> = json
+        .meshes
+        .iter()
+        .flat_map(|mesh| &mesh.primitives)
+        .filter(|primitive| {
+            primitive
+                .extensions
+                .as_ref()
+                .is_some_and(|extensions| extensions.others.contains_key(DRACO))
+        })
+        .flat_map(|primitive| primitive.attributes.values().copied().chain(primitive.indices))
+        .map(|accessor| format!("accessors[{}].bufferView", accessor.value()))
+        .collect();
+
+    match gltf::Document::from_json(json.clone()) {
+        Ok(document) => Ok(document),
+        Err(gltf::Error::Validation(errors)) => {
+            let errors: Vec<_> = errors
+                .into_iter()
+                .filter(|(path, error)| {
+                    !(matches!(error, gltf::json::validation::Error::Missing)
+                        && (draco_views.contains(&path.0)
+                            || path.0.ends_with(".attributes[\"POSITION\"]")))
+                })
+                .collect();
+            if !errors.is_empty() {
+                return Err(gltf::Error::Validation(errors).into());
+            }
+            Ok(gltf::Document::from_json_without_validation(json))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Meshopt fallback buffers have no data of their own, they only reserve
+// space for the views decoded into them
+fn import_buffers(
+    document: &gltf::Document,
+    base: &Path,
+    mut blob: Option<Vec<u8>>,
+) -> Result<Vec<gltf::buffer::Data>, LoadError> {
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let fallback = buffer
+            .extension_value(MESHOPT)
+            .is_some_and(|extension| extension["fallback"].as_bool() == Some(true));
+        let data = if fallback {
+            gltf::buffer::Data(vec![0; buffer.length()])
+        } else {
+            gltf::buffer::Data::from_source_and_blob(buffer.source(), Some(base), &mut blob)?
+        };
+        if data.len() < buffer.length() {
+            return Err(gltf::Error::BufferLength {
+                buffer: buffer.index(),
+                expected: buffer.length(),
+                actual: data.len(),
+            }
+            .into());
+        }
+        buffers.push(data);
+    }
+
+    for view in document.views() {
+        if let Some(extension) = view.extension_value(MESHOPT) {
+            decode_meshopt(&view, extension, &mut buffers)?;
+        }
+    }
+
+    Ok(buffers)
+}
+
+// The compressed bytes live in another buffer, the view itself describes
+// where the decoded data goes so accessors read it like any other view
+fn decode_meshopt(
+    view: &gltf::buffer::View,
+    extension: &gltf::json::Value,
+    buffers: &mut [gltf::buffer::Data],
+) -> Result<(), LoadError> {
+    let error = |what: &str| LoadError::Meshopt(format!("bufferView {}: {}", view.index(), what));
+    let field = |name: &str| extension[name].as_u64().map(|v| v as usize);
+
+    let mode = match extension["mode"].as_str() {
+        Some("ATTRIBUTES") => meshopt::Mode::Attributes,
+        Some("TRIANGLES") => meshopt::Mode::Triangles,
+        Some("INDICES") => meshopt::Mode::Indices,
+        _ => return Err(error("unknown mode")),
+    };
+    let filter = match extension["filter"].as_str() {
+        None | Some("NONE") => meshopt::Filter::None,
+        Some("OCTAHEDRAL") => meshopt::Filter::Octahedral,
+        Some("QUATERNION") => meshopt::Filter::Quaternion,
+        Some("EXPONENTIAL") => meshopt::Filter::Exponential,
+        Some(other) => return Err(error(&format!("unknown filter {}", other))),
+    };
+    let (Some(buffer), Some(length), Some(stride), Some(count)) = (
+        field("buffer"),
+        field("byteLength"),
+        field("byteStride"),
+        field("count"),
+    ) else {
+        return Err(error("incomplete extension"));
+    };
+    let offset = field("byteOffset").unwrap_or(0);
+
+    let compressed = buffers
+        .get(buffer)
+        .and_then(|data| data.get(offset..offset + length))
+        .ok_or_else(|| error("compressed data out of range"))?;
+    let decoded =
+        meshopt::decode(compressed, count, stride, mode, filter).map_err(|e| error(&e))?;
+
+    let target = &mut buffers[view.buffer().index()];
+    let range = view.offset()..view.offset() + decoded.len();
+    if decoded.len() > view.length() || range.end > target.len() {
+        return Err(error("decoded data doesn't fit the bufferView"));
+    }
+    target.0[range].copy_from_slice(&decoded);
+
+    log::info!(
+        "decoded meshopt bufferView {} ({:?}, {} x {} bytes)",
+        view.index(),
+        mode,
+        count,
+        stride
+    );
+    Ok(())
+}
+
+fn load_node(
+    node: &gltf::Node,
+    parent: Mat4,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    model: &mut ModelData,
+) -> Result<(), LoadError> {
+    let world = parent * Mat4::from_cols_array_2d(&node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            // glTF allows it, for extensions that supply the positions
+            if primitive.get(&Semantic::Positions).is_none() {
+                log::warn!(
+                    "{}: primitive {} has no POSITION, left out",
+                    mesh.name().unwrap_or("unnamed"),
+                    primitive.index()
+                );
+                continue;
+            }
+            let mut data = load_primitive(&mesh, &primitive, world, document, buffers)?;
+            data.node = Some(node.index());
+            if let Some(weights) = node.weights() {
+                data.morph_weights = weights.to_vec();
+            }
+
+            // skinned vertices end up in model space through the joints, the
+            // mesh node's own transform doesn't apply
+            if let Some(skin) = node.skin() {
+                if data.joints.is_empty() || data.weights.is_empty() {
+                    log::warn!("{}: skinned but has no JOINTS_0/WEIGHTS_0", data.name);
+                } else {
+                    data.skin = Some(skin.index());
+                    data.transform = Mat4::IDENTITY;
+                }
+            }
+
+            model.meshes.push(data);
+        }
+    }
+
+    if let Some(camera) = node.camera() {
+        let projection = match camera.projection() {
+            gltf::camera::Projection::Perspective(p) => Projection::Perspective {
+                yfov: p.yfov(),
+                znear: p.znear(),
+                zfar: p.zfar(),
+            },
+            gltf::camera::Projection::Orthographic(o) => Projection::Orthographic {
+                xmag: o.xmag(),
+                ymag: o.ymag(),
+                znear: o.znear(),
+                zfar: o.zfar(),
+            },
+        };
+
+        log::info!(
+            "imported camera {} ({:?}) from node {:?}",
+            model.cameras.len(),
+            camera.name().unwrap_or("unnamed"),
+            node.name().unwrap_or("unnamed"),
+        );
+
+        model.cameras.push((world, projection));
+    }
+
+    for child in node.children() {
+        load_node(&child, world, document, buffers, model)?;
+    }
+
+    Ok(())
+}
+
+// What a primitive stores per vertex, read through its accessors or
+// decoded from a Draco buffer
+struct Attributes {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    extra_uvs: Vec<Vec<[f32; 2]>>,
+    tangents: Vec<[f32; 4]>,
+    // None when the primitive isn't indexed
+    indices: Option<Vec<u32>>,
+    joints: Vec<[u16; 4]>,
+    weights: Vec<[f32; 4]>,
+    targets: Vec<MorphTarget>,
+}
+
+fn load_primitive(
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    transform: Mat4,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Result<MeshData, LoadError> {
+    let name = mesh.name().unwrap_or("unnamed");
+
+    let Attributes {
+        positions,
+        normals,
+        uvs,
+        extra_uvs,
+        tangents,
+        indices,
+        joints,
+        weights,
+        targets,
+    } = match primitive.extension_value(DRACO) {
+        Some(extension) => decode_draco(name, primitive, extension, document, buffers)?,
+        None => read_attributes(primitive, buffers)?,
+    };
+
+    let indices = indices.unwrap_or_else(|| {
+        log::info!("{}: generated indices", name);
+        (0..positions.len() as u32).collect()
+    });
+    let (topology, indices) = convert_mode(primitive.mode(), indices);
+
+    // the node can override these, see load_node
+    let morph_weights = mesh
+        .weights()
+        .map(|weights| weights.to_vec())
+        .unwrap_or_else(|| vec![0.0; targets.len()]);
+
+    let mut data = MeshData {
+        name: name.to_string(),
+        positions,
+        normals,
+        uvs,
+        extra_uvs,
+        tangents,
+        indices,
+        topology,
+        material: primitive.material().index(),
+        transform,
+        node: None,
+        skin: None,
+        joints,
+        weights,
+        targets,
+        morph_weights,
+    };
+    geometry::fill_missing(&mut data);
+    Ok(data)
+}
+
+fn read_attributes(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Attributes, LoadError> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    // load_node leaves out primitives without POSITION, anything else
+    // that's missing is left empty and generated later
+    // read by hand, the reader assumes float positions, normals and
+    // tangents and can't do signed tex coords
+    let attribute = |semantic| primitive.get(&semantic);
+    let positions: Vec<[f32; 3]> = attribute(Semantic::Positions)
+        .map(|accessor| read_floats(&accessor, buffers))
+        .transpose()?
+        .unwrap_or_default();
+    let normals: Vec<[f32; 3]> = attribute(Semantic::Normals)
+        .map(|accessor| read_floats(&accessor, buffers))
+        .transpose()?
+        .unwrap_or_default();
+    let uvs: Vec<[f32; 2]> = attribute(Semantic::TexCoords(0))
+        .map(|accessor| read_floats(&accessor, buffers))
+        .transpose()?
+        .unwrap_or_default();
+    let extra_uvs: Vec<Vec<[f32; 2]>> = (1..)
+        .map_while(|set| attribute(Semantic::TexCoords(set)))
+        .map(|accessor| read_floats(&accessor, buffers))
+        .collect::<Result<_, _>>()?;
+    let tangents: Vec<[f32; 4]> = attribute(Semantic::Tangents)
+        .map(|accessor| read_floats(&accessor, buffers))
+        .transpose()?
+        .unwrap_or_default();
+
+    let indices: Option<Vec<u32>> = reader
+        .read_indices()
+        .map(|indices| indices.into_u32().collect());
+
+    let joints: Vec<[u16; 4]> = reader
+        .read_joints(0)
+        .map(|joints| joints.into_u16().collect())
+        .unwrap_or_default();
+
+    let weights: Vec<[f32; 4]> = reader
+        .read_weights(0)
+        .map(|weights| weights.into_f32().collect())
+        .unwrap_or_default();
+
+    let targets: Vec<MorphTarget> = primitive
+        .morph_targets()
+        .map(|target| {
+            Ok(MorphTarget {
+                positions: target
+                    .positions()
+                    .map(|p| read_floats(&p, buffers))
+                    .transpose()?
+                    .unwrap_or_default(),
+                normals: target
+                    .normals()
+                    .map(|n| read_floats(&n, buffers))
+                    .transpose()?
+                    .unwrap_or_default(),
+                tangents: target
+                    .tangents()
+                    .map(|t| read_floats(&t, buffers))
+                    .transpose()?
+                    .unwrap_or_default(),
+            })
+        })
+        .collect::<Result<_, LoadError>>()?;
+
+    Ok(Attributes {
+        positions,
+        normals,
+        uvs,
+        extra_uvs,
+        tangents,
+        indices,
+        joints,
+        weights,
+        targets,
+    })
+}
+
+// Any component type, so KHR_mesh_quantization's integer attributes come
+// out as floats. Normalized ones are mapped to [0, 1] or [-1, 1], the rest
+// keep their integer values and rely on the node transform to scale them.
+// Reading past the end of a view is an error
+fn read_floats<const N: usize>(
+    accessor: &gltf::Accessor,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Vec<[f32; N]>, LoadError> {
+    let out_of_bounds = || -> LoadError {
+        let path = gltf::json::Path::new()
+            .field("accessors")
+            .index(accessor.index());
+        let error = gltf::json::validation::Error::IndexOutOfBounds;
+        gltf::Error::Validation(vec![(path, error)]).into()
+    };
+    let size = accessor.data_type().size();
+    let read = |bytes: &[u8]| -> f32 {
+        let normalized = accessor.normalized();
+        match accessor.data_type() {
+            DataType::I8 if normalized => (bytes[0] as i8 as f32 / 127.0).max(-1.0),
+            DataType::I8 => bytes[0] as i8 as f32,
+            DataType::U8 if normalized => bytes[0] as f32 / 255.0,
+            DataType::U8 => bytes[0] as f32,
+            DataType::I16 => {
+                let v = i16::from_le_bytes([bytes[0], bytes[1]]) as f32;
+                if normalized {
+                    (v / 32767.0).max(-1.0)
+                } else {
+                    v
+                }
+            }
+            DataType::U16 => {
+                let v = u16::from_le_bytes([bytes[0], bytes[1]]) as f32;
+                if normalized {
+                    v / 65535.0
+                } else {
+                    v
+                }
+            }
+            DataType::U32 => u32::from_le_bytes(bytes[..4].try_into().unwrap()) as f32,
+            DataType::F32 => f32::from_le_bytes(bytes[..4].try_into().unwrap()),
+        }
+    };
+    let element = |data: &[u8], offset: usize| -> Option<[f32; N]> {
+        let bytes = data.get(offset..offset + size * N)?;
+        Some(std::array::from_fn(|i| read(&bytes[i * size..])))
+    };
+    let view_data = |view: &gltf::buffer::View, offset: usize| {
+        let data = &buffers[view.buffer().index()];
+        data.get(view.offset()..view.offset() + view.length())?
+            .get(offset..)
+    };
+
+    // no view means all zeros, only sparse values are set
+    let mut values = match accessor.view() {
+        Some(view) => {
+            let data = view_data(&view, accessor.offset()).ok_or_else(out_of_bounds)?;
+            let stride = view.stride().unwrap_or(accessor.size());
+            (0..accessor.count())
+                .map(|i| element(data, i * stride))
+                .collect::<Option<_>>()
+                .ok_or_else(out_of_bounds)?
+        }
+        None => vec![[0.0; N]; accessor.count()],
+    };
+
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_data = view_data(&indices.view(), indices.offset()).ok_or_else(out_of_bounds)?;
+        let values_view = sparse.values();
+        let value_data =
+            view_data(&values_view.view(), values_view.offset()).ok_or_else(out_of_bounds)?;
+        for i in 0..sparse.count() {
+            let index = match indices.index_type() {
+                IndexType::U8 => index_data.get(i).map(|&index| index as usize),
+                IndexType::U16 => index_data
+                    .get(i * 2..i * 2 + 2)
+                    .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize),
+                IndexType::U32 => index_data
+                    .get(i * 4..i * 4 + 4)
+                    .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()) as usize),
+            };
+            let value = element(value_data, i * size * N);
+            let (Some(index), Some(value)) = (index, value) else {
+                return Err(out_of_bounds());
+            };
+            if let Some(slot) = values.get_mut(index) {
+                *slot = value;
+            }
+        }
+    }
+
+    Ok(values)
+}
+
+// The extension points at one bufferView holding the whole compressed
+// primitive and maps each attribute to an id inside it. The accessors only
+// describe the decoded result, they have no data of their own
+fn decode_draco(
+    name: &str,
+    primitive: &gltf::Primitive,
+    extension: &gltf::json::Value,
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Attributes, LoadError> {
+    let error = |what: &str| LoadError::Draco(format!("{}: {}", name, what));
+
+    let view = extension["bufferView"]
+        .as_u64()
+        .and_then(|index| document.views().nth(index as usize))
+        .ok_or_else(|| error("missing bufferView"))?;
+    let bytes = buffers[view.buffer().index()]
+        .get(view.offset()..view.offset() + view.length())
+        .ok_or_else(|| error("bufferView out of range"))?;
+    let decoded = draco::decode(bytes).map_err(|e| error(&e))?;
+
+    let ids = &extension["attributes"];
+    let positions = draco_attribute::<3>(&decoded, ids, "POSITION");
+    if positions.is_empty() {
+        return Err(error("no positions"));
+    }
+
+    // Draco reorders vertices, uncompressed targets wouldn't line up
+    if primitive.morph_targets().next().is_some() {
+        log::warn!("{}: morph targets on Draco primitives aren't supported, dropped", name);
+    }
+
+    log::info!(
+        "{}: decoded Draco primitive ({} points, {} triangles)",
+        name,
+        decoded.num_points,
+        decoded.indices.len() / 3
+    );
+
+    Ok(Attributes {
+        positions,
+        normals: draco_attribute(&decoded, ids, "NORMAL"),
+        uvs: draco_attribute(&decoded, ids, "TEXCOORD_0"),
+        extra_uvs: (1..)
+            .map_while(|set| {
+                let semantic = format!("TEXCOORD_{}", set);
+                ids.get(&semantic)
+                    .map(|_| draco_attribute(&decoded, ids, &semantic))
+            })
+            .collect(),
+        tangents: draco_attribute(&decoded, ids, "TANGENT"),
+        joints: draco_attribute::<4>(&decoded, ids, "JOINTS_0")
+            .into_iter()
+            .map(|joints| joints.map(|j| j as u16))
+            .collect(),
+        weights: draco_attribute(&decoded, ids, "WEIGHTS_0"),
+        indices: Some(decoded.indices),
+        targets: Vec::new(),
+    })
+}
+
+// Empty when the primitive doesn't have the attribute, same as the reader
+fn draco_attribute<const N: usize>(
+    decoded: &draco::Mesh,
+    ids: &gltf::json::Value,
+    semantic: &str,
+) -> Vec<[f32; N]> {
+    let Some(id) = ids[semantic].as_u64() else {
+        return Vec::new();
+    };
+    match decoded.attribute(id as u32) {
+        Some(attribute) if attribute.components == N => attribute
+            .values
+            .chunks_exact(N)
+            .map(|value| value.try_into().unwrap())
+            .collect(),
+        _ => {
+            log::warn!("Draco attribute {} is missing or has the wrong size", semantic);
+            Vec::new()
+        }
+    }
+}
+
+fn load_nodes(document: &gltf::Document) -> Vec<NodeData> {
+    let mut nodes: Vec<NodeData> = document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            NodeData {
+                name: node.name().unwrap_or("unnamed").to_string(),
+                parent: None,
+                children: node.children().map(|child| child.index()).collect(),
+                translation: Vec3::from(translation),
+                rotation: Quat::from_array(rotation),
+                scale: Vec3::from(scale),
+            }
+        })
+        .collect();
+
+    for i in 0..nodes.len() {
+        for child in nodes[i].children.clone() {
+            nodes[child].parent = Some(i);
+        }
+    }
+
+    nodes
+}
+
+fn load_skin(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> SkinData {
+    let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+
+    // missing inverse bind matrices means they're all identity
+    let inverse_bind_matrices = skin
+        .reader(|buffer| Some(&buffers[buffer.index()]))
+        .read_inverse_bind_matrices()
+        .map(|matrices| matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect())
+        .unwrap_or_else(|| vec![Mat4::IDENTITY; joints.len()]);
+
+    SkinData {
+        name: skin.name().unwrap_or("unnamed").to_string(),
+        joints,
+        inverse_bind_matrices,
+    }
+}
+
+fn load_animation(
+    animation: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+) -> Option<AnimationData> {
+    use gltf::animation::util::ReadOutputs;
+
+    let name = animation.name().unwrap_or("unnamed").to_string();
+    let mut channels = Vec::new();
+
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            log::warn!("animation {:?}: channel without keyframes, skipping", name);
+            continue;
+        };
+
+        let (property, values): (Property, Vec<f32>) = match outputs {
+            ReadOutputs::Translations(t) => (Property::Translation, t.flatten().collect()),
+            ReadOutputs::Rotations(r) => (Property::Rotation, r.into_f32().flatten().collect()),
+            ReadOutputs::Scales(s) => (Property::Scale, s.flatten().collect()),
+            ReadOutputs::MorphTargetWeights(w) => (Property::Weights, w.into_f32().collect()),
+        };
+
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+
+        let times: Vec<f32> = inputs.collect();
+        if times.is_empty() {
+            continue;
+        }
+
+        let values_per_key = match interpolation {
+            Interpolation::CubicSpline => times.len() * 3,
+            _ => times.len(),
+        };
+        let components = values.len() / values_per_key;
+        if components == 0 || values.len() != components * values_per_key {
+            log::warn!("animation {:?}: keyframe count mismatch, skipping channel", name);
+            continue;
+        }
+
+        channels.push(Channel {
+            node: channel.target().node().index(),
+            property,
+            interpolation,
+            times,
+            values,
+            components,
+        });
+    }
+
+    if channels.is_empty() {
+        return None;
+    }
+
+    let duration = channels
+        .iter()
+        .map(|channel| *channel.times.last().unwrap())
+        .fold(0.0, f32::max);
+
+    log::info!(
+        "imported animation {:?}: {} channels, {:.2}s",
+        name,
+        channels.len(),
+        duration
+    );
+
+    Some(AnimationData {
+        name,
+        channels,
+        duration,
+    })
+}
+
+// Loops become closed strips, triangle strips and fans become plain lists so
+// normal and tangent generation only has to deal with one layout
+fn convert_mode(mode: gltf::mesh::Mode, indices: Vec<u32>) -> (Topology, Vec<u32>) {
+    use gltf::mesh::Mode;
+
+    match mode {
+        Mode::Points => (Topology::Points, indices),
+        Mode::Lines => (Topology::Lines, indices),
+        Mode::LineStrip => (Topology::LineStrip, indices),
+        Mode::LineLoop => {
+            let mut indices = indices;
+            if let Some(&first) = indices.first() {
+                indices.push(first);
+            }
+            (Topology::LineStrip, indices)
+        }
+        Mode::Triangles => (Topology::Triangles, indices),
+        Mode::TriangleStrip => {
+            let mut list = Vec::with_capacity(indices.len().saturating_sub(2) * 3);
+            for (i, w) in indices.windows(3).enumerate() {
+                // every other triangle is wound the other way round
+                if i % 2 == 0 {
+                    list.extend_from_slice(&[w[0], w[1], w[2]]);
+                } else {
+                    list.extend_from_slice(&[w[1], w[0], w[2]]);
+                }
+            }
+            (Topology::Triangles, list)
+        }
+        Mode::TriangleFan => {
+            let mut list = Vec::with_capacity(indices.len().saturating_sub(2) * 3);
+            for w in indices.get(1..).unwrap_or_default().windows(2) {
+                list.extend_from_slice(&[indices[0], w[0], w[1]]);
+            }
+            (Topology::Triangles, list)
+        }
+    }
+}
+
+fn load_material(
+    material: &gltf::Material,
+    document: &gltf::Document,
+    base_dir: &Path,
+) -> MaterialData {
+    let pbr = material.pbr_metallic_roughness();
+
+    let info_ref = |info: gltf::texture::Info, color_space| {
+        // the transform can also switch the uv set
+        let (tex_coord, transform) = match info.texture_transform() {
+            Some(transform) => (
+                transform.tex_coord().unwrap_or(info.tex_coord()),
+                uv_transform(transform.offset(), transform.rotation(), transform.scale()),
+            ),
+            None => (info.tex_coord(), Mat3::IDENTITY),
+        };
+        texture_ref(&info.texture(), tex_coord, transform, color_space, document, base_dir)
+    };
+
+    // the crate doesn't expose KHR_texture_transform on occlusion and
+    // normal textures, it's read from their extensions as is
+    let linear_ref = |texture: gltf::Texture, tex_coord: u32, extension: Option<&Value>| {
+        let transform = extension
+            .and_then(|value| serde_json::from_value::<TextureTransform>(value.clone()).ok());
+        let (tex_coord, transform) = match transform {
+            Some(transform) => (
+                transform.tex_coord.unwrap_or(tex_coord),
+                uv_transform(transform.offset.0, transform.rotation.0, transform.scale.0),
+            ),
+            None => (tex_coord, Mat3::IDENTITY),
+        };
+        texture_ref(
+            &texture,
+            tex_coord,
+            transform,
+            ColorSpace::Linear,
+            document,
+            base_dir,
+        )
+    };
+    let occlusion = material.occlusion_texture();
+    let occlusion_texture = occlusion.as_ref().and_then(|occlusion| {
+        linear_ref(
+            occlusion.texture(),
+            occlusion.tex_coord(),
+            occlusion.extension_value(TRANSFORM),
+        )
+    });
+    let normal_texture = material.normal_texture().and_then(|normal| {
+        linear_ref(
+            normal.texture(),
+            normal.tex_coord(),
+            normal.extension_value(TRANSFORM),
+        )
+    });
+
+    MaterialData {
+        name: material.name().unwrap_or("unnamed").to_string(),
+        base_color: pbr.base_color_factor(),
+        base_color_texture: pbr
+            .base_color_texture()
+            .and_then(|info| info_ref(info, ColorSpace::Srgb)),
+        emissive: material.emissive_factor(),
+        emissive_texture: material
+            .emissive_texture()
+            .and_then(|info| info_ref(info, ColorSpace::Srgb)),
+        occlusion_texture,
+        occlusion_strength: occlusion.map_or(1.0, |occlusion| occlusion.strength()),
+        normal_texture,
+        metallic_roughness_texture: pbr
+            .metallic_roughness_texture()
+            .and_then(|info| info_ref(info, ColorSpace::Linear)),
+    }
+}
+
+fn texture_ref(
+    texture: &gltf::Texture,
+    tex_coord: u32,
+    transform: Mat3,
+    color_space: ColorSpace,
+    document: &gltf::Document,
+    base_dir: &Path,
+) -> Option<TextureRef> {
+    // with KHR_texture_basisu or MSFT_texture_dds, `source` is an optional
+    // PNG/JPEG for viewers that can't read the compressed image
+    let compressed = [BASISU, DDS].into_iter().find_map(|name| {
+        let index = texture.extension_value(name)?.get("source")?.as_u64()?;
+        document.images().nth(index as usize)
+    });
+    let mut paths = compressed
+        .into_iter()
+        .chain(texture.source())
+        .filter_map(|image| image_path(&image, base_dir));
+
+    Some(TextureRef {
+        path: paths.next()?,
+        fallback: paths.next(),
+        tex_coord: tex_coord as usize,
+        transform,
+        sampler: load_sampler(&texture.sampler()),
+        color_space,
+    })
+}
+
+fn image_path(image: &gltf::Image, base_dir: &Path) -> Option<PathBuf> {
+    match image.source() {
+        gltf::image::Source::Uri { uri, .. } => Some(base_dir.join(uri)),
+        // TODO: embedded images (.glb, data URIs)
+        gltf::image::Source::View { .. } => {
+            log::warn!("embedded textures are not supported yet, skipping");
+            None
+        }
+    }
+}
+
+// offset * rotation * scale. The extension's rotation goes the opposite
+// way to glam's
+fn uv_transform(offset: [f32; 2], rotation: f32, scale: [f32; 2]) -> Mat3 {
+    Mat3::from_scale_angle_translation(Vec2::from(scale), -rotation, Vec2::from(offset))
+}
+
+// Filters the file leaves out get the defaults, glTF lets us pick
+fn load_sampler(sampler: &gltf::texture::Sampler) -> SamplerData {
+    use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+    let wrap = |mode| match mode {
+        WrappingMode::Repeat => Wrap::Repeat,
+        WrappingMode::MirroredRepeat => Wrap::MirroredRepeat,
+        WrappingMode::ClampToEdge => Wrap::ClampToEdge,
+    };
+
+    let default = SamplerData::default();
+    let (min_filter, mip_filter) = match sampler.min_filter() {
+        None => (default.min_filter, default.mip_filter),
+        Some(MinFilter::Nearest) => (Filter::Nearest, None),
+        Some(MinFilter::Linear) => (Filter::Linear, None),
+        Some(MinFilter::NearestMipmapNearest) => (Filter::Nearest, Some(Filter::Nearest)),
+        Some(MinFilter::LinearMipmapNearest) => (Filter::Linear, Some(Filter::Nearest)),
+        Some(MinFilter::NearestMipmapLinear) => (Filter::Nearest, Some(Filter::Linear)),
+        Some(MinFilter::LinearMipmapLinear) => (Filter::Linear, Some(Filter::Linear)),
+    };
+
+    SamplerData {
+        wrap_s: wrap(sampler.wrap_s()),
+        wrap_t: wrap(sampler.wrap_t()),
+        mag_filter: match sampler.mag_filter() {
+            None => default.mag_filter,
+            Some(MagFilter::Nearest) => Filter::Nearest,
+            Some(MagFilter::Linear) => Filter::Linear,
+        },
+        min_filter,
+        mip_filter,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use gltf::mesh::Mode;
+    use serde_json::{Value, json};
+
+    // one triangle in the xy plane
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    // A .gltf and its .bin in a scratch directory
+    fn write_gltf(name: &str, mut gltf: Value, bin: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bs-gltf-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("model.bin"), bin).unwrap();
+        gltf["asset"] = json!({ "version": "2.0" });
+        gltf["buffers"] = json!([{ "uri": "model.bin", "byteLength": bin.len() }]);
+        let path = dir.join("model.gltf");
+        std::fs::write(&path, gltf.to_string()).unwrap();
+        path
+    }
+
+    // A scene with a single node holding a single mesh
+    fn mesh_gltf(primitives: Value, accessors: Value, views: Value) -> Value {
+        json!({
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{ "name": "mesh", "primitives": primitives }],
+            "accessors": accessors,
+            "bufferViews": views,
+        })
+    }
+
+    // float VEC3s from the start of view 0
+    fn vec3(count: usize) -> Value {
+        json!({ "bufferView": 0, "componentType": 5126, "count": count, "type": "VEC3" })
+    }
+
+    // POSITION needs its bounds
+    fn positions(count: usize) -> Value {
+        let mut accessor = vec3(count);
+        accessor["min"] = json!([0, 0, 0]);
+        accessor["max"] = json!([1, 1, 0]);
+        accessor
+    }
+
+    #[test]
+    fn strips_wind_every_triangle_the_same_way() {
+        // a zig-zag along x, 5 corners make an odd number of triangles
+        let corners: Vec<Vec3> = (0..5)
+            .map(|i| Vec3::new(i as f32, (i % 2) as f32, 0.0))
+            .collect();
+        for count in [5, 4] {
+            let strip: Vec<u32> = (0..count).collect();
+            let (topology, list) = convert_mode(Mode::TriangleStrip, strip);
+            assert_eq!(topology, Topology::Triangles);
+            assert_eq!(list.len(), (count as usize - 2) * 3);
+            for triangle in list.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| corners[triangle[i] as usize]);
+                assert!((b - a).cross(c - a).z < 0.0, "{:?}", triangle);
+            }
+        }
+        assert_eq!(
+            convert_mode(Mode::TriangleStrip, vec![0, 1, 2, 3, 4]).1,
+            [0, 1, 2, 2, 1, 3, 2, 3, 4]
+        );
+        assert!(convert_mode(Mode::TriangleStrip, vec![0, 1]).1.is_empty());
+    }
+
+    #[test]
+    fn fans_share_their_first_corner() {
+        let (topology, list) = convert_mode(Mode::TriangleFan, vec![7, 1, 2, 3, 4]);
+        assert_eq!(topology, Topology::Triangles);
+        assert_eq!(list, [7, 1, 2, 7, 2, 3, 7, 3, 4]);
+        assert!(convert_mode(Mode::TriangleFan, vec![7, 1]).1.is_empty());
+        assert!(convert_mode(Mode::TriangleFan, Vec::new()).1.is_empty());
+    }
+
+    #[test]
+    fn loops_close_back_to_the_first_corner() {
+        let (topology, strip) = convert_mode(Mode::LineLoop, vec![3, 4, 5]);
+        assert_eq!(topology, Topology::LineStrip);
+        assert_eq!(strip, [3, 4, 5, 3]);
+        assert!(convert_mode(Mode::LineLoop, Vec::new()).1.is_empty());
+
+        let lines = vec![0, 1, 1, 2];
+        assert_eq!(
+            convert_mode(Mode::Lines, lines.clone()),
+            (Topology::Lines, lines)
+        );
+    }
+
+    #[test]
+    fn primitives_without_positions_are_left_out() {
+        let path = write_gltf(
+            "no-positions",
+            mesh_gltf(
+                json!([{ "attributes": { "NORMAL": 1 } }, { "attributes": { "POSITION": 0 } }]),
+                json!([positions(3), vec3(3)]),
+                json!([{ "buffer": 0, "byteLength": 36 }]),
+            ),
+            bytemuck::cast_slice(&TRIANGLE),
+        );
+        let model = load(&path).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        assert_eq!(model.meshes[0].positions, TRIANGLE);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn truncated_accessors_are_errors() {
+        let bin: &[u8] = bytemuck::cast_slice(&TRIANGLE);
+        let primitives = json!([{ "attributes": { "POSITION": 0 } }]);
+        // the view holds two of the three positions
+        let short_view = mesh_gltf(
+            primitives.clone(),
+            json!([positions(3)]),
+            json!([{ "buffer": 0, "byteLength": 24 }]),
+        );
+        // sparse indices past the end of their view
+        let mut sparse = positions(3);
+        sparse["sparse"] = json!({
+            "count": 2,
+            "indices": { "bufferView": 1, "componentType": 5125 },
+            "values": { "bufferView": 1 },
+        });
+        let short_sparse = mesh_gltf(
+            primitives,
+            json!([sparse]),
+            json!([
+                { "buffer": 0, "byteLength": 36 },
+                { "buffer": 0, "byteOffset": 32, "byteLength": 4 },
+            ]),
+        );
+
+        for (name, gltf) in [("short-view", short_view), ("short-sparse", short_sparse)] {
+            let path = write_gltf(name, gltf, bin);
+            match load(&path) {
+                Err(LoadError::Gltf(e)) => assert!(e.to_string().contains("accessors[0]"), "{}", e),
+                Err(e) => panic!("{}: {}", name, e),
+                Ok(_) => panic!("{}: loaded", name),
+            }
+            std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+    }
+
+    // the triangle's xy plus the set
+    fn uvs(set: usize) -> Vec<[f32; 2]> {
+        TRIANGLE
+            .map(|[x, y, _]| [x + set as f32, y + set as f32])
+            .to_vec()
+    }
+
+    // A triangle with three uv sets, each in its own view after the
+    // positions
+    fn uv_sets_gltf(material: Value) -> (Value, Vec<u8>) {
+        let mut bin: Vec<u8> = bytemuck::cast_slice(&TRIANGLE).to_vec();
+        let mut views = vec![json!({ "buffer": 0, "byteLength": 36 })];
+        let mut accessors = vec![positions(3)];
+        let mut attributes = json!({ "POSITION": 0 });
+        for set in 0..3 {
+            views.push(json!({ "buffer": 0, "byteOffset": bin.len(), "byteLength": 24 }));
+            bin.extend_from_slice(bytemuck::cast_slice(&uvs(set)));
+            let view = views.len() - 1;
+            accessors.push(json!({
+                "bufferView": view, "componentType": 5126, "count": 3, "type": "VEC2",
+            }));
+            attributes[format!("TEXCOORD_{}", set)] = json!(accessors.len() - 1);
+        }
+
+        let mut gltf = mesh_gltf(
+            json!([{ "attributes": attributes, "material": 0 }]),
+            json!(accessors),
+            json!(views),
+        );
+        gltf["materials"] = json!([material]);
+        gltf["textures"] = json!([{ "source": 0 }]);
+        gltf["images"] = json!([{ "uri": "image.png" }]);
+        gltf["extensionsUsed"] = json!([TRANSFORM]);
+        (gltf, bin)
+    }
+
+    #[test]
+    fn loads_every_uv_set_and_packs_the_sampled_one() {
+        let (gltf, bin) = uv_sets_gltf(json!({
+            "pbrMetallicRoughness": { "baseColorTexture": { "index": 0, "texCoord": 2 } },
+            "emissiveTexture": { "index": 0, "texCoord": 0 },
+        }));
+        let path = write_gltf("uv-sets", gltf, &bin);
+        let model = load(&path).unwrap();
+
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.uvs, uvs(0));
+        // set 2 went where the vertex has room for it
+        assert_eq!(mesh.extra_uvs, [uvs(2), uvs(1)]);
+        assert_eq!(mesh.vertex(1)[12..], [3.0, 2.0]);
+        let material = &model.materials[0];
+        assert_eq!(material.base_color_texture.as_ref().unwrap().tex_coord, 1);
+        assert_eq!(material.emissive_texture.as_ref().unwrap().tex_coord, 0);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn texture_transforms_apply_to_every_slot() {
+        let transform = json!({ TRANSFORM: {
+            "offset": [0.5, 0.25], "rotation": 0.5, "scale": [2, 3], "texCoord": 1,
+        } });
+        let info = json!({ "index": 0, "extensions": transform });
+        let (gltf, bin) = uv_sets_gltf(json!({
+            "pbrMetallicRoughness": {
+                "baseColorTexture": info,
+                "metallicRoughnessTexture": info,
+            },
+            "emissiveTexture": info,
+            "normalTexture": info,
+            "occlusionTexture": info,
+        }));
+        let path = write_gltf("transforms", gltf, &bin);
+        let model = load(&path).unwrap();
+
+        let expected =
+            Mat3::from_scale_angle_translation(Vec2::new(2.0, 3.0), -0.5, Vec2::new(0.5, 0.25));
+        let material = &model.materials[0];
+        assert_eq!(material.textures().count(), 5);
+        for texture in material.textures() {
+            assert_eq!(texture.transform, expected);
+            assert_eq!(texture.tex_coord, 1);
+        }
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -4,6 +4,8 @@ use std::{collections::HashSet, sync::Mutex};
 
 // Global keystate - accessible from anywhere
 pub static KEYSTATE: Lazy<Mutex<HashSet<u16>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+// Keys that went down since they were last checked with `was_pressed`
+pub static KEYPRESSED: Lazy<Mutex<HashSet<u16>>> = Lazy::new(|| Mutex::new(HashSet::new()));
 
 #[repr(u16)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -18,10 +20,41 @@ pub enum Key {
     C = 8,
     R = 15,
     F = 3,
+    TAB = 48,
+    B = 11,
+    P = 35,
+    N = 45,
+    M = 46,
+    L = 37,
+    K = 40,
+    LBRACKET = 33,
+    RBRACKET = 30,
+    RETURN = 36,
+    EQUAL = 24,
+    MINUS = 27,
+    X = 7,
+    T = 17,
+    O = 31,
+    COMMA = 43,
+    PERIOD = 47,
+    NUM1 = 18,
+    NUM2 = 19,
+    NUM3 = 20,
+    NUM4 = 21,
+    NUM5 = 23,
+    NUM6 = 22,
+    NUM7 = 26,
+    NUM8 = 28,
+    NUM9 = 25,
 }
 
 impl Key {
     pub fn is_pressed(self) -> bool {
         return KEYSTATE.lock().unwrap().contains(&(self as u16));
     }
+
+    // One-shot version of is_pressed for toggles, consumes the press
+    pub fn was_pressed(self) -> bool {
+        return KEYPRESSED.lock().unwrap().remove(&(self as u16));
+    }
 }
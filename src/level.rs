@@ -0,0 +1,656 @@
+// Level files, a scene saved as JSON:
+//
+//   {
+//     "version": 1,
+//     "assets": [{ "asset": "Sponza", "translation": [0, 0, 0],
+//                  "rotation": [0, 0, 0, 1], "scale": [1, 1, 1],
+//                  "parent": 0, "spin": [0, 45, 0],
+//                  "materials": [{ "material": "name", "base_color": [1, 0, 0, 1],
+//                                  "emissive": [0, 0, 0] }] }],
+//     "lights": [{ "type": "directional", "direction": [0, -1, 0],
+//                  "color": [1, 1, 1], "intensity": 3 },
+//                { "type": "point", "position": [0, 2, 0], "color": [1, 1, 1],
+//                  "intensity": 10, "range": 20 }],
+//     "bookmarks": [{ "name": "view1", "position": [0, 1, 0], "yaw": -90,
+//                     "pitch": 0, "fov": 60 }],
+//     "paths": [{ "name": "tour", "kind": "catmull-rom", "duration": 10,
+//                 "bookmarks": ["view1", "view2"] }]
+//   }
+//
+// An asset is a catalog name or a model file relative to the level. Only
+// "version" and "assets" are required, a missing transform is the identity.
+// "parent" is the index of an earlier asset, the transform is relative to
+// it and the asset moves along with it. "spin" turns the asset around that
+// axis in its own space, its length in degrees per second
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use glam::{Mat4, Quat, Vec3};
+use serde_json::{Value, json};
+
+use crate::bookmark::{Bookmarks, CameraPose, PathKind};
+use crate::catalog::{self, AssetEntry};
+use crate::gameplay::Spin;
+use crate::model::{self, LoadError, MaterialOverride, ModelData};
+use crate::resource::Device;
+use crate::scene::{Light, Scene};
+use crate::transform::{self, Transform};
+
+// bumped when a change would make older builds misread a level
+pub const VERSION: u64 = 1;
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    // written by a newer version
+    Version(u64),
+    Invalid(String),
+    // every asset reference that didn't resolve, with where it is
+    Missing(Vec<String>),
+    Load(PathBuf, LoadError),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io(e) => write!(f, "{}", e),
+            LevelError::Json(e) => write!(f, "{}", e),
+            LevelError::Version(version) => write!(
+                f,
+                "level version {} is newer than this build reads ({})",
+                version, VERSION
+            ),
+            LevelError::Invalid(what) => write!(f, "invalid level: {}", what),
+            LevelError::Missing(assets) => {
+                write!(f, "missing assets (see bs --list): {}", assets.join(", "))
+            }
+            LevelError::Load(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<io::Error> for LevelError {
+    fn from(e: io::Error) -> Self {
+        LevelError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LevelError {
+    fn from(e: serde_json::Error) -> Self {
+        LevelError::Json(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, LevelError>;
+
+pub struct Instance {
+    pub entry: AssetEntry,
+    // relative to the parent, when there is one
+    pub root: Mat4,
+    // index of an earlier instance
+    pub parent: Option<usize>,
+    pub overrides: Vec<MaterialOverride>,
+    pub spin: Option<Spin>,
+}
+
+// A level with its assets found, ready to be loaded
+#[derive(Default)]
+pub struct Level {
+    pub instances: Vec<Instance>,
+    pub lights: Vec<Light>,
+    pub bookmarks: Bookmarks,
+}
+
+impl Level {
+    // Loads every instance's model. Nothing is kept when one fails
+    pub fn build_scene(&self, device: &Device) -> Result<Scene> {
+        let mut scene = Scene::default();
+        for light in &self.lights {
+            scene.add_light(light.clone());
+        }
+        for instance in &self.instances {
+            let object = scene
+                .add(device, instance.entry.clone(), instance.root)
+                .map_err(|e| LevelError::Load(instance.entry.model.clone(), e))?;
+            if !instance.overrides.is_empty() {
+                object.asset.set_overrides(instance.overrides.clone());
+            }
+            let entity = object.entity;
+            if let Some(spin) = instance.spin {
+                scene.world.insert(entity, spin);
+            }
+            if let Some(parent) = instance.parent {
+                let parent = scene.objects[parent].entity;
+                transform::set_parent(&mut scene.world, entity, Some(parent));
+            }
+        }
+        transform::propagate(&mut scene.world);
+        Ok(scene)
+    }
+
+    // Every instance as one static model, without a device. What exporting
+    // the level from the command line writes
+    pub fn flatten(&self) -> Result<ModelData> {
+        let mut model = ModelData::default();
+        let mut worlds: Vec<Mat4> = Vec::new();
+        for instance in &self.instances {
+            let part = model::load(&instance.entry.model)
+                .map_err(|e| LevelError::Load(instance.entry.model.clone(), e))?;
+            let world = instance
+                .parent
+                .map_or(instance.root, |parent| worlds[parent] * instance.root);
+            model.append(part, world, &instance.overrides);
+            worlds.push(world);
+        }
+        Ok(model)
+    }
+}
+
+// Reads the level and finds its assets. All missing assets are reported at
+// once rather than the first one
+pub fn load(path: &Path, catalog: &[AssetEntry]) -> Result<Level> {
+    let root: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+    let version = root["version"]
+        .as_u64()
+        .ok_or_else(|| invalid("level.version", "expected a number"))?;
+    if version > VERSION {
+        return Err(LevelError::Version(version));
+    }
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut level = Level::default();
+    let mut missing = Vec::new();
+    for (i, value) in array(&root, "assets", "level", true)?.iter().enumerate() {
+        let at = format!("assets[{}]", i);
+        let name = string(value, "asset", &at)?;
+        let Some(entry) = find_asset(catalog, dir, name) else {
+            missing.push(format!("{} ({})", name, at));
+            continue;
+        };
+
+        let translation = optional(value, "translation", &at, floats)?.unwrap_or([0.0; 3]);
+        let rotation = optional(value, "rotation", &at, floats)?.unwrap_or([0.0, 0.0, 0.0, 1.0]);
+        let scale = optional(value, "scale", &at, floats)?.unwrap_or([1.0; 3]);
+        // normalized again only to drop the rounding of the decimals
+        let rotation = Quat::from_array(rotation);
+        if !rotation.is_normalized() {
+            return Err(invalid(&at, "rotation is not a unit quaternion"));
+        }
+        let root = Mat4::from_scale_rotation_translation(
+            Vec3::from(scale),
+            rotation.normalize(),
+            Vec3::from(translation),
+        );
+
+        // earlier only, so there can't be a loop
+        let parent = optional(value, "parent", &at, index)?;
+        if parent.is_some_and(|parent| parent >= i) {
+            return Err(invalid(&at, "parent has to be an earlier asset"));
+        }
+
+        let spin = optional(value, "spin", &at, floats::<3>)?
+            .map(Vec3::from)
+            .filter(|spin| *spin != Vec3::ZERO)
+            .map(|spin| Spin {
+                axis: spin.normalize(),
+                speed: spin.length().to_radians(),
+            });
+
+        let mut overrides = Vec::new();
+        for (j, value) in array(value, "materials", &at, false)?.iter().enumerate() {
+            let at = format!("{}.materials[{}]", at, j);
+            overrides.push(MaterialOverride {
+                material: string(value, "material", &at)?.to_string(),
+                base_color: optional(value, "base_color", &at, floats)?,
+                emissive: optional(value, "emissive", &at, floats)?,
+            });
+        }
+
+        level.instances.push(Instance {
+            entry,
+            root,
+            parent,
+            overrides,
+            spin,
+        });
+    }
+    if !missing.is_empty() {
+        return Err(LevelError::Missing(missing));
+    }
+
+    for (i, value) in array(&root, "lights", "level", false)?.iter().enumerate() {
+        let at = format!("lights[{}]", i);
+        let color = optional(value, "color", &at, floats)?.unwrap_or([1.0; 3]);
+        let intensity = optional(value, "intensity", &at, float)?.unwrap_or(1.0);
+        let light = match string(value, "type", &at)? {
+            "directional" => Light::Directional {
+                direction: Vec3::from(required(value, "direction", &at, floats::<3>)?),
+                color: Vec3::from(color),
+                intensity,
+            },
+            "point" => Light::Point {
+                position: Vec3::from(required(value, "position", &at, floats::<3>)?),
+                color: Vec3::from(color),
+                intensity,
+                range: optional(value, "range", &at, float)?,
+            },
+            other => return Err(invalid(&at, &format!("unknown light type {:?}", other))),
+        };
+        level.lights.push(light);
+    }
+
+    for (i, value) in array(&root, "bookmarks", "level", false)?
+        .iter()
+        .enumerate()
+    {
+        let at = format!("bookmarks[{}]", i);
+        let pose = CameraPose {
+            position: Vec3::from(required(value, "position", &at, floats::<3>)?),
+            yaw: required(value, "yaw", &at, float)?,
+            pitch: required(value, "pitch", &at, float)?,
+            fov: required(value, "fov", &at, float)?,
+        };
+        let name = string(value, "name", &at)?.to_string();
+        level.bookmarks.bookmarks.push((name, pose));
+    }
+
+    for (i, value) in array(&root, "paths", "level", false)?.iter().enumerate() {
+        let at = format!("paths[{}]", i);
+        let kind = string(value, "kind", &at)?;
+        let kind = PathKind::from_name(kind)
+            .ok_or_else(|| invalid(&at, "kind is catmull-rom or bezier"))?;
+        let stops = array(value, "bookmarks", &at, true)?
+            .iter()
+            .map(|stop| stop.as_str().map(String::from))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| invalid(&at, "bookmarks are names"))?;
+        level.bookmarks.paths.push((
+            string(value, "name", &at)?.to_string(),
+            kind,
+            required(value, "duration", &at, float)?,
+            stops,
+        ));
+    }
+
+    log::info!(
+        "loaded level {}: {} assets, {} lights, {} bookmarks",
+        path.display(),
+        level.instances.len(),
+        level.lights.len(),
+        level.bookmarks.bookmarks.len()
+    );
+    Ok(level)
+}
+
+// Assets from the catalog are saved by name, anything else by its model
+// path, relative to the level when it's next to or below it
+pub fn save(
+    path: &Path,
+    scene: &Scene,
+    bookmarks: &Bookmarks,
+    catalog: &[AssetEntry],
+) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let assets: Vec<Value> = scene
+        .objects
+        .iter()
+        .map(|object| {
+            let entry = &object.entry;
+            let name = match catalog.iter().find(|known| known.model == entry.model) {
+                Some(known) => known.dir.file_name().unwrap_or_default().to_string_lossy(),
+                None => entry
+                    .model
+                    .strip_prefix(dir)
+                    .unwrap_or(&entry.model)
+                    .to_string_lossy(),
+            };
+            let (translation, rotation, scale) = scene
+                .world
+                .get::<Transform>(object.entity)
+                .map_or((Vec3::ZERO, Quat::IDENTITY, Vec3::ONE), |transform| {
+                    (
+                        transform.translation(),
+                        transform.rotation(),
+                        transform.scale(),
+                    )
+                });
+            let materials: Vec<Value> = object
+                .asset
+                .overrides()
+                .iter()
+                .map(|o| {
+                    let mut material = json!({ "material": o.material });
+                    if let Some(base_color) = o.base_color {
+                        material["base_color"] = json!(base_color);
+                    }
+                    if let Some(emissive) = o.emissive {
+                        material["emissive"] = json!(emissive);
+                    }
+                    material
+                })
+                .collect();
+            let mut asset = json!({
+                "asset": name,
+                "translation": translation.to_array(),
+                "rotation": rotation.to_array(),
+                "scale": scale.to_array(),
+                "materials": materials,
+            });
+            let parent = transform::parent(&scene.world, object.entity).and_then(|parent| {
+                scene
+                    .objects
+                    .iter()
+                    .position(|other| other.entity == parent)
+            });
+            if let Some(parent) = parent {
+                asset["parent"] = json!(parent);
+            }
+            if let Some(spin) = scene.world.get::<Spin>(object.entity) {
+                asset["spin"] = json!((spin.axis * spin.speed.to_degrees()).to_array());
+            }
+            asset
+        })
+        .collect();
+
+    let lights: Vec<Value> = scene
+        .world
+        .query::<Light>()
+        .map(|(_, light)| match light {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => json!({
+                "type": "directional",
+                "direction": direction.to_array(),
+                "color": color.to_array(),
+                "intensity": intensity,
+            }),
+            Light::Point {
+                position,
+                color,
+                intensity,
+                range,
+            } => {
+                let mut light = json!({
+                    "type": "point",
+                    "position": position.to_array(),
+                    "color": color.to_array(),
+                    "intensity": intensity,
+                });
+                if let Some(range) = range {
+                    light["range"] = json!(range);
+                }
+                light
+            }
+        })
+        .collect();
+
+    let poses: Vec<Value> = bookmarks
+        .bookmarks
+        .iter()
+        .map(|(name, pose)| {
+            json!({
+                "name": name,
+                "position": pose.position.to_array(),
+                "yaw": pose.yaw,
+                "pitch": pose.pitch,
+                "fov": pose.fov,
+            })
+        })
+        .collect();
+
+    let paths: Vec<Value> = bookmarks
+        .paths
+        .iter()
+        .map(|(name, kind, duration, stops)| {
+            json!({
+                "name": name,
+                "kind": kind.name(),
+                "duration": duration,
+                "bookmarks": stops,
+            })
+        })
+        .collect();
+
+    let level = json!({
+        "version": VERSION,
+        "assets": assets,
+        "lights": lights,
+        "bookmarks": poses,
+        "paths": paths,
+    });
+    let mut text = serde_json::to_string_pretty(&level).map_err(io::Error::other)?;
+    text.push('\n');
+    fs::write(path, text)
+}
+
+// Next to the level first, so a level can be moved along with its models
+fn find_asset(catalog: &[AssetEntry], dir: &Path, name: &str) -> Option<AssetEntry> {
+    let beside = dir.join(name);
+    if beside.is_file() {
+        return Some(AssetEntry::from_model(&beside));
+    }
+    catalog::find(catalog, name)
+}
+
+fn invalid(at: &str, what: &str) -> LevelError {
+    LevelError::Invalid(format!("{}: {}", at, what))
+}
+
+// An absent array is empty unless it's `required`
+fn array<'a>(value: &'a Value, key: &str, at: &str, required: bool) -> Result<&'a [Value]> {
+    match &value[key] {
+        Value::Array(items) => Ok(items),
+        Value::Null if !required => Ok(&[]),
+        _ => Err(invalid(&format!("{}.{}", at, key), "expected an array")),
+    }
+}
+
+fn string<'a>(value: &'a Value, key: &str, at: &str) -> Result<&'a str> {
+    value[key]
+        .as_str()
+        .ok_or_else(|| invalid(&format!("{}.{}", at, key), "expected a string"))
+}
+
+fn required<T>(value: &Value, key: &str, at: &str, parse: fn(&Value) -> Option<T>) -> Result<T> {
+    optional(value, key, at, parse)?.ok_or_else(|| invalid(&format!("{}.{}", at, key), "missing"))
+}
+
+// Ok(None) when the key isn't there, an error when it holds something else
+fn optional<T>(
+    value: &Value,
+    key: &str,
+    at: &str,
+    parse: fn(&Value) -> Option<T>,
+) -> Result<Option<T>> {
+    match &value[key] {
+        Value::Null => Ok(None),
+        field => parse(field)
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{}.{}", at, key), "wrong type or length")),
+    }
+}
+
+fn index(value: &Value) -> Option<usize> {
+    value.as_u64().map(|index| index as usize)
+}
+
+fn float(value: &Value) -> Option<f32> {
+    value.as_f64().map(|f| f as f32)
+}
+
+fn floats<const N: usize>(value: &Value) -> Option<[f32; N]> {
+    let items = value.as_array()?;
+    if items.len() != N {
+        return None;
+    }
+    let mut out = [0.0; N];
+    for (out, item) in out.iter_mut().zip(items) {
+        *out = float(item)?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOX: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Box/glTF");
+
+    // A scratch directory with a copy of the Box next to the level, so
+    // loading it cooks there and not in the assets
+    fn level_dir(name: &str, level: &Value) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bs-level-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for file in ["Box.gltf", "Box0.bin"] {
+            fs::copy(Path::new(BOX).join(file), dir.join(file)).unwrap();
+        }
+        fs::write(dir.join("level.json"), level.to_string()).unwrap();
+        dir
+    }
+
+    #[test]
+    fn round_trips() {
+        let dir = level_dir(
+            "round-trip",
+            &json!({
+                "version": 1,
+                "assets": [
+                    { "asset": "Box.gltf", "translation": [1, 2, 3],
+                      "rotation": [0, 0.6, 0, 0.8], "scale": [2, 2, 2] },
+                    { "asset": "Box.gltf", "translation": [0, 1, 0], "parent": 0,
+                      "spin": [0, 90, 0],
+                      "materials": [{ "material": "Red", "base_color": [0, 0, 1, 1] }] },
+                ],
+                "lights": [
+                    { "type": "directional", "direction": [0, -1, 0], "intensity": 3 },
+                    { "type": "point", "position": [0, 2, 0], "color": [1, 0.5, 0],
+                      "range": 20 },
+                ],
+                "bookmarks": [
+                    { "name": "a", "position": [0, 1, 5], "yaw": -90, "pitch": 0, "fov": 60 },
+                    { "name": "b", "position": [5, 1, 0], "yaw": 180, "pitch": -10, "fov": 45 },
+                ],
+                "paths": [{ "name": "tour", "kind": "bezier", "duration": 10,
+                            "bookmarks": ["a", "b"] }],
+            }),
+        );
+        let level = load(&dir.join("level.json"), &[]).unwrap();
+
+        let [first, second] = &level.instances[..] else {
+            panic!("{} instances", level.instances.len());
+        };
+        assert_eq!(first.entry.model, dir.join("Box.gltf"));
+        let rotation = Quat::from_xyzw(0.0, 0.6, 0.0, 0.8);
+        let root = Mat4::from_scale_rotation_translation(
+            Vec3::splat(2.0),
+            rotation,
+            Vec3::new(1.0, 2.0, 3.0),
+        );
+        assert!(first.root.abs_diff_eq(root, 1e-6));
+        assert_eq!(first.parent, None);
+        assert_eq!(second.parent, Some(0));
+        let spin = second.spin.unwrap();
+        assert_eq!(spin.axis, Vec3::Y);
+        assert_eq!(spin.speed, 90f32.to_radians());
+        assert_eq!(second.overrides[0].base_color, Some([0.0, 0.0, 1.0, 1.0]));
+        assert_eq!(
+            level.lights[1],
+            Light::Point {
+                position: Vec3::new(0.0, 2.0, 0.0),
+                color: Vec3::new(1.0, 0.5, 0.0),
+                intensity: 1.0,
+                range: Some(20.0),
+            }
+        );
+
+        // through a scene and back
+        let scene = level.build_scene(&Device::system_default()).unwrap();
+        let saved = dir.join("saved.json");
+        save(&saved, &scene, &level.bookmarks, &[]).unwrap();
+        let again = load(&saved, &[]).unwrap();
+
+        assert_eq!(again.instances.len(), level.instances.len());
+        for (a, b) in again.instances.iter().zip(&level.instances) {
+            assert_eq!(a.entry.model, b.entry.model);
+            assert!(a.root.abs_diff_eq(b.root, 1e-5));
+            assert_eq!(a.parent, b.parent);
+            assert_eq!(a.spin.map(|s| s.axis), b.spin.map(|s| s.axis));
+            assert_eq!(a.spin.map(|s| s.speed), b.spin.map(|s| s.speed));
+            assert_eq!(a.overrides.len(), b.overrides.len());
+            for (a, b) in a.overrides.iter().zip(&b.overrides) {
+                assert_eq!(a.material, b.material);
+                assert_eq!(a.base_color, b.base_color);
+                assert_eq!(a.emissive, b.emissive);
+            }
+        }
+        assert_eq!(again.lights, level.lights);
+        let poses = |level: &Level| -> Vec<_> {
+            let bookmarks = &level.bookmarks.bookmarks;
+            bookmarks
+                .iter()
+                .map(|(name, p)| (name.clone(), p.position, p.yaw, p.pitch, p.fov))
+                .collect()
+        };
+        assert_eq!(poses(&again), poses(&level));
+        assert_eq!(again.bookmarks.paths, level.bookmarks.paths);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_every_missing_asset() {
+        let dir = level_dir(
+            "missing",
+            &json!({
+                "version": 1,
+                "assets": [{ "asset": "Nope" }, { "asset": "Box.gltf" }, { "asset": "gone.glb" }],
+            }),
+        );
+        match load(&dir.join("level.json"), &[]) {
+            Err(LevelError::Missing(missing)) => {
+                assert_eq!(missing, ["Nope (assets[0])", "gone.glb (assets[2])"])
+            }
+            Err(e) => panic!("{}", e),
+            Ok(_) => panic!("loaded"),
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_bad_levels() {
+        for (name, assets, expected) in [
+            (
+                "zero-rotation",
+                json!([{ "asset": "Box.gltf", "rotation": [0, 0, 0, 0] }]),
+                "assets[0]: rotation is not a unit quaternion",
+            ),
+            (
+                "long-rotation",
+                json!([{ "asset": "Box.gltf", "rotation": [0, 1, 0, 1] }]),
+                "assets[0]: rotation is not a unit quaternion",
+            ),
+            (
+                "later-parent",
+                json!([{ "asset": "Box.gltf", "parent": 1 }, { "asset": "Box.gltf" }]),
+                "assets[0]: parent has to be an earlier asset",
+            ),
+        ] {
+            let dir = level_dir(name, &json!({ "version": 1, "assets": assets }));
+            match load(&dir.join("level.json"), &[]) {
+                Err(LevelError::Invalid(what)) => assert_eq!(what, expected),
+                Err(e) => panic!("{}", e),
+                Ok(_) => panic!("{} loaded", name),
+            }
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
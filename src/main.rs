@@ -1,24 +1,55 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
+mod animation;
+mod basis;
+mod bc;
+mod bookmark;
 mod camera;
+mod catalog;
+mod compress;
+mod cook;
+mod draco;
+mod ecs;
+mod game_loop;
+mod gameplay;
+mod geometry;
+mod gltf_export;
+mod gltf_loader;
 mod input;
+mod level;
+mod meshopt;
+mod model;
+mod morph;
+mod obj_loader;
 mod platform;
+mod player;
 mod render;
 mod resource;
+mod scene;
+mod skin;
+mod texture;
+mod transform;
+mod watch;
 
-// TODO: What?
-use objc2::AnyThread;
-use objc2::runtime::AnyObject;
-
+use crate::bookmark::{Bookmarks, CameraPose, PathPlayer};
 use crate::camera::Camera;
+use crate::catalog::AssetEntry;
+use crate::game_loop::GameLoop;
 use crate::input::Key;
+use crate::level::{Instance, Level};
+use crate::model::ModelData;
 use crate::platform::{Delegate, Ivars};
-use crate::render::{Asset, Mesh, RenderPass, SinglePass, Uniforms};
-use crate::resource::{Buffer, BufferKind, Device, ShaderLibrary};
+use crate::render::{FRAMES_IN_FLIGHT, RenderPass, SinglePass, Uniforms, draw_list};
+use crate::resource::{Device, ShaderLibrary, ShaderSource};
+use crate::scene::Scene;
+use crate::transform::Transform;
+use crate::watch::FileWatcher;
 
 use objc2::MainThreadOnly;
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::path::{Path, PathBuf};
 
 use objc2::rc::Retained;
 use objc2::runtime::ProtocolObject;
@@ -26,9 +57,7 @@ use objc2::{MainThreadMarker, msg_send};
 
 use glam::{Mat4, Vec3};
 
-use objc2_foundation::{
-    NSDate, NSDictionary, NSNumber, NSPoint, NSRect, NSSize, NSString, NSUInteger, NSURL, ns_string,
-};
+use objc2_foundation::{NSDate, NSPoint, NSRect, NSSize, NSString, NSUInteger};
 
 use objc2_app_kit::{
     NSApplication, NSApplicationActivationPolicy, NSBackingStoreType, NSWindow, NSWindowStyleMask,
@@ -36,26 +65,497 @@ use objc2_app_kit::{
 
 use objc2_metal::*;
 
-use objc2_metal_kit::{MTKTextureLoader, MTKTextureLoaderOptionAllocateMipmaps, MTKView};
+use objc2_metal_kit::MTKView;
 
 const WINDOW_W: f64 = 800.0;
 const WINDOW_H: f64 = 600.0;
 
-const GLTF_NAME: &str = "Sponza";
+// opened when no asset is given on the command line
+const DEFAULT_ASSET: &str = "Sponza";
+
+// how far in front of the camera added assets are put
+const PLACE_DISTANCE: f32 = 5.0;
+
+// where K saves a scene that wasn't opened from a level
+const DEFAULT_LEVEL: &str = "./level.json";
+
+// where X exports the scene
+const EXPORT_PATH: &str = "./scene.glb";
+
+// Assets opened by name sit under it, meshes and cameras
+fn scene_root() -> Mat4 {
+    Mat4::from_rotation_x(f32::to_radians(-15.0))
+}
+
+fn window_title(scene: &Scene) -> String {
+    format!("fortnite_source_code_leaked - {}", scene.title())
+}
+
+// The first glTF camera is the default viewpoint. The free camera starts
+// there too, so switching to it doesn't jump across the scene
+fn start_camera(scene_cameras: &[Camera]) -> (Camera, Option<usize>) {
+    match scene_cameras.first() {
+        Some(first) => (first.clone(), Some(0)),
+        None => {
+            let cam_position = Vec3::new(0.0, 10.0, 0.0);
+            let cam_target = Vec3::new(0.0, 0.0, 0.0);
+            let camera = Camera::new(
+                cam_position,
+                cam_target,
+                Vec3::normalize(cam_position - cam_target), // direction
+                Vec3::new(0.0, 0.0, -1.0),                  // front, Looking at -Z
+                Vec3::new(0.0, 1.0, 0.0),                   // up
+                -90.0,                                      // yaw
+                0.0,                                        // pitch
+            );
+            (camera, None)
+        }
+    }
+}
+
+// Kept next to the asset
+fn bookmarks_path(asset: &AssetEntry) -> PathBuf {
+    asset.dir.join("bookmarks.txt")
+}
+
+// A missing file is no bookmarks yet
+fn load_bookmarks(asset: &AssetEntry) -> Bookmarks {
+    let path = bookmarks_path(asset);
+    Bookmarks::load(&path).unwrap_or_else(|e| {
+        log::error!("could not load {}: {}", path.display(), e);
+        Bookmarks::default()
+    })
+}
+
+// 1-9 jump to the bookmark with that number
+const BOOKMARK_KEYS: [Key; 9] = [
+    Key::NUM1,
+    Key::NUM2,
+    Key::NUM3,
+    Key::NUM4,
+    Key::NUM5,
+    Key::NUM6,
+    Key::NUM7,
+    Key::NUM8,
+    Key::NUM9,
+];
 
 pub struct AppState {
     start_date: Retained<NSDate>,
+    // the scene ticks at a fixed rate, frames are drawn in between
+    game_loop: RefCell<GameLoop>,
     pub device: Device,
-    model: Asset,
+    // command buffers of the last frames, by frame % FRAMES_IN_FLIGHT. A
+    // frame waits for the one in its slot before animation writes vertices
+    in_flight: RefCell<Vec<Option<Retained<ProtocolObject<dyn MTLCommandBuffer>>>>>,
+    frame_count: Cell<usize>,
+    // RefCell for the same reason as the camera, systems and animation move
+    // entities and assets come and go
+    scene: RefCell<Scene>,
+    // the level file the scene came from or was last saved to. The
+    // bookmarks are kept in it rather than next to the first asset
+    level_path: RefCell<Option<PathBuf>>,
+    // everything under assets/, and the entry the browser is on
+    catalog: Vec<AssetEntry>,
+    selected: Cell<usize>,
+    watcher: RefCell<FileWatcher>,
     // RefCell? In frame() an immutable reference to AppState is passed in.
     // But camera state needs to mutate when input is pressed
     // RefCell allows for mutable borrows at runtime, even when the data is immutable
     // Maybe move out of app state
     camera: RefCell<Camera>,
-    pass: SinglePass,
+    // Cameras imported from the scene's glTFs, cycled through with TAB.
+    // None means the free camera above is active
+    scene_cameras: RefCell<Vec<Camera>>,
+    active_camera: Cell<Option<usize>>,
+    bookmarks: RefCell<Bookmarks>,
+    camera_path: RefCell<Option<PathPlayer>>,
+    // RefCell so the pipeline can be swapped when the shaders change
+    pass: RefCell<SinglePass>,
+    // kept to rebuild the pipeline with recompiled shaders
+    pipeline_descriptor: Retained<MTLRenderPipelineDescriptor>,
+    shader_path: PathBuf,
+    shader_watcher: RefCell<FileWatcher>,
+}
+
+impl AppState {
+    // free camera -> scene camera 0 -> ... -> scene camera n -> free camera
+    fn cycle_camera(&self) {
+        let count = self.scene_cameras.borrow().len();
+        let next = match self.active_camera.get() {
+            None if count > 0 => Some(0),
+            Some(i) if i + 1 < count => Some(i + 1),
+            _ => None,
+        };
+        match next {
+            Some(i) => log::info!("switched to scene camera {}", i),
+            None => log::info!("switched to free camera"),
+        }
+        self.active_camera.set(next);
+    }
+
+    // L lists the assets, [ and ] pick one. RETURN opens it on its own, =
+    // adds it in front of the camera and - removes the last one added
+    fn handle_browser(&self, view: &MTKView) {
+        if Key::L.was_pressed() {
+            let scene = self.scene.borrow();
+            for (i, entry) in self.catalog.iter().enumerate() {
+                let marker = if i == self.selected.get() { '>' } else { ' ' };
+                let in_scene = scene
+                    .objects
+                    .iter()
+                    .any(|object| object.entry.model == entry.model);
+                log::info!(
+                    "{} {}: {}{} - {} [{}]",
+                    marker,
+                    i,
+                    entry.name,
+                    if in_scene { " (in scene)" } else { "" },
+                    entry.summary,
+                    entry.tags.join(", ")
+                );
+            }
+        }
+
+        if Key::MINUS.was_pressed() {
+            self.remove_asset(view);
+        }
+
+        if self.catalog.is_empty() {
+            return;
+        }
+        let count = self.catalog.len();
+        let selected = self.selected.get();
+        let next = if Key::RBRACKET.was_pressed() {
+            (selected + 1) % count
+        } else if Key::LBRACKET.was_pressed() {
+            (selected + count - 1) % count
+        } else {
+            selected
+        };
+        if next != selected {
+            self.selected.set(next);
+            log::info!("selected {}", self.catalog[next].name);
+        }
+
+        if Key::RETURN.was_pressed() {
+            self.open_asset(self.catalog[next].clone(), view);
+        }
+        if Key::EQUAL.was_pressed() {
+            self.add_asset(self.catalog[next].clone(), view);
+        }
+    }
+
+    // Replaces the whole scene. Its cameras, bookmarks and animations go with
+    // it, so do the old GPU buffers and textures
+    fn open_asset(&self, entry: AssetEntry, view: &MTKView) {
+        let path = entry.model.clone();
+        let mut scene = Scene::default();
+        if let Err(e) = scene.add(&self.device, entry, scene_root()) {
+            log::error!("could not open {}: {}", path.display(), e);
+            return;
+        }
+
+        let scene_cameras = scene.cameras();
+        let (camera, active_camera) = start_camera(&scene_cameras);
+        *self.camera.borrow_mut() = camera;
+        *self.scene_cameras.borrow_mut() = scene_cameras;
+        self.active_camera.set(active_camera);
+
+        *self.bookmarks.borrow_mut() = load_bookmarks(&scene.objects[0].entry);
+        *self.camera_path.borrow_mut() = None;
+        *self.level_path.borrow_mut() = None;
+        *self.scene.borrow_mut() = scene;
+        self.scene_changed(view);
+    }
+
+    // Puts the asset in front of whichever camera is looking, upright like
+    // the rest of the scene
+    fn add_asset(&self, entry: AssetEntry, view: &MTKView) {
+        let position = match self.active_camera.get() {
+            Some(i) => {
+                let scene_cameras = self.scene_cameras.borrow();
+                scene_cameras[i].position + scene_cameras[i].front * PLACE_DISTANCE
+            }
+            None => {
+                let camera = self.camera.borrow();
+                camera.position + camera.front * PLACE_DISTANCE
+            }
+        };
+        let root = Mat4::from_translation(position) * scene_root();
+
+        let path = entry.model.clone();
+        let added = self
+            .scene
+            .borrow_mut()
+            .add(&self.device, entry, root)
+            .map(|_| ());
+        match added {
+            Ok(()) => {
+                *self.scene_cameras.borrow_mut() = self.scene.borrow().cameras();
+                self.scene_changed(view);
+            }
+            Err(e) => log::error!("could not add {}: {}", path.display(), e),
+        }
+    }
+
+    // The first asset stays, the bookmarks may belong to it
+    fn remove_asset(&self, view: &MTKView) {
+        let mut scene = self.scene.borrow_mut();
+        if scene.objects.len() < 2 {
+            log::warn!("nothing was added to the scene");
+            return;
+        }
+        let last = scene.objects.len() - 1;
+        scene.remove(last);
+
+        let scene_cameras = scene.cameras();
+        if self
+            .active_camera
+            .get()
+            .is_some_and(|i| i >= scene_cameras.len())
+        {
+            self.active_camera.set(None);
+        }
+        *self.scene_cameras.borrow_mut() = scene_cameras;
+        drop(scene);
+        self.scene_changed(view);
+    }
+
+    // K writes the scene to its level file, or starts one
+    fn save_level(&self) {
+        let mut level_path = self.level_path.borrow_mut();
+        let path = level_path.get_or_insert_with(|| PathBuf::from(DEFAULT_LEVEL));
+        match level::save(
+            path,
+            &self.scene.borrow(),
+            &self.bookmarks.borrow(),
+            &self.catalog,
+        ) {
+            Ok(()) => log::info!("saved {}", path.display()),
+            Err(e) => log::error!("could not save {}: {}", path.display(), e),
+        }
+    }
+
+    // X writes everything in the scene out as one glTF
+    fn export_scene(&self) {
+        let path = Path::new(EXPORT_PATH);
+        let model = match self.scene.borrow().flatten() {
+            Ok(model) => model,
+            Err(e) => {
+                log::error!("could not export the scene: {}", e);
+                return;
+            }
+        };
+        match gltf_export::write(&model, path) {
+            Ok(()) => log::info!("exported {}", path.display()),
+            Err(e) => log::error!("could not write {}: {}", path.display(), e),
+        }
+    }
+
+    // Watches the files of what's in the scene now and names it in the title
+    fn scene_changed(&self, view: &MTKView) {
+        let scene = self.scene.borrow();
+        scene.watch(&mut self.watcher.borrow_mut());
+        if let Some(window) = view.window() {
+            window.setTitle(&NSString::from_str(&window_title(&scene)));
+        }
+    }
+
+    // N crossfades every animated asset to its next clip, M pauses/resumes.
+    // T restarts the current clip, comma and period halve and double its
+    // speed, O toggles its looping. Then the scene's systems and animations
+    // run for however many ticks fit since the last frame, which may move
+    // cameras
+    fn update_scene(&self, time: f64) {
+        let crossfade = Key::N.was_pressed();
+        let toggle_pause = Key::M.was_pressed();
+        let restart = Key::T.was_pressed();
+        let speed_factor = if Key::COMMA.was_pressed() {
+            Some(0.5)
+        } else if Key::PERIOD.was_pressed() {
+            Some(2.0)
+        } else {
+            None
+        };
+        let toggle_looping = Key::O.was_pressed();
+
+        let mut scene = self.scene.borrow_mut();
+        for object in &mut scene.objects {
+            let clips = &object.data.animations;
+            let player = &mut object.player;
+
+            if crossfade && !clips.is_empty() {
+                let next = player
+                    .current_clip()
+                    .map_or(0, |clip| (clip + 1) % clips.len());
+                log::info!("crossfading to animation {:?}", clips[next].name);
+                player.crossfade(next, 0.3);
+            }
+            if toggle_pause {
+                if player.is_paused() {
+                    player.resume();
+                } else {
+                    player.pause();
+                }
+            }
+
+            let Some(clip) = player.current_clip() else {
+                continue;
+            };
+            if restart {
+                player.seek(0.0);
+            }
+            let settings = player.settings(clip);
+            if let Some(factor) = speed_factor {
+                let speed = settings.speed * factor;
+                log::info!("{:?} plays at {}x", clips[clip].name, speed);
+                player.set_speed(clip, speed);
+            }
+            if toggle_looping {
+                let looping = !settings.looping;
+                log::info!("{:?} looping: {}", clips[clip].name, looping);
+                player.set_looping(clip, looping);
+            }
+        }
+
+        scene.alpha = self.game_loop.borrow_mut().advance(time, &mut *scene);
+
+        // a reload may have taken the active camera away
+        let scene_cameras = scene.cameras();
+        if self
+            .active_camera
+            .get()
+            .is_some_and(|i| i >= scene_cameras.len())
+        {
+            self.active_camera.set(None);
+        }
+        *self.scene_cameras.borrow_mut() = scene_cameras;
+    }
+
+    // Picks up edits to the files of any asset in the scene while running
+    fn reload_changed(&self, time: f64) {
+        let mut watcher = self.watcher.borrow_mut();
+        let changed = watcher.poll(time);
+        if changed.is_empty() {
+            return;
+        }
+        let mut scene = self.scene.borrow_mut();
+        scene.reload_changed(&self.device, &changed);
+
+        // the models may reference different files now
+        scene.watch(&mut watcher);
+    }
+
+    // Recompiles the shader when it or a header it includes changed and
+    // rebuilds the pipeline. Compile errors are logged and the last working
+    // pipeline stays in use
+    fn reload_shaders(&self, time: f64) {
+        let mut watcher = self.shader_watcher.borrow_mut();
+        if watcher.poll(time).is_empty() {
+            return;
+        }
+
+        let source = match ShaderSource::read(&self.shader_path) {
+            Ok(source) => source,
+            Err(e) => {
+                log::error!("could not read {}: {}", self.shader_path.display(), e);
+                return;
+            }
+        };
+        // a header may have been added or removed
+        watcher.watch_only(source.files.iter().map(PathBuf::as_path));
+
+        let library = match ShaderLibrary::compile(
+            String::from("Single pass shader library"),
+            &source,
+            &self.device.device,
+        ) {
+            Ok(library) => library,
+            Err(e) => {
+                log::error!("{} failed to compile:\n{}", self.shader_path.display(), e);
+                return;
+            }
+        };
+
+        self.pipeline_descriptor
+            .setVertexFunction(Some(library.vertex.as_ref()));
+        self.pipeline_descriptor
+            .setFragmentFunction(Some(library.fragment.as_ref()));
+        match self
+            .device
+            .device
+            .newRenderPipelineStateWithDescriptor_error(&self.pipeline_descriptor)
+        {
+            Ok(pipeline) => {
+                self.pass.borrow_mut().set_pipeline(pipeline);
+                log::info!("reloaded {}", self.shader_path.display());
+            }
+            Err(e) => log::error!(
+                "could not create a pipeline with the new shaders: {}",
+                e.localizedDescription()
+            ),
+        }
+    }
+
+    // B saves the current view, 1-9 jump to a bookmark, P plays a path
+    fn handle_bookmarks(&self, camera: &mut Camera, now: f64) {
+        let mut bookmarks = self.bookmarks.borrow_mut();
+        let mut camera_path = self.camera_path.borrow_mut();
+
+        if Key::B.was_pressed() {
+            let pose = match self.active_camera.get() {
+                Some(i) => CameraPose::from_camera(&self.scene_cameras.borrow()[i]),
+                None => CameraPose::from_camera(camera),
+            };
+            let name = bookmarks.add(pose).to_string();
+            let saved = match self.level_path.borrow().as_ref() {
+                Some(path) => level::save(path, &self.scene.borrow(), &bookmarks, &self.catalog),
+                None => bookmarks.save(&bookmarks_path(&self.scene.borrow().objects[0].entry)),
+            };
+            match saved {
+                Ok(()) => log::info!("saved bookmark {:?}", name),
+                Err(e) => log::error!("could not save bookmarks: {}", e),
+            }
+        }
+
+        for (i, key) in BOOKMARK_KEYS.iter().enumerate() {
+            if !key.was_pressed() {
+                continue;
+            }
+            if let Some((name, pose)) = bookmarks.bookmarks.get(i) {
+                log::info!("jumping to bookmark {:?}", name);
+                pose.apply(camera);
+                self.active_camera.set(None);
+                *camera_path = None;
+            }
+        }
+
+        if Key::P.was_pressed() {
+            if camera_path.take().is_none() {
+                match bookmarks.path(0) {
+                    Some(path) => {
+                        self.active_camera.set(None);
+                        *camera_path = Some(PathPlayer::new(path, now));
+                    }
+                    None => log::warn!("no camera path to play"),
+                }
+            }
+        }
+
+        if let Some(player) = camera_path.as_mut() {
+            if !player.update(camera, now) {
+                *camera_path = None;
+            }
+        }
+    }
 }
 
-pub fn init() -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
+pub fn init(
+    level: &Level,
+    level_path: Option<&Path>,
+    catalog: &[AssetEntry],
+) -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
     let mtm = MainThreadMarker::new().unwrap();
 
     let window = {
@@ -83,6 +583,8 @@ pub fn init() -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
         let frame_rect = window.frame();
         let mtk_view = MTKView::initWithFrame(MTKView::alloc(mtm), frame_rect);
         mtk_view.setDevice(Some(&device));
+        // the shader works in linear, writes get encoded back to sRGB
+        mtk_view.setColorPixelFormat(MTLPixelFormat::BGRA8Unorm_sRGB);
         mtk_view.setDepthStencilPixelFormat(MTLPixelFormat::Depth32Float);
 
         mtk_view
@@ -97,10 +599,6 @@ pub fn init() -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
             .setPixelFormat(view.colorPixelFormat());
     }
 
-    //
-    // init Metal Kit Texture Loader
-    let mtk_tex_loader = MTKTextureLoader::initWithDevice(MTKTextureLoader::alloc(), &device);
-
     let shader_lib = ShaderLibrary::new(
         String::from("Single pass shader library"),
         String::from("./src/shaders/normals.metallib"),
@@ -111,16 +609,16 @@ pub fn init() -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
     // Add depth stencil attachment
     pipeline_descriptor.setDepthAttachmentPixelFormat(MTLPixelFormat::Depth32Float);
 
+    // linear, the same blue as before the drawable went sRGB
     view.setClearColor(MTLClearColor {
-        red: 0.2,
-        green: 0.2,
-        blue: 0.8,
+        red: 0.033,
+        green: 0.033,
+        blue: 0.604,
         alpha: 1.0,
     });
 
     window.setContentView(Some(&view));
     window.center();
-    window.setTitle(ns_string!("fortnite_source_code_leaked"));
     window.makeKeyAndOrderFront(None);
 
     // Depth stencil
@@ -131,150 +629,23 @@ pub fn init() -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
         .newDepthStencilStateWithDescriptor(&depth_stencil_descriptor)
         .expect("Failed to create depth stencil state");
 
-    let gltf_path = format!("./assets/{}/glTF/{}.gltf", GLTF_NAME, GLTF_NAME);
-    let (document, buffers, images) = gltf::import(gltf_path).expect("could not import glTF");
-    assert_eq!(buffers.len(), document.buffers().count());
-    assert_eq!(images.len(), document.images().count());
-
-    let mut all_meshes = Vec::new();
-
-    let key = unsafe { MTKTextureLoaderOptionAllocateMipmaps };
-    let value = NSNumber::numberWithBool(true);
-    let options = NSDictionary::from_slices(&[key], &[&*value as &AnyObject]);
-
-    let mipmap_command_buffer = command_queue
-        .commandBuffer()
-        .expect("Failed to create mipmap command buffer");
-    let mipmap_blit_encoder = mipmap_command_buffer
-        .blitCommandEncoder()
-        .expect("Failed to create mipmap blit encoder");
-
-    // FIXME: This is kind of horible
-    for mesh in document.meshes() {
-        for primitive in mesh.primitives() {
-            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-
-            let positions: Vec<[f32; 3]> = reader.read_positions().expect("No positions").collect();
-
-            let normals: Vec<[f32; 3]> = reader.read_normals().expect("No normals").collect();
-
-            let uvs: Vec<[f32; 2]> = reader
-                .read_tex_coords(0)
-                .expect("no texture coordinates")
-                .into_f32()
-                .collect();
-
-            let indices: Vec<u32> = reader
-                .read_indices()
-                .expect("No indices")
-                .into_u32()
-                .collect();
-
-            let num_vertices = positions.len();
-            let stride = std::mem::size_of::<[f32; 8]>();
-
-            // allocate buffers
-            // interleave all attributes into a single buffer
-            let buffer = Buffer::new(
-                &device,
-                num_vertices,
-                stride,
-                MTLResourceOptions::StorageModeShared,
-                BufferKind::POSITIONS,
-            );
-
-            // fill the buffer with data
-            unsafe {
-                let contents = buffer.buffer.contents().as_ptr() as *mut u8;
-                for i in 0..num_vertices {
-                    let offset = i * stride;
-
-                    std::ptr::copy_nonoverlapping(
-                        positions[i].as_ptr() as *const u8,
-                        contents.add(offset + 0),
-                        12,
-                    );
-
-                    std::ptr::copy_nonoverlapping(
-                        normals[i].as_ptr() as *const u8,
-                        contents.add(offset + 12),
-                        12,
-                    );
-
-                    std::ptr::copy_nonoverlapping(
-                        uvs[i].as_ptr() as *const u8,
-                        contents.add(offset + 24),
-                        8,
-                    );
-                }
-            }
-
-            // TODO: more generic buffer create?
-            let index_buffer = device
-                .newBufferWithLength_options(
-                    (indices.len() * std::mem::size_of::<[i32; 3]>()) as NSUInteger,
-                    MTLResourceOptions::StorageModeShared,
-                )
-                .expect("Failed to create index buffer");
-
-            unsafe {
-                let contents = index_buffer.contents().as_ptr() as *mut u32;
-                std::ptr::copy_nonoverlapping(indices.as_ptr(), contents, indices.len());
-            }
-
-            let material = primitive.material();
-
-            let texture = if let Some(tex) = material.pbr_metallic_roughness().base_color_texture()
-            {
-                let image = tex.texture().source();
-
-                match image.source() {
-                    gltf::image::Source::Uri { uri, .. } => {
-                        let full_path = format!("./assets/{}/glTF/{}", GLTF_NAME, uri);
-                        let path_to_tex = NSURL::fileURLWithPath(&NSString::from_str(&full_path));
-
-                        let texture = unsafe {
-                            mtk_tex_loader
-                                .newTextureWithContentsOfURL_options_error(
-                                    &path_to_tex,
-                                    Some(&options),
-                                )
-                                .expect("Failed to load texture from file")
-                        };
-
-                        mipmap_blit_encoder.generateMipmapsForTexture(&texture);
-
-                        Some(texture)
-                    }
-                    gltf::image::Source::View { .. } => None,
-                }
-            } else {
-                None
-            };
-
-            let mut all_buffers = Vec::new();
-            all_buffers.push(buffer);
-
-            let model = Mat4::from_rotation_x(f32::to_radians(-15.0));
-
-            let mut materials = Vec::new();
-            materials.push(texture);
-
-            let submesh = Mesh::new(
-                all_buffers,
-                index_buffer,
-                materials,
-                indices.len(),
-                MTLPrimitiveType::Triangle,
-                model,
-            );
+    let device = Device {
+        device,
+        command_queue,
+    };
 
-            all_meshes.push(submesh);
+    // reported like level::load errors in main
+    let scene = level.build_scene(&device).unwrap_or_else(|e| {
+        match level_path {
+            Some(path) => eprintln!("{}: {}", path.display(), e),
+            None => eprintln!("{}", e),
         }
-    }
+        std::process::exit(1);
+    });
+    window.setTitle(&NSString::from_str(&window_title(&scene)));
 
-    mipmap_blit_encoder.endEncoding();
-    mipmap_command_buffer.commit();
+    let mut watcher = FileWatcher::default();
+    scene.watch(&mut watcher);
 
     // TODO: Move to resource module
     // A MTLVertexDescriptor has attributes and layouts
@@ -296,11 +667,22 @@ pub fn init() -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
         uv_attr.setFormat(MTLVertexFormat::Float2);
         uv_attr.setOffset(24);
         uv_attr.setBufferIndex(1);
+
+        let tangent_attr = vertex_descriptor.attributes().objectAtIndexedSubscript(3);
+        tangent_attr.setFormat(MTLVertexFormat::Float4);
+        tangent_attr.setOffset(32);
+        tangent_attr.setBufferIndex(1);
+
+        // TEXCOORD_1, for textures that ask for the second uv set
+        let uv1_attr = vertex_descriptor.attributes().objectAtIndexedSubscript(4);
+        uv1_attr.setFormat(MTLVertexFormat::Float2);
+        uv1_attr.setOffset(48);
+        uv1_attr.setBufferIndex(1);
     }
 
     unsafe {
         let layout = vertex_descriptor.layouts().objectAtIndexedSubscript(1);
-        layout.setStride(std::mem::size_of::<[f32; 8]>() as NSUInteger);
+        layout.setStride(std::mem::size_of::<[f32; 14]>() as NSUInteger);
         layout.setStepFunction(MTLVertexStepFunction::PerVertex);
         layout.setStepRate(1);
     }
@@ -309,42 +691,79 @@ pub fn init() -> (AppState, Retained<NSWindow>, Retained<MTKView>) {
     pipeline_descriptor.setVertexDescriptor(Some(&vertex_descriptor));
 
     let pipeline_state = device
+        .device
         .newRenderPipelineStateWithDescriptor_error(&pipeline_descriptor)
         .expect("Failed to create pipeline state");
 
-    let cam_position = Vec3::new(0.0, 10.0, 0.0);
-    let cam_target = Vec3::new(0.0, 0.0, 0.0);
-    let camera = Camera::new(
-        cam_position,
-        cam_target,
-        Vec3::normalize(cam_position - cam_target), // direction
-        Vec3::new(0.0, 0.0, -1.0),                  // front, Looking at -Z
-        Vec3::new(0.0, 1.0, 0.0),                   // up
-        -90.0,                                      // yaw
-        0.0,                                        // pitch
-    );
+    let scene_cameras = scene.cameras();
+    let (camera, active_camera) = start_camera(&scene_cameras);
+    let bookmarks = level.bookmarks.clone();
+    let selected = level
+        .instances
+        .first()
+        .and_then(|first| {
+            catalog
+                .iter()
+                .position(|entry| entry.model == first.entry.model)
+        })
+        .unwrap_or(0);
 
     let pass = SinglePass::new(pipeline_state, depth_stencil_state);
 
+    // build.rs compiled the metallib loaded above, edits to its source get
+    // compiled at runtime
+    let shader_path = PathBuf::from("./src/shaders/normals.metal");
+    let mut shader_watcher = FileWatcher::default();
+    match ShaderSource::read(&shader_path) {
+        Ok(source) => shader_watcher.watch_only(source.files.iter().map(PathBuf::as_path)),
+        Err(e) => log::warn!("not watching {}: {}", shader_path.display(), e),
+    }
+
     let app_state = AppState {
         start_date: NSDate::now(),
-        device: Device {
-            device,
-            command_queue,
-        },
-        model: Asset {
-            meshes: all_meshes,
-            name: "Box".to_string(),
-        },
+        game_loop: RefCell::new(GameLoop::default()),
+        in_flight: RefCell::new(vec![None; FRAMES_IN_FLIGHT]),
+        frame_count: Cell::new(0),
+        device,
+        scene: RefCell::new(scene),
+        level_path: RefCell::new(level_path.map(Path::to_path_buf)),
+        catalog: catalog.to_vec(),
+        selected: Cell::new(selected),
+        watcher: RefCell::new(watcher),
         camera: RefCell::new(camera),
-        pass,
+        scene_cameras: RefCell::new(scene_cameras),
+        active_camera: Cell::new(active_camera),
+        bookmarks: RefCell::new(bookmarks),
+        camera_path: RefCell::new(None),
+        pass: RefCell::new(pass),
+        pipeline_descriptor,
+        shader_path,
+        shader_watcher: RefCell::new(shader_watcher),
     };
     (app_state, window, view)
 }
 
 pub fn frame(view: &MTKView, state: &AppState) {
+    // before the camera is borrowed, opening an asset resets it
+    state.handle_browser(view);
+
+    if Key::TAB.was_pressed() {
+        state.cycle_camera();
+    }
+    if Key::K.was_pressed() {
+        state.save_level();
+    }
+    if Key::X.was_pressed() {
+        state.export_scene();
+    }
+
+    // movement only drives the free camera, scene cameras stay put
     let mut camera = state.camera.borrow_mut();
 
+    // seconds since start, drives everything time based this frame
+    let time = -state.start_date.timeIntervalSinceNow();
+    state.handle_bookmarks(&mut camera, time);
+
     let move_speed = 4.0;
 
     let direction = Vec3::new(
@@ -401,6 +820,17 @@ pub fn frame(view: &MTKView, state: &AppState) {
         camera.pitch = -89.0;
     }
 
+    state.reload_changed(time);
+    state.reload_shaders(time);
+
+    let frame_index = state.frame_count.get();
+    let slot = frame_index % FRAMES_IN_FLIGHT;
+    if let Some(previous) = state.in_flight.borrow_mut()[slot].take() {
+        previous.waitUntilCompleted();
+    }
+    state.scene.borrow_mut().frame = frame_index;
+    state.update_scene(time);
+
     let Some(drawable) = view.currentDrawable() else {
         return;
     };
@@ -414,41 +844,234 @@ pub fn frame(view: &MTKView, state: &AppState) {
         return;
     };
 
+    let scene_cameras = state.scene_cameras.borrow();
+    let active = match state.active_camera.get() {
+        Some(i) => &scene_cameras[i],
+        None => &*camera,
+    };
+
     // https://learnopengl.com/Getting-started/Camera
     let aspect_ratio = WINDOW_W as f32 / WINDOW_H as f32;
-    let projection = glam::Mat4::perspective_rh(
-        f32::to_radians(60.0),
-        aspect_ratio,
-        0.025,  // near plane
-        8000.0, // far plane
-    );
+    let projection = active.projection.matrix(aspect_ratio);
 
     // Update camera uniform
-    let view = Mat4::look_at_rh(camera.position, camera.position + camera.front, camera.up);
+    let view = active.view_matrix();
     let view_proj = projection * view;
-    let time = state.start_date.timeIntervalSinceNow() as f32;
 
     let model = Mat4::ZERO;
+    // the shaders only animate with it, they can do with less precision
+    let time = time as f32;
     let uniforms = Uniforms {
         view_proj,
         time,
         model,
     };
 
-    state.pass.render(&encoder, &uniforms, &state.model, time);
+    let scene = state.scene.borrow();
+    state
+        .pass
+        .borrow()
+        .render(&encoder, &uniforms, &draw_list(&scene), time);
 
     encoder.endEncoding();
     command_buffer.presentDrawable(ProtocolObject::from_ref(&*drawable));
     command_buffer.commit();
+    state.in_flight.borrow_mut()[slot] = Some(command_buffer);
+    state.frame_count.set(frame_index + 1);
+}
+
+// The scene still needs a device for its buffers, nothing is drawn
+fn simulate(path: &Path, seconds: f64, fps: f64) {
+    let catalog = catalog::scan(Path::new(catalog::ASSETS_DIR));
+    let level = level::load(path, &catalog).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+    let device = Device::system_default();
+    let mut scene = level.build_scene(&device).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        std::process::exit(1);
+    });
+
+    let game_loop = game_loop::run_headless(&mut scene, seconds, 1.0 / fps);
+    println!(
+        "{} ticks, {:.3}s simulated, {:.3}s dropped",
+        game_loop.ticks(),
+        game_loop.time(),
+        game_loop.dropped()
+    );
+    for object in &scene.objects {
+        if let Some(transform) = scene.world.get::<Transform>(object.entity) {
+            let (_, rotation, translation) = transform.world().to_scale_rotation_translation();
+            let (axis, angle) = rotation.to_axis_angle();
+            println!(
+                "{:<24} at {:.3} turned {:.2} degrees around {:.3}",
+                object.entry.name,
+                translation,
+                angle.to_degrees(),
+                axis
+            );
+        }
+    }
 }
 
 fn main() {
+    env_logger::init();
+
+    // `bs compress <model>` bakes the model's textures and exits
+    let args: Vec<String> = std::env::args().collect();
+    if let [_, command, path] = args.as_slice()
+        && command == "compress"
+    {
+        match compress::compress_model(Path::new(path)) {
+            Ok(count) => println!("compressed {} textures", count),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `bs cook <model>` writes the cooked file the next launch maps
+    if let [_, command, path] = args.as_slice()
+        && command == "cook"
+    {
+        match cook::cook(Path::new(path)) {
+            Ok(cooked) => println!("wrote {}", cooked.display()),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `bs export <model or level> <out.gltf or .glb>` writes it back out as
+    // glTF, a level as one static model
+    if let [_, command, input, output] = args.as_slice()
+        && command == "export"
+    {
+        let input = Path::new(input);
+        let model: Result<ModelData, Box<dyn Error>> =
+            if input.extension().is_some_and(|e| e == "json") {
+                let catalog = catalog::scan(Path::new(catalog::ASSETS_DIR));
+                level::load(input, &catalog)
+                    .and_then(|level| level.flatten())
+                    .map_err(Into::into)
+            } else {
+                model::load(input).map_err(Into::into)
+            };
+        let model = model.unwrap_or_else(|e| {
+            eprintln!("{}: {}", input.display(), e);
+            std::process::exit(1);
+        });
+        if let Err(e) = gltf_export::write(&model, Path::new(output)) {
+            eprintln!("{}: {}", output, e);
+            std::process::exit(1);
+        }
+        println!("wrote {}", output);
+        return;
+    }
+
+    // `bs simulate <level> <seconds> [fps]` runs the level's simulation
+    // without a window, as if frames came `fps` times a second (60 by
+    // default), and prints where everything ended up
+    if let [_, command, path, seconds, rest @ ..] = args.as_slice()
+        && command == "simulate"
+        && rest.len() <= 1
+    {
+        let parse = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|&v| v > 0.0)
+                .unwrap_or_else(|| {
+                    eprintln!("{} is not a positive number", value);
+                    std::process::exit(1);
+                })
+        };
+        let seconds = parse(seconds);
+        let fps = rest.first().map_or(60.0, |fps| parse(fps));
+        simulate(Path::new(path), seconds, fps);
+        return;
+    }
+
+    // `bs --list` prints what can be opened, `bs [asset or model file...]`
+    // opens them together, Sponza by default. `bs --level <file>` opens a
+    // saved scene
+    let catalog = catalog::scan(Path::new(catalog::ASSETS_DIR));
+    let (level, level_path) = if let [_, flag, path] = args.as_slice()
+        && flag == "--level"
+    {
+        let path = PathBuf::from(path);
+        let level = level::load(&path, &catalog).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        (level, Some(path))
+    } else {
+        let names: Vec<&str> = match args.as_slice() {
+            [_] => vec![DEFAULT_ASSET],
+            [_, flag] if flag == "--list" => {
+                for entry in &catalog {
+                    println!(
+                        "{:<16} {} - {} [{}]",
+                        entry.dir.file_name().unwrap_or_default().to_string_lossy(),
+                        entry.name,
+                        entry.summary,
+                        entry.tags.join(", ")
+                    );
+                }
+                return;
+            }
+            [_, names @ ..] if !names.iter().any(|name| name.starts_with('-')) => {
+                names.iter().map(String::as_str).collect()
+            }
+            _ => {
+                eprintln!(
+                    "usage: bs [asset | model file]... | --level <file> | --list | compress <model> | cook <model> | export <model | level> <out> | simulate <level> <seconds> [fps]"
+                );
+                std::process::exit(1);
+            }
+        };
+        let assets: Vec<AssetEntry> = names
+            .iter()
+            .map(|name| {
+                catalog::find(&catalog, name).unwrap_or_else(|| {
+                    eprintln!("no asset or model file named {} (see bs --list)", name);
+                    std::process::exit(1);
+                })
+            })
+            .collect();
+
+        // all at the origin, with the first one's bookmarks
+        let level = Level {
+            bookmarks: load_bookmarks(&assets[0]),
+            instances: assets
+                .into_iter()
+                .map(|entry| Instance {
+                    entry,
+                    root: scene_root(),
+                    parent: None,
+                    overrides: Vec::new(),
+                    spin: None,
+                })
+                .collect(),
+            lights: Vec::new(),
+        };
+        (level, None)
+    };
+
     let mtm = MainThreadMarker::new().unwrap();
     let app = NSApplication::sharedApplication(mtm);
     app.setActivationPolicy(NSApplicationActivationPolicy::Regular);
     let delegate: Retained<Delegate> = unsafe {
         let this = Delegate::alloc(mtm).set_ivars(Ivars {
             state: RefCell::new(None),
+            level,
+            level_path,
+            catalog,
         });
         msg_send![super(this), init]
     };
@@ -0,0 +1,616 @@
+// Decoder for EXT_meshopt_compression buffer views: the meshoptimizer vertex
+// codec (version 0), the index codec (versions 0 and 1), the index sequence
+// codec and the octahedral, quaternion and exponential filters.
+// Ported from the reference decoder without the SIMD paths, the bounds
+// checks are the same ones it relies on so malformed data can't read past
+// the end
+
+pub type Result<T> = std::result::Result<T, String>;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Mode {
+    Attributes,
+    Triangles,
+    Indices,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Filter {
+    None,
+    Octahedral,
+    Quaternion,
+    Exponential,
+}
+
+// `count` elements of `stride` bytes each, the layout of the uncompressed
+// bufferView
+pub fn decode(
+    data: &[u8],
+    count: usize,
+    stride: usize,
+    mode: Mode,
+    filter: Filter,
+) -> Result<Vec<u8>> {
+    let mut out = vec![0u8; count * stride];
+
+    match mode {
+        Mode::Attributes => {
+            if stride == 0 || stride > 256 || !stride.is_multiple_of(4) {
+                return Err(format!("invalid vertex stride {}", stride));
+            }
+            decode_vertices(&mut out, count, stride, data)?;
+        }
+        Mode::Triangles | Mode::Indices => {
+            if stride != 2 && stride != 4 {
+                return Err(format!("invalid index size {}", stride));
+            }
+            let indices = match mode {
+                Mode::Triangles => decode_triangles(count, data)?,
+                _ => decode_sequence(count, data)?,
+            };
+            for (chunk, index) in out.chunks_exact_mut(stride).zip(indices) {
+                match stride {
+                    2 => chunk.copy_from_slice(&(index as u16).to_le_bytes()),
+                    _ => chunk.copy_from_slice(&index.to_le_bytes()),
+                }
+            }
+        }
+    }
+
+    match filter {
+        Filter::None => {}
+        Filter::Octahedral => match stride {
+            4 => octahedral::<1>(&mut out),
+            8 => octahedral::<2>(&mut out),
+            _ => return Err(format!("invalid octahedral filter stride {}", stride)),
+        },
+        Filter::Quaternion if stride == 8 => quaternion(&mut out),
+        Filter::Quaternion => return Err(format!("invalid quaternion filter stride {}", stride)),
+        Filter::Exponential if stride.is_multiple_of(4) => exponential(&mut out),
+        Filter::Exponential => return Err(format!("invalid exponential filter stride {}", stride)),
+    }
+
+    Ok(out)
+}
+
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const SEQUENCE_HEADER: u8 = 0xd0;
+
+const BLOCK_SIZE_BYTES: usize = 8192;
+const BLOCK_MAX_SIZE: usize = 256;
+const GROUP_SIZE: usize = 16;
+const TAIL_MAX_SIZE: usize = 32;
+
+// Vertices are split into blocks and each byte of the vertex is stored as
+// its own stream of zigzagged deltas from the previous vertex. The first
+// vertex the deltas start from is stored at the very end
+fn decode_vertices(out: &mut [u8], count: usize, stride: usize, data: &[u8]) -> Result<()> {
+    if data.len() < 1 + stride {
+        return Err("vertex buffer too short".into());
+    }
+    if data[0] != VERTEX_HEADER {
+        return Err(format!("unsupported vertex codec header {:#x}", data[0]));
+    }
+
+    let mut last = data[data.len() - stride..].to_vec();
+    // getVertexBlockSize, a whole block has to fit in 8KB and the count
+    // stays a multiple of the byte group size
+    let block_size = ((BLOCK_SIZE_BYTES / stride) & !(GROUP_SIZE - 1)).min(BLOCK_MAX_SIZE);
+
+    let mut pos = 1;
+    let mut deltas = [0u8; BLOCK_MAX_SIZE];
+    for start in (0..count).step_by(block_size) {
+        let block = block_size.min(count - start);
+        let aligned = block.next_multiple_of(GROUP_SIZE);
+
+        for k in 0..stride {
+            pos = decode_bytes(data, pos, &mut deltas[..aligned])?;
+
+            let mut p = last[k];
+            for i in 0..block {
+                let delta = deltas[i];
+                p = p.wrapping_add((delta >> 1) ^ (delta & 1).wrapping_neg());
+                out[(start + i) * stride + k] = p;
+            }
+            last[k] = p;
+        }
+    }
+
+    let tail = stride.max(TAIL_MAX_SIZE);
+    if data.len() - pos != tail {
+        return Err("vertex buffer has trailing data".into());
+    }
+    Ok(())
+}
+
+// Groups of 16 bytes, each stored with 0, 2, 4 or 8 bits per byte. The
+// 2 and 4 bit encodings use the all-ones value to mean the byte follows the
+// group in full
+fn decode_bytes(data: &[u8], mut pos: usize, out: &mut [u8]) -> Result<usize> {
+    let header_size = (out.len() / GROUP_SIZE).div_ceil(4);
+    if data.len() - pos < header_size {
+        return Err("vertex buffer too short".into());
+    }
+    let header = pos;
+    pos += header_size;
+
+    for (group, bytes) in out.chunks_exact_mut(GROUP_SIZE).enumerate() {
+        // every group reads at most 24 bytes, the tail is always at least 32
+        if data.len() - pos < TAIL_MAX_SIZE {
+            return Err("vertex buffer too short".into());
+        }
+        let bits_log2 = (data[header + group / 4] >> ((group % 4) * 2)) & 3;
+        pos = match bits_log2 {
+            0 => {
+                bytes.fill(0);
+                pos
+            }
+            3 => {
+                bytes.copy_from_slice(&data[pos..pos + GROUP_SIZE]);
+                pos + GROUP_SIZE
+            }
+            _ => {
+                let bits = 1 << bits_log2;
+                let mut var = pos + bits * 2;
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    let shift = 8 - bits - (i * bits) % 8;
+                    let enc = (data[pos + i * bits / 8] >> shift) & ((1 << bits) - 1);
+                    *byte = if enc == (1 << bits) - 1 {
+                        var += 1;
+                        data[var - 1]
+                    } else {
+                        enc
+                    };
+                }
+                var
+            }
+        };
+    }
+
+    Ok(pos)
+}
+
+fn vbyte(data: &[u8], pos: &mut usize) -> u32 {
+    let lead = data[*pos];
+    *pos += 1;
+    if lead < 128 {
+        return lead as u32;
+    }
+
+    // at most four more bytes, so malformed data still terminates
+    let mut result = (lead & 127) as u32;
+    let mut shift = 7;
+    for _ in 0..4 {
+        let group = data[*pos];
+        *pos += 1;
+        result |= ((group & 127) as u32) << shift;
+        shift += 7;
+        if group < 128 {
+            break;
+        }
+    }
+    result
+}
+
+fn unzigzag(v: u32) -> u32 {
+    (v >> 1) ^ (v & 1).wrapping_neg()
+}
+
+// Triangles are coded against a FIFO of recent edges and one of recent
+// vertices. Vertices that are neither come as deltas from the last one read
+fn decode_triangles(count: usize, data: &[u8]) -> Result<Vec<u32>> {
+    if !count.is_multiple_of(3) {
+        return Err("index count isn't a multiple of 3".into());
+    }
+    // header, one code per triangle and the 16 byte codeaux table
+    if data.len() < 1 + count / 3 + 16 {
+        return Err("index buffer too short".into());
+    }
+    if data[0] & 0xf0 != INDEX_HEADER {
+        return Err(format!("unsupported index codec header {:#x}", data[0]));
+    }
+    let version = data[0] & 0x0f;
+    if version > 1 {
+        return Err(format!("unsupported index codec version {}", version));
+    }
+    // version 1 uses 13 and 14 for the last free index -1 and +1
+    let fec_max = if version >= 1 { 13 } else { 15 };
+
+    let mut edges = [[u32::MAX; 2]; 16];
+    let mut vertices = [u32::MAX; 16];
+    let mut edge_offset = 0usize;
+    let mut vertex_offset = 0usize;
+
+    let push_edge = |edges: &mut [[u32; 2]; 16], offset: &mut usize, a: u32, b: u32| {
+        edges[*offset] = [a, b];
+        *offset = (*offset + 1) & 15;
+    };
+    let push_vertex = |vertices: &mut [u32; 16], offset: &mut usize, v: u32, cond: bool| {
+        vertices[*offset] = v;
+        *offset = (*offset + cond as usize) & 15;
+    };
+
+    let mut next = 0u32;
+    let mut last = 0u32;
+
+    let codes = &data[1..1 + count / 3];
+    let mut pos = 1 + count / 3;
+    let safe_end = data.len() - 16;
+    let codeaux_table = &data[safe_end..];
+
+    let mut indices = Vec::with_capacity(count);
+    for &codetri in codes {
+        // a triangle reads at most 16 bytes: codeaux and three free indices
+        if pos > safe_end {
+            return Err("index buffer too short".into());
+        }
+
+        let [a, b, c];
+        if codetri < 0xf0 {
+            let fe = (codetri >> 4) as usize;
+            [a, b] = edges[edge_offset.wrapping_sub(1 + fe) & 15];
+
+            let fec = codetri & 15;
+            if fec < fec_max {
+                c = if fec == 0 {
+                    next
+                } else {
+                    vertices[vertex_offset.wrapping_sub(1 + fec as usize) & 15]
+                };
+                next += (fec == 0) as u32;
+                push_vertex(&mut vertices, &mut vertex_offset, c, fec == 0);
+            } else {
+                c = match fec {
+                    15 => last.wrapping_add(unzigzag(vbyte(data, &mut pos))),
+                    13 => last.wrapping_sub(1),
+                    _ => last.wrapping_add(1),
+                };
+                last = c;
+                push_vertex(&mut vertices, &mut vertex_offset, c, true);
+            }
+
+            push_edge(&mut edges, &mut edge_offset, c, b);
+            push_edge(&mut edges, &mut edge_offset, a, c);
+        } else {
+            // 0xf0..0xfd take the codeaux byte from the table, 0xfe and 0xff
+            // store it inline and may start with a free index
+            let (fea, codeaux) = if codetri < 0xfe {
+                (0, codeaux_table[(codetri & 15) as usize])
+            } else {
+                pos += 1;
+                (if codetri == 0xfe { 0 } else { 15 }, data[pos - 1])
+            };
+            let feb = (codeaux >> 4) as usize;
+            let fec = (codeaux & 15) as usize;
+
+            // next advances for all three before any free index is read,
+            // matching the encoder
+            let mut vertex = |fe: usize| match fe {
+                0 => {
+                    next += 1;
+                    next - 1
+                }
+                15 => 0,
+                _ => vertices[vertex_offset.wrapping_sub(fe) & 15],
+            };
+            let (mut va, mut vb, mut vc) = (vertex(fea), vertex(feb), vertex(fec));
+            for (fe, v) in [(fea, &mut va), (feb, &mut vb), (fec, &mut vc)] {
+                if fe == 15 {
+                    last = last.wrapping_add(unzigzag(vbyte(data, &mut pos)));
+                    *v = last;
+                }
+            }
+            [a, b, c] = [va, vb, vc];
+
+            push_vertex(&mut vertices, &mut vertex_offset, a, true);
+            push_vertex(&mut vertices, &mut vertex_offset, b, feb == 0 || feb == 15);
+            push_vertex(&mut vertices, &mut vertex_offset, c, fec == 0 || fec == 15);
+
+            push_edge(&mut edges, &mut edge_offset, b, a);
+            push_edge(&mut edges, &mut edge_offset, c, b);
+            push_edge(&mut edges, &mut edge_offset, a, c);
+        }
+
+        indices.extend([a, b, c]);
+    }
+
+    // everything up to the codeaux table has to be used
+    if pos != safe_end {
+        return Err("index buffer has trailing data".into());
+    }
+    Ok(indices)
+}
+
+// Arbitrary index lists, each a delta from one of two running baselines
+fn decode_sequence(count: usize, data: &[u8]) -> Result<Vec<u32>> {
+    // header, at least a byte per index and a 4 byte tail
+    if data.len() < 1 + count + 4 {
+        return Err("index sequence too short".into());
+    }
+    if data[0] & 0xf0 != SEQUENCE_HEADER {
+        return Err(format!("unsupported index sequence header {:#x}", data[0]));
+    }
+    let version = data[0] & 0x0f;
+    if version > 1 {
+        return Err(format!("unsupported index sequence version {}", version));
+    }
+
+    let safe_end = data.len() - 4;
+    let mut last = [0u32; 2];
+    let mut pos = 1;
+
+    let mut indices = Vec::with_capacity(count);
+    for _ in 0..count {
+        if pos >= safe_end {
+            return Err("index sequence too short".into());
+        }
+        let v = vbyte(data, &mut pos);
+        let baseline = (v & 1) as usize;
+        let index = last[baseline].wrapping_add(unzigzag(v >> 1));
+        last[baseline] = index;
+        indices.push(index);
+    }
+
+    if pos != safe_end {
+        return Err("index sequence has trailing data".into());
+    }
+    Ok(indices)
+}
+
+// Four signed components of N bytes: octahedral x and y, z holding what 1.0
+// was encoded as, and w left alone. Writes back the unit vector
+fn octahedral<const N: usize>(data: &mut [u8]) {
+    let max = ((1 << (N * 8 - 1)) - 1) as f32;
+    let read = |bytes: &[u8]| match N {
+        1 => bytes[0] as i8 as f32,
+        _ => i16::from_le_bytes([bytes[0], bytes[1]]) as f32,
+    };
+    let write = |bytes: &mut [u8], v: f32| {
+        let v = (v + if v >= 0.0 { 0.5 } else { -0.5 }) as i32;
+        bytes.copy_from_slice(&v.to_le_bytes()[..N]);
+    };
+
+    for element in data.chunks_exact_mut(N * 4) {
+        let mut x = read(&element[..N]);
+        let mut y = read(&element[N..]);
+        let z = read(&element[N * 2..]) - x.abs() - y.abs();
+
+        // unfold the lower hemisphere
+        let t = z.min(0.0);
+        x += if x >= 0.0 { t } else { -t };
+        y += if y >= 0.0 { t } else { -t };
+
+        let s = max / (x * x + y * y + z * z).sqrt();
+        write(&mut element[..N], x * s);
+        write(&mut element[N..N * 2], y * s);
+        write(&mut element[N * 2..N * 3], z * s);
+    }
+}
+
+// Three components of a unit quaternion scaled by 1/sqrt(2), and a fourth
+// with the index of the dropped largest component in its low two bits and
+// the scale in the rest
+fn quaternion(data: &mut [u8]) {
+    let scale = std::f32::consts::FRAC_1_SQRT_2;
+
+    for element in data.chunks_exact_mut(8) {
+        let q: [i16; 4] =
+            std::array::from_fn(|i| i16::from_le_bytes([element[i * 2], element[i * 2 + 1]]));
+
+        let s = scale / (q[3] | 3) as f32;
+        let x = q[0] as f32 * s;
+        let y = q[1] as f32 * s;
+        let z = q[2] as f32 * s;
+        // precision errors can push this slightly negative
+        let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+
+        let round = |v: f32| (v * 32767.0 + if v >= 0.0 { 0.5 } else { -0.5 }) as i16;
+        let largest = (q[3] & 3) as usize;
+        for (offset, v) in [(1, x), (2, y), (3, z), (0, w)] {
+            let i = (largest + offset) & 3;
+            element[i * 2..i * 2 + 2].copy_from_slice(&round(v).to_le_bytes());
+        }
+    }
+}
+
+// 32-bit values with a 24-bit signed mantissa and an 8-bit signed exponent
+fn exponential(data: &mut [u8]) {
+    for value in data.chunks_exact_mut(4) {
+        let v = u32::from_le_bytes(value.try_into().unwrap());
+        let mantissa = ((v << 8) as i32) >> 8;
+        let exponent = (v as i32) >> 24;
+        // ldexp(mantissa, exponent), the exponent stays in float range
+        let f = f32::from_bits(((exponent + 127) as u32) << 23) * mantissa as f32;
+        value.copy_from_slice(&f.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Written by meshoptimizer 0.23 (vertex codec version 0). The filtered
+    // ones were encoded from floats with meshopt_encodeFilter*, the expected
+    // bytes are what meshopt_decodeFilter* turns them back into. The index
+    // encoder may rotate triangles, TRIANGLES is the reference decoder's
+    // output
+    const VERTICES: [u8; 320] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 192, 0, 0, 0, 0, 0, 0, 128, 62, 0, 0, 192, 63, 0, 0, 128,
+        191, 3, 0, 0, 0, 0, 0, 0, 63, 0, 0, 64, 64, 0, 0, 0, 0, 6, 0, 0, 0, 0, 0, 64, 63, 0, 0,
+        144, 64, 0, 0, 128, 63, 2, 0, 0, 0, 0, 0, 128, 63, 0, 0, 192, 64, 0, 0, 0, 64, 5, 0, 0, 0,
+        0, 0, 160, 63, 0, 0, 0, 0, 0, 0, 64, 64, 1, 0, 0, 0, 0, 0, 192, 63, 0, 0, 192, 63, 0, 0,
+        128, 64, 4, 0, 0, 0, 0, 0, 224, 63, 0, 0, 64, 64, 0, 0, 160, 64, 0, 0, 0, 0, 0, 0, 0, 64,
+        0, 0, 144, 64, 0, 0, 192, 64, 3, 0, 0, 0, 0, 0, 16, 64, 0, 0, 192, 64, 0, 0, 224, 64, 6, 0,
+        0, 0, 0, 0, 32, 64, 0, 0, 0, 0, 0, 0, 0, 65, 2, 0, 0, 0, 0, 0, 48, 64, 0, 0, 192, 63, 0, 0,
+        16, 65, 5, 0, 0, 0, 0, 0, 64, 64, 0, 0, 64, 64, 0, 0, 32, 65, 1, 0, 0, 0, 0, 0, 80, 64, 0,
+        0, 144, 64, 0, 0, 48, 65, 4, 0, 0, 0, 0, 0, 96, 64, 0, 0, 192, 64, 0, 0, 64, 65, 0, 0, 0,
+        0, 0, 0, 112, 64, 0, 0, 0, 0, 0, 0, 80, 65, 3, 0, 0, 0, 0, 0, 128, 64, 0, 0, 192, 63, 0, 0,
+        96, 65, 6, 0, 0, 0, 0, 0, 136, 64, 0, 0, 64, 64, 0, 0, 112, 65, 2, 0, 0, 0, 0, 0, 144, 64,
+        0, 0, 144, 64, 0, 0, 128, 65, 5, 0, 0, 0, 0, 0, 152, 64, 0, 0, 192, 64, 0, 0, 136, 65, 1,
+        0, 0, 0,
+    ];
+    const VERTICES_ENCODED: [u8; 163] = [
+        160, 0, 0, 7, 0, 255, 255, 128, 128, 64, 64, 64, 64, 32, 32, 32, 32, 32, 32, 32, 255, 0, 0,
+        0, 32, 16, 16, 16, 1, 56, 0, 128, 0, 124, 0, 0, 7, 0, 127, 255, 160, 96, 128, 127, 255,
+        160, 96, 128, 127, 255, 160, 96, 128, 255, 0, 0, 0, 127, 255, 160, 96, 5, 56, 62, 15, 131,
+        126, 127, 126, 127, 126, 127, 224, 0, 0, 0, 126, 0, 0, 7, 0, 255, 255, 255, 255, 128, 128,
+        64, 64, 64, 64, 32, 32, 32, 32, 32, 255, 0, 0, 0, 32, 32, 32, 16, 1, 31, 128, 8, 0, 130,
+        126, 10, 6, 103, 103, 103, 102, 118, 118, 118, 103, 103, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 192, 0, 0, 0, 0,
+    ];
+    const BLOCKS_ENCODED: [u8; 357] = [
+        160, 85, 85, 85, 85, 42, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+        170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+        170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+        170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170,
+        14, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 85, 85, 85, 85, 2, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8,
+        32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32,
+        130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8,
+        32, 130, 0, 0, 0, 0, 21, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 170, 0, 42, 238,
+        238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+        238, 238, 238, 0, 0, 21, 8, 32, 130, 8, 32, 130, 8, 32, 130, 8, 32, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 85,
+    ];
+    const TRIANGLES_V0: [u8; 27] = [
+        224, 240, 16, 0, 16, 96, 18, 240, 25, 24, 200, 0, 118, 135, 86, 103, 120, 169, 134, 101,
+        137, 104, 152, 1, 105, 0, 0,
+    ];
+    const TRIANGLES_V1: [u8; 27] = [
+        225, 240, 16, 0, 16, 96, 18, 240, 25, 24, 200, 0, 118, 135, 86, 103, 120, 169, 134, 101,
+        137, 104, 152, 1, 105, 0, 0,
+    ];
+    const TRIANGLES: [u8; 120] = [
+        0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 3, 0, 0, 0, 2, 0, 0, 0, 3, 0,
+        0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 3, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 6, 0, 0, 0,
+        6, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0, 7, 0, 0, 0, 8, 0, 0, 0, 9, 0, 0, 0, 9, 0, 0, 0, 8, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 8, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0, 3, 0, 0, 0, 1, 0, 0, 0,
+    ];
+    const SEQUENCE: [u8; 44] = [
+        0, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 100, 0, 0, 0, 50, 0, 0, 0, 51, 0, 0, 0, 7,
+        0, 0, 0, 7, 0, 0, 0, 112, 17, 1, 0, 3, 0, 0, 0,
+    ];
+    const SEQUENCE_ENCODED: [u8; 21] = [
+        209, 0, 4, 4, 4, 145, 3, 188, 1, 4, 243, 2, 1, 244, 137, 17, 15, 0, 0, 0, 0,
+    ];
+    const OCT8_ENCODED: [u8; 59] = [
+        160, 1, 63, 255, 0, 0, 108, 238, 166, 170, 88, 173, 170, 1, 63, 255, 0, 0, 146, 38, 74, 42,
+        212, 84, 170, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 127, 127,
+    ];
+    const OCT8: [u8; 32] = [
+        0, 0, 127, 127, 76, 102, 0, 127, 196, 76, 174, 127, 0, 129, 0, 127, 34, 189, 154, 127, 129,
+        0, 0, 127, 73, 73, 74, 127, 0, 0, 129, 127,
+    ];
+    const OCT16_ENCODED: [u8; 85] = [
+        160, 1, 63, 255, 0, 0, 73, 103, 178, 156, 153, 173, 170, 1, 63, 255, 0, 0, 108, 236, 168,
+        170, 86, 171, 170, 1, 63, 255, 0, 0, 72, 70, 139, 156, 157, 171, 170, 1, 63, 255, 0, 0,
+        146, 38, 72, 42, 214, 84, 170, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 127, 255, 127,
+    ];
+    const OCT16: [u8; 64] = [
+        0, 0, 0, 0, 255, 127, 255, 127, 204, 76, 102, 102, 0, 0, 255, 127, 144, 194, 204, 76, 20,
+        174, 255, 127, 0, 0, 1, 128, 0, 0, 255, 127, 41, 34, 140, 187, 98, 153, 255, 127, 1, 128,
+        0, 0, 0, 0, 255, 127, 229, 73, 229, 73, 231, 73, 255, 127, 0, 0, 0, 0, 1, 128, 255, 127,
+    ];
+    const QUAT_ENCODED: [u8; 91] = [
+        160, 1, 3, 240, 0, 0, 177, 212, 33, 1, 3, 240, 0, 0, 10, 5, 3, 1, 11, 240, 0, 0, 176, 111,
+        65, 1, 15, 240, 0, 0, 15, 4, 20, 7, 1, 27, 240, 0, 0, 177, 233, 99, 1, 63, 176, 0, 0, 14,
+        13, 10, 11, 1, 57, 208, 0, 0, 5, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 7,
+    ];
+    const QUAT: [u8; 48] = [
+        0, 0, 0, 0, 0, 0, 255, 127, 130, 90, 0, 0, 0, 0, 130, 90, 0, 0, 130, 90, 0, 0, 126, 165,
+        15, 64, 250, 63, 6, 192, 250, 63, 100, 23, 188, 46, 32, 70, 117, 93, 0, 0, 0, 0, 255, 127,
+        0, 0,
+    ];
+    const EXP_ENCODED: [u8; 108] = [
+        160, 1, 63, 0, 0, 0, 239, 127, 143, 1, 63, 0, 0, 0, 124, 3, 183, 1, 1, 0, 0, 0, 1, 63, 0,
+        0, 0, 20, 14, 33, 1, 63, 0, 0, 0, 117, 109, 228, 1, 9, 0, 0, 0, 0, 1, 63, 0, 0, 0, 19, 19,
+        62, 1, 3, 0, 0, 0, 136, 1, 63, 0, 0, 0, 39, 216, 11, 1, 8, 0, 0, 0, 1, 41, 0, 0, 0, 0, 0,
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 242, 0, 32, 0, 243, 0, 224,
+        255, 243,
+    ];
+    const EXP: [u8; 48] = [
+        0, 0, 0, 0, 0, 0, 128, 63, 0, 0, 128, 191, 0, 32, 122, 68, 0, 20, 131, 58, 0, 0, 80, 192,
+        0, 32, 241, 71, 0, 56, 134, 53, 0, 0, 224, 64, 0, 0, 0, 191, 0, 0, 0, 69, 0, 16, 73, 64,
+    ];
+
+    fn words(bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn vertices() {
+        let out = decode(&VERTICES_ENCODED, 20, 16, Mode::Attributes, Filter::None).unwrap();
+        assert_eq!(out, VERTICES);
+
+        let blocks: Vec<u8> = (0..300usize)
+            .flat_map(|i| [i as u8, (i * 7) as u8, (i / 3) as u8, 0x55])
+            .collect();
+        let out = decode(&BLOCKS_ENCODED, 300, 4, Mode::Attributes, Filter::None).unwrap();
+        assert_eq!(out, blocks);
+    }
+
+    #[test]
+    fn triangles() {
+        for encoded in [&TRIANGLES_V0[..], &TRIANGLES_V1[..]] {
+            let out = decode(encoded, 30, 4, Mode::Triangles, Filter::None).unwrap();
+            assert_eq!(out, TRIANGLES);
+
+            let out = decode(encoded, 30, 2, Mode::Triangles, Filter::None).unwrap();
+            let indices: Vec<u32> = out
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]) as u32)
+                .collect();
+            assert_eq!(indices, words(&TRIANGLES));
+        }
+    }
+
+    #[test]
+    fn sequence() {
+        let out = decode(&SEQUENCE_ENCODED, 11, 4, Mode::Indices, Filter::None).unwrap();
+        assert_eq!(out, SEQUENCE);
+    }
+
+    #[test]
+    fn filters() {
+        let out = decode(&OCT8_ENCODED, 8, 4, Mode::Attributes, Filter::Octahedral).unwrap();
+        assert_eq!(out, OCT8);
+        let out = decode(&OCT16_ENCODED, 8, 8, Mode::Attributes, Filter::Octahedral).unwrap();
+        assert_eq!(out, OCT16);
+        let out = decode(&QUAT_ENCODED, 6, 8, Mode::Attributes, Filter::Quaternion).unwrap();
+        assert_eq!(out, QUAT);
+        let out = decode(&EXP_ENCODED, 4, 12, Mode::Attributes, Filter::Exponential).unwrap();
+        assert_eq!(out, EXP);
+    }
+
+    #[test]
+    fn truncated_streams_are_errors() {
+        let streams = [
+            (&VERTICES_ENCODED[..], 20, 16, Mode::Attributes),
+            (&TRIANGLES_V1[..], 30, 4, Mode::Triangles),
+            (&SEQUENCE_ENCODED[..], 11, 4, Mode::Indices),
+        ];
+        for (data, count, stride, mode) in streams {
+            for len in 0..data.len() {
+                assert!(
+                    decode(&data[..len], count, stride, mode, Filter::None).is_err(),
+                    "{:?} decoded from {} bytes",
+                    mode,
+                    len
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use glam::{Mat3, Mat4};
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::animation::{AnimationData, NodeData, SkinData};
+use crate::camera::Projection;
+use crate::{gltf_loader, obj_loader};
+
+// CPU side of a loaded model. Every importer produces one of these, the
+// renderer turns it into an `Asset` without caring where it came from
+
+// What the renderer can draw directly. Importers convert anything else
+// (loops, strips, fans) into one of these
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Topology {
+    Points,
+    Lines,
+    LineStrip,
+    Triangles,
+}
+
+pub struct MeshData {
+    pub name: String,
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    // TEXCOORD_1 and up, each as long as positions. Only the first one
+    // makes it into the vertex, the glTF importer swaps the set a material
+    // samples there
+    pub extra_uvs: Vec<Vec<[f32; 2]>>,
+    // xyz tangent, w is the bitangent sign
+    pub tangents: Vec<[f32; 4]>,
+    pub indices: Vec<u32>,
+    pub topology: Topology,
+    // index into ModelData::materials
+    pub material: Option<usize>,
+    // node world transform, identity for skinned meshes
+    pub transform: Mat4,
+    // index into ModelData::nodes
+    pub node: Option<usize>,
+    // index into ModelData::skins, joints and weights are empty without one
+    pub skin: Option<usize>,
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+    // blend shapes, deltas added to the attributes above
+    pub targets: Vec<MorphTarget>,
+    // one per target, used when nothing animates the weights
+    pub morph_weights: Vec<f32>,
+}
+
+impl MeshData {
+    // Interleaved layout the renderer uploads:
+    // position (3), normal (3), uv (2), tangent (4), second uv (2)
+    pub fn vertex(&self, i: usize) -> [f32; 14] {
+        let [px, py, pz] = self.positions[i];
+        let [nx, ny, nz] = self.normals[i];
+        let [u, v] = self.uvs[i];
+        let [tx, ty, tz, tw] = self.tangents[i];
+        let [u1, v1] = self.extra_uvs.first().map_or([0.0; 2], |uvs| uvs[i]);
+        [px, py, pz, nx, ny, nz, u, v, tx, ty, tz, tw, u1, v1]
+    }
+}
+
+// Per vertex offsets of one morph target. Attributes the target doesn't
+// move are left empty
+#[derive(Clone, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    // xyz only, the bitangent sign never changes
+    pub tangents: Vec<[f32; 3]>,
+}
+
+#[derive(Clone)]
+pub struct TextureRef {
+    pub path: PathBuf,
+    // loaded instead when `path` can't be, e.g. a PNG next to a KTX2
+    pub fallback: Option<PathBuf>,
+    // which uv set to sample with, 0 is MeshData::uvs
+    pub tex_coord: usize,
+    // applied to the uvs first, KHR_texture_transform
+    pub transform: Mat3,
+    pub sampler: SamplerData,
+    // from what the material uses it for
+    pub color_space: ColorSpace,
+}
+
+// Colors are stored sRGB encoded, anything else (occlusion, normals) as
+// plain numbers. Sampling an sRGB texture decodes it, so shading always
+// works on linear values
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+// How a texture is sampled, what a glTF sampler describes. Renderers keep
+// one sampler object per distinct value
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerData {
+    pub wrap_s: Wrap,
+    pub wrap_t: Wrap,
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    // None only ever samples the top level
+    pub mip_filter: Option<Filter>,
+}
+
+// trilinear and repeating, for anything that doesn't say otherwise
+impl Default for SamplerData {
+    fn default() -> Self {
+        Self {
+            wrap_s: Wrap::Repeat,
+            wrap_t: Wrap::Repeat,
+            mag_filter: Filter::Linear,
+            min_filter: Filter::Linear,
+            mip_filter: Some(Filter::Linear),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MaterialData {
+    pub name: String,
+    // linear RGBA, multiplied with the texture
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<TextureRef>,
+    // linear RGB, multiplied with the texture and added on top
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<TextureRef>,
+    // red channel, 1 is unoccluded. Strength blends between none and all
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    // tangent space, xyz in rgb
+    pub normal_texture: Option<TextureRef>,
+    // roughness in green, metalness in blue
+    pub metallic_roughness_texture: Option<TextureRef>,
+}
+
+impl MaterialData {
+    pub fn textures(&self) -> impl Iterator<Item = &TextureRef> {
+        self.texture_slots().into_iter().flatten()
+    }
+
+    // Every slot, empty ones included, always in this order. Cooked files
+    // store them by position
+    pub fn texture_slots(&self) -> [&Option<TextureRef>; 5] {
+        [
+            &self.base_color_texture,
+            &self.emissive_texture,
+            &self.occlusion_texture,
+            &self.normal_texture,
+            &self.metallic_roughness_texture,
+        ]
+    }
+
+    pub fn texture_slots_mut(&mut self) -> [&mut Option<TextureRef>; 5] {
+        [
+            &mut self.base_color_texture,
+            &mut self.emissive_texture,
+            &mut self.occlusion_texture,
+            &mut self.normal_texture,
+            &mut self.metallic_roughness_texture,
+        ]
+    }
+}
+
+impl Default for MaterialData {
+    fn default() -> Self {
+        Self {
+            name: String::from("default"),
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            emissive: [0.0; 3],
+            emissive_texture: None,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            normal_texture: None,
+            metallic_roughness_texture: None,
+        }
+    }
+}
+
+// Replaces factors of the material with that name, textures stay. Lets one
+// instance of an asset look different from the others
+#[derive(Clone, Debug)]
+pub struct MaterialOverride {
+    pub material: String,
+    pub base_color: Option<[f32; 4]>,
+    pub emissive: Option<[f32; 3]>,
+}
+
+impl MaterialOverride {
+    pub fn apply(&self, material: &mut MaterialData) {
+        if let Some(base_color) = self.base_color {
+            material.base_color = base_color;
+        }
+        if let Some(emissive) = self.emissive {
+            material.emissive = emissive;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ModelData {
+    pub name: String,
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    // world transform and projection of every camera in the file
+    pub cameras: Vec<(Mat4, Projection)>,
+    // the full node hierarchy, only needed for animation and skinning
+    pub nodes: Vec<NodeData>,
+    pub skins: Vec<SkinData>,
+    pub animations: Vec<AnimationData>,
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Gltf(gltf::Error),
+    Obj(tobj::LoadError),
+    Draco(String),
+    Meshopt(String),
+    Unsupported(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Gltf(e) => write!(f, "glTF: {}", e),
+            LoadError::Obj(e) => write!(f, "OBJ: {}", e),
+            LoadError::Draco(e) => write!(f, "Draco: {}", e),
+            LoadError::Meshopt(e) => write!(f, "meshopt: {}", e),
+            LoadError::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<gltf::Error> for LoadError {
+    fn from(e: gltf::Error) -> Self {
+        LoadError::Gltf(e)
+    }
+}
+
+impl From<tobj::LoadError> for LoadError {
+    fn from(e: tobj::LoadError) -> Self {
+        LoadError::Obj(e)
+    }
+}
+
+impl ModelData {
+    // Adds `other` placed at `root`, with the overrides baked into its
+    // materials. Only meshes, materials and cameras come along, the result
+    // is static
+    pub fn append(&mut self, other: ModelData, root: Mat4, overrides: &[MaterialOverride]) {
+        if !other.skins.is_empty() || !other.animations.is_empty() {
+            log::warn!("{}: skins and animations are left out", other.name);
+        }
+
+        let first_material = self.materials.len();
+        for mut material in other.materials {
+            for o in overrides {
+                if o.material == material.name {
+                    o.apply(&mut material);
+                }
+            }
+            self.materials.push(material);
+        }
+
+        for mut mesh in other.meshes {
+            mesh.transform = root * mesh.transform;
+            mesh.material = mesh.material.map(|i| first_material + i);
+            mesh.node = None;
+            mesh.skin = None;
+            mesh.joints.clear();
+            mesh.weights.clear();
+            self.meshes.push(mesh);
+        }
+
+        self.cameras.extend(
+            other
+                .cameras
+                .into_iter()
+                .map(|(world, projection)| (root * world, projection)),
+        );
+    }
+}
+
+// Picks the importer from the file extension
+pub fn load(path: &Path) -> Result<ModelData, LoadError> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("gltf") | Some("glb") => gltf_loader::load(path),
+        Some("obj") => obj_loader::load(path),
+        _ => Err(LoadError::Unsupported(format!(
+            "no importer for {}",
+            path.display()
+        ))),
+    }
+}
+
+pub fn name_from_path(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("unnamed"))
+}
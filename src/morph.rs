@@ -0,0 +1,121 @@
+use glam::Vec3;
+
+use crate::model::MeshData;
+
+// Base attributes plus the weighted target deltas. Output has the same
+// interleaved layout as `MeshData::vertex`. Targets without a weight count
+// as 0, extra weights are ignored
+pub fn morph_vertices(mesh: &MeshData, weights: &[f32], out: &mut Vec<[f32; 14]>) {
+    out.clear();
+    out.extend((0..mesh.positions.len()).map(|i| mesh.vertex(i)));
+
+    for (target, &weight) in mesh.targets.iter().zip(weights) {
+        if weight == 0.0 {
+            continue;
+        }
+        let add = |out: &mut Vec<[f32; 14]>, deltas: &[[f32; 3]], offset: usize| {
+            for (vertex, delta) in out.iter_mut().zip(deltas) {
+                for k in 0..3 {
+                    vertex[offset + k] += delta[k] * weight;
+                }
+            }
+        };
+        add(out, &target.positions, 0);
+        add(out, &target.normals, 3);
+        add(out, &target.tangents, 8);
+    }
+
+    // deltas are linear, the result has to be unit length again
+    for vertex in out.iter_mut() {
+        let normal = Vec3::from_slice(&vertex[3..6]).normalize_or_zero();
+        vertex[3..6].copy_from_slice(&normal.to_array());
+        let tangent = Vec3::from_slice(&vertex[8..11]).normalize_or_zero();
+        vertex[8..11].copy_from_slice(&tangent.to_array());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use glam::Mat4;
+
+    use crate::model::{MorphTarget, Topology};
+
+    fn mesh() -> MeshData {
+        MeshData {
+            name: String::from("test"),
+            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            normals: vec![[0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            uvs: vec![[0.0; 2]; 3],
+            extra_uvs: Vec::new(),
+            tangents: vec![[1.0, 0.0, 0.0, -1.0]; 3],
+            indices: vec![0, 1, 2],
+            topology: Topology::Triangles,
+            material: None,
+            transform: Mat4::IDENTITY,
+            node: None,
+            skin: None,
+            joints: Vec::new(),
+            weights: Vec::new(),
+            targets: vec![
+                MorphTarget {
+                    positions: vec![[2.0, 0.0, 0.0], [0.0, 4.0, 0.0], [0.0, 0.0, 0.0]],
+                    normals: vec![[0.0, 0.0, 0.0], [-2.0, 0.0, 2.0], [0.0, 0.0, 0.0]],
+                    tangents: Vec::new(),
+                },
+                // leaves the normals alone
+                MorphTarget {
+                    positions: vec![[0.0, 0.0, 4.0], [4.0, 0.0, 0.0], [8.0, 8.0, 8.0]],
+                    normals: Vec::new(),
+                    tangents: Vec::new(),
+                },
+            ],
+            morph_weights: Vec::new(),
+        }
+    }
+
+    fn positions(vertices: &[[f32; 14]]) -> Vec<[f32; 3]> {
+        vertices.iter().map(|v| [v[0], v[1], v[2]]).collect()
+    }
+
+    fn normals(vertices: &[[f32; 14]]) -> Vec<[f32; 3]> {
+        vertices.iter().map(|v| [v[3], v[4], v[5]]).collect()
+    }
+
+    #[test]
+    fn blends_weighted_deltas() {
+        let mut out = Vec::new();
+        // the third weight has no target
+        morph_vertices(&mesh(), &[0.5, 0.25, 1.0], &mut out);
+
+        assert_eq!(
+            positions(&out),
+            [[1.0, 0.0, 1.0], [2.0, 2.0, 0.0], [2.0, 3.0, 2.0]]
+        );
+        // (1, 0, 0) + (-1, 0, 1) normalized again
+        assert_eq!(
+            normals(&out),
+            [[0.0, 0.0, 1.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]]
+        );
+        for vertex in &out {
+            assert_eq!(vertex[8..12], [1.0, 0.0, 0.0, -1.0]);
+        }
+    }
+
+    #[test]
+    fn missing_weights_count_as_zero() {
+        let mesh = mesh();
+        let mut out = Vec::new();
+        morph_vertices(&mesh, &[], &mut out);
+        assert_eq!(positions(&out), mesh.positions);
+        assert_eq!(normals(&out), mesh.normals);
+
+        morph_vertices(&mesh, &[0.0, 0.5], &mut out);
+        assert_eq!(
+            positions(&out),
+            [[0.0, 0.0, 2.0], [3.0, 0.0, 0.0], [4.0, 5.0, 4.0]]
+        );
+        assert_eq!(normals(&out), mesh.normals);
+    }
+}
@@ -0,0 +1,333 @@
+use glam::{Mat3, Mat4};
+
+use std::fs;
+use std::path::Path;
+
+use crate::geometry;
+use crate::model::{
+    ColorSpace, LoadError, MaterialData, MeshData, ModelData, SamplerData, TextureRef, Topology,
+    Wrap, name_from_path,
+};
+
+pub fn load(path: &Path) -> Result<ModelData, LoadError> {
+    let options = tobj::LoadOptions {
+        single_index: true,
+        // we triangulate ourselves so faces can be matched up with their
+        // smoothing group
+        triangulate: false,
+        ignore_points: true,
+        ignore_lines: true,
+    };
+    let (objects, materials) = tobj::load_obj(path, &options)?;
+
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    let materials = match materials {
+        Ok(materials) => materials
+            .iter()
+            .map(|material| load_material(material, base_dir))
+            .collect(),
+        Err(e) => {
+            log::warn!("{}: could not load materials: {}", path.display(), e);
+            Vec::new()
+        }
+    };
+
+    // smoothing groups of every face in file order, tobj drops them
+    let mut groups = smoothing_groups(path);
+    let total_faces: usize = objects.iter().map(|o| face_count(&o.mesh)).sum();
+    if groups.len() != total_faces {
+        log::warn!(
+            "{}: found {} smoothing groups for {} faces, smoothing everything",
+            path.display(),
+            groups.len(),
+            total_faces
+        );
+        groups = vec![1; total_faces];
+    }
+
+    let mut meshes = Vec::new();
+    let mut first_face = 0;
+    for object in &objects {
+        let count = face_count(&object.mesh);
+        meshes.push(load_object(object, &groups[first_face..first_face + count]));
+        first_face += count;
+    }
+
+    Ok(ModelData {
+        name: name_from_path(path),
+        meshes,
+        materials,
+        cameras: Vec::new(),
+        nodes: Vec::new(),
+        skins: Vec::new(),
+        animations: Vec::new(),
+    })
+}
+
+fn face_count(mesh: &tobj::Mesh) -> usize {
+    if mesh.face_arities.is_empty() {
+        mesh.indices.len() / 3
+    } else {
+        mesh.face_arities.len()
+    }
+}
+
+fn load_object(object: &tobj::Model, face_groups: &[u32]) -> MeshData {
+    let mesh = &object.mesh;
+
+    let positions: Vec<[f32; 3]> = mesh
+        .positions
+        .chunks_exact(3)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+
+    // OBJ has v going up, we sample with v going down like glTF
+    let uvs: Vec<[f32; 2]> = if mesh.texcoords.is_empty() {
+        vec![[0.0, 0.0]; positions.len()]
+    } else {
+        mesh.texcoords
+            .chunks_exact(2)
+            .map(|t| [t[0], 1.0 - t[1]])
+            .collect()
+    };
+
+    // fan triangulation, remembering which face each triangle came from
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    let mut triangle_faces = Vec::with_capacity(mesh.indices.len() / 3);
+    if mesh.face_arities.is_empty() {
+        indices.extend_from_slice(&mesh.indices);
+        triangle_faces.extend(0..mesh.indices.len() / 3);
+    } else {
+        let mut start = 0;
+        for (face, &arity) in mesh.face_arities.iter().enumerate() {
+            let arity = arity as usize;
+            for i in 1..arity - 1 {
+                indices.push(mesh.indices[start]);
+                indices.push(mesh.indices[start + i]);
+                indices.push(mesh.indices[start + i + 1]);
+                triangle_faces.push(face);
+            }
+            start += arity;
+        }
+    }
+
+    let mut data = MeshData {
+        name: object.name.clone(),
+        positions,
+        normals: Vec::new(),
+        uvs,
+        extra_uvs: Vec::new(),
+        tangents: Vec::new(),
+        indices,
+        topology: Topology::Triangles,
+        material: mesh.material_id,
+        transform: Mat4::IDENTITY,
+        node: None,
+        skin: None,
+        joints: Vec::new(),
+        weights: Vec::new(),
+        targets: Vec::new(),
+        morph_weights: Vec::new(),
+    };
+
+    if mesh.normals.is_empty() {
+        let triangle_groups: Vec<u32> = triangle_faces.iter().map(|&f| face_groups[f]).collect();
+        geometry::generate_normals(&mut data, &triangle_groups);
+    } else {
+        data.normals = mesh
+            .normals
+            .chunks_exact(3)
+            .map(|n| [n[0], n[1], n[2]])
+            .collect();
+    }
+
+    geometry::fill_missing(&mut data);
+    data
+}
+
+// Smoothing group for every polygon, in the order tobj sees them
+fn smoothing_groups(path: &Path) -> Vec<u32> {
+    let Ok(text) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+
+    let mut groups = Vec::new();
+    let mut current = 0;
+    for line in text.lines() {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("s") => {
+                current = match tokens.next() {
+                    Some("off") | None => 0,
+                    Some(group) => group.parse().unwrap_or(1),
+                };
+            }
+            // points and lines are ignored by the loader
+            Some("f") if tokens.count() >= 3 => groups.push(current),
+            _ => {}
+        }
+    }
+    groups
+}
+
+fn load_material(material: &tobj::Material, base_dir: &Path) -> MaterialData {
+    let [r, g, b] = material.diffuse.unwrap_or([1.0; 3]);
+    let alpha = material.dissolve.unwrap_or(1.0);
+
+    // the texture is the last token, anything before it are map options.
+    // -clamp is the only one that maps onto the sampler
+    let base_color_texture = material.diffuse_texture.as_deref().and_then(|texture| {
+        let tokens: Vec<&str> = texture.split_whitespace().collect();
+        let clamp = tokens.windows(2).any(|option| option == ["-clamp", "on"]);
+        let wrap = if clamp { Wrap::ClampToEdge } else { Wrap::Repeat };
+        tokens.last().map(|file| TextureRef {
+            path: base_dir.join(file),
+            fallback: None,
+            tex_coord: 0,
+            transform: Mat3::IDENTITY,
+            sampler: SamplerData {
+                wrap_s: wrap,
+                wrap_t: wrap,
+                ..SamplerData::default()
+            },
+            color_space: ColorSpace::Srgb,
+        })
+    });
+
+    MaterialData {
+        name: material.name.clone(),
+        base_color: [r, g, b, alpha],
+        base_color_texture,
+        ..MaterialData::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+    use std::path::PathBuf;
+
+    // Two unit quads meeting at a right angle along x = 1, one facing +z,
+    // the other +x
+    const POSITIONS: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 1 0 -1\nv 1 1 -1\n";
+    const UVS: &str = "vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n";
+    const NORMALS: &str = "vn 0 0 1\nvn 1 0 0\n";
+
+    fn write_obj(name: &str, obj: &str, mtl: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bs-obj-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        if let Some(mtl) = mtl {
+            fs::write(dir.join("model.mtl"), mtl).unwrap();
+        }
+        let path = dir.join("model.obj");
+        fs::write(&path, obj).unwrap();
+        path
+    }
+
+    // Both quads, as `f` lines with uv and normal references when asked for
+    fn faces(uvs: bool, normals: bool) -> String {
+        let quads = [([1, 2, 3, 4], 1), ([2, 5, 6, 3], 2)];
+        let mut text = String::new();
+        for (corners, normal) in quads {
+            text += "f";
+            for (i, corner) in corners.into_iter().enumerate() {
+                text += &match (uvs, normals) {
+                    (true, true) => format!(" {}/{}/{}", corner, i + 1, normal),
+                    (true, false) => format!(" {}/{}", corner, i + 1),
+                    (false, true) => format!(" {}//{}", corner, normal),
+                    (false, false) => format!(" {}", corner),
+                };
+            }
+            text += "\n";
+        }
+        text
+    }
+
+    fn normal(mesh: &MeshData, index: u32) -> Vec3 {
+        Vec3::from(mesh.normals[index as usize])
+    }
+
+    #[test]
+    fn loads_with_and_without_normals_and_uvs() {
+        for (uvs, normals) in [(true, true), (true, false), (false, true), (false, false)] {
+            let name = format!("attributes-{}-{}", uvs, normals);
+            let mut obj = format!("o quads\n{}s off\n", POSITIONS);
+            if uvs {
+                obj += UVS;
+            }
+            if normals {
+                obj += NORMALS;
+            }
+            obj += &faces(uvs, normals);
+            let model = load(&write_obj(&name, &obj, None)).unwrap();
+
+            assert_eq!(model.meshes.len(), 1, "{}", name);
+            let mesh = &model.meshes[0];
+            assert_eq!(mesh.indices.len(), 12, "{}", name);
+            let count = mesh.positions.len();
+            assert_eq!(mesh.normals.len(), count, "{}", name);
+            assert_eq!(mesh.uvs.len(), count, "{}", name);
+            assert_eq!(mesh.tangents.len(), count, "{}", name);
+
+            // given or generated, every corner has its quad's normal
+            for (triangle, corners) in mesh.indices.chunks_exact(3).enumerate() {
+                let expected = if triangle < 2 { Vec3::Z } else { Vec3::X };
+                for &corner in corners {
+                    let actual = normal(mesh, corner);
+                    assert!(actual.abs_diff_eq(expected, 1e-5), "{}: {}", name, actual);
+                }
+            }
+
+            // v is flipped, the first corner is at the bottom left
+            let first = mesh.indices[0] as usize;
+            let expected = if uvs { [0.0, 1.0] } else { [0.0, 0.0] };
+            assert_eq!(mesh.uvs[first], expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn smoothing_groups_and_materials_carry_over() {
+        let mtl = "newmtl painted\nKd 1 0 0\nmap_Kd -clamp on paint.png\n";
+        let mut obj = format!("mtllib model.mtl\n{}", POSITIONS);
+        obj += "o smooth\nusemtl painted\ns 1\n";
+        obj += &faces(false, false);
+        obj += "o flat\ns off\n";
+        obj += &faces(false, false);
+        let path = write_obj("groups", &obj, Some(mtl));
+        let model = load(&path).unwrap();
+
+        assert_eq!(model.materials.len(), 1);
+        let material = &model.materials[0];
+        assert_eq!(material.base_color, [1.0, 0.0, 0.0, 1.0]);
+        let texture = material.base_color_texture.as_ref().unwrap();
+        assert_eq!(texture.path, path.parent().unwrap().join("paint.png"));
+        assert_eq!(texture.sampler.wrap_s, Wrap::ClampToEdge);
+
+        let [smooth, flat] = &model.meshes[..] else {
+            panic!("{} meshes", model.meshes.len());
+        };
+        assert_eq!(smooth.material, Some(0));
+
+        // the shared edge leans between the two quads, only there
+        for &corner in &smooth.indices {
+            let position = Vec3::from(smooth.positions[corner as usize]);
+            let actual = normal(smooth, corner);
+            if position.x == 1.0 && position.z == 0.0 {
+                let leaning = actual.x > 0.1 && actual.y == 0.0 && actual.z > 0.1;
+                assert!(leaning, "{} at {}", actual, position);
+            } else {
+                let expected = if position.x == 0.0 { Vec3::Z } else { Vec3::X };
+                let close = actual.abs_diff_eq(expected, 1e-5);
+                assert!(close, "{} at {}", actual, position);
+            }
+        }
+        for (triangle, corners) in flat.indices.chunks_exact(3).enumerate() {
+            let expected = if triangle < 2 { Vec3::Z } else { Vec3::X };
+            for &corner in corners {
+                assert!(normal(flat, corner).abs_diff_eq(expected, 1e-5));
+            }
+        }
+    }
+}
@@ -1,9 +1,12 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 
+use crate::catalog::AssetEntry;
+use crate::level::Level;
 use crate::{AppState, frame, init};
+use std::path::PathBuf;
 use std::ptr;
 
-use crate::input::KEYSTATE;
+use crate::input::{KEYPRESSED, KEYSTATE};
 
 use objc2::DefinedClass;
 
@@ -23,6 +26,10 @@ use block2::RcBlock;
 
 pub struct Ivars {
     pub state: RefCell<Option<AppState>>,
+    // what the scene starts with and what the browser cycles through
+    pub level: Level,
+    pub level_path: Option<PathBuf>,
+    pub catalog: Vec<AssetEntry>,
 }
 
 define_class!(
@@ -36,7 +43,11 @@ define_class!(
     unsafe impl NSApplicationDelegate for Delegate {
         #[unsafe(method(applicationDidFinishLaunching:))]
         unsafe fn init(&self, _notification: &NSNotification) {
-            let (state, _window, view) = init();
+            let (state, _window, view) = init(
+                &self.ivars().level,
+                self.ivars().level_path.as_deref(),
+                &self.ivars().catalog,
+            );
             view.setDelegate(Some(ProtocolObject::from_ref(self)));
             *self.ivars().state.borrow_mut() = Some(state);
 
@@ -47,7 +58,10 @@ define_class!(
                 // https://doc.rust-lang.org/rust-by-example/compatibility/raw_identifiers.html
                 match event_ref.r#type() {
                     NSEventType::KeyDown => {
-                        KEYSTATE.lock().unwrap().insert(keycode);
+                        // key repeats don't count as a new press
+                        if KEYSTATE.lock().unwrap().insert(keycode) {
+                            KEYPRESSED.lock().unwrap().insert(keycode);
+                        }
                     }
                     NSEventType::KeyUp => {
                         KEYSTATE.lock().unwrap().remove(&keycode);
@@ -0,0 +1,332 @@
+use std::collections::VecDeque;
+
+use crate::animation::{AnimationData, NodeData, Pose};
+
+// Per clip playback settings, kept when switching between clips
+#[derive(Copy, Clone, Debug)]
+pub struct ClipSettings {
+    pub speed: f32,
+    pub looping: bool,
+}
+
+impl Default for ClipSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            looping: true,
+        }
+    }
+}
+
+// A clip that is currently being played
+#[derive(Copy, Clone, Debug)]
+struct Track {
+    clip: usize,
+    time: f32,
+}
+
+// Fired when playback passes a marker
+#[derive(Clone, Debug)]
+pub struct AnimationEvent {
+    pub clip: usize,
+    pub name: String,
+    pub time: f32,
+}
+
+struct Marker {
+    clip: usize,
+    time: f32,
+    name: String,
+}
+
+// Plays the model's animation clips on a timeline. One clip is the main
+// track, a second one can be blended on top of it with a fixed weight or
+// crossfaded in over time
+pub struct AnimationPlayer {
+    settings: Vec<ClipSettings>,
+    track: Option<Track>,
+    blend: Option<Track>,
+    // weight of the blend track, 0 is only the main track
+    blend_weight: f32,
+    // weight change per second while crossfading
+    fade_rate: f32,
+    paused: bool,
+    last_time: Option<f64>,
+    markers: Vec<Marker>,
+    events: VecDeque<AnimationEvent>,
+}
+
+impl AnimationPlayer {
+    pub fn new(clip_count: usize) -> Self {
+        Self {
+            settings: vec![ClipSettings::default(); clip_count],
+            track: None,
+            blend: None,
+            blend_weight: 0.0,
+            fade_rate: 0.0,
+            paused: false,
+            last_time: None,
+            markers: Vec::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn play(&mut self, clip: usize) {
+        self.track = Some(Track { clip, time: 0.0 });
+        self.blend = None;
+        self.blend_weight = 0.0;
+        self.fade_rate = 0.0;
+        self.paused = false;
+    }
+
+    pub fn stop(&mut self) {
+        self.track = None;
+        self.blend = None;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn current_clip(&self) -> Option<usize> {
+        self.track.map(|track| track.clip)
+    }
+
+    // Jumps the main track to `time` seconds into its clip
+    pub fn seek(&mut self, time: f32) {
+        if let Some(track) = self.track.as_mut() {
+            track.time = time.max(0.0);
+        }
+    }
+
+    pub fn set_speed(&mut self, clip: usize, speed: f32) {
+        self.settings[clip].speed = speed;
+    }
+
+    pub fn set_looping(&mut self, clip: usize, looping: bool) {
+        self.settings[clip].looping = looping;
+    }
+
+    pub fn settings(&self, clip: usize) -> ClipSettings {
+        self.settings[clip]
+    }
+
+    // Plays `clip` on top of the main track at a fixed weight
+    pub fn blend(&mut self, clip: usize, weight: f32) {
+        let time = match self.blend {
+            Some(track) if track.clip == clip => track.time,
+            _ => 0.0,
+        };
+        self.blend = Some(Track { clip, time });
+        self.blend_weight = weight.clamp(0.0, 1.0);
+        self.fade_rate = 0.0;
+    }
+
+    // Fades from the current clip to `clip` over `seconds`, `clip` becomes
+    // the main track once it's done
+    pub fn crossfade(&mut self, clip: usize, seconds: f32) {
+        if self.track.is_none() || seconds <= 0.0 {
+            self.play(clip);
+            return;
+        }
+        self.blend = Some(Track { clip, time: 0.0 });
+        self.blend_weight = 0.0;
+        self.fade_rate = 1.0 / seconds;
+    }
+
+    // Fires an event whenever playback of `clip` passes `time`
+    pub fn add_marker(&mut self, clip: usize, time: f32, name: &str) {
+        self.markers.push(Marker {
+            clip,
+            time,
+            name: name.to_string(),
+        });
+    }
+
+    // Markers on every keyframe of the clip, named "<clip>:<key time>"
+    pub fn add_keyframe_markers(&mut self, clip: usize, animation: &AnimationData) {
+        let mut times: Vec<f32> = animation
+            .channels
+            .iter()
+            .flat_map(|channel| channel.times.iter().copied())
+            .collect();
+        times.sort_by(f32::total_cmp);
+        times.dedup();
+
+        for time in times {
+            let name = format!("{}:{:.3}", animation.name, time);
+            self.add_marker(clip, time, &name);
+        }
+    }
+
+    pub fn poll_event(&mut self) -> Option<AnimationEvent> {
+        self.events.pop_front()
+    }
+
+    // Advances playback to `time`, the same absolute seconds the renderer
+    // gets. The first call only sets the reference point
+    pub fn update(&mut self, time: f64, clips: &[AnimationData]) {
+        let dt = match self.last_time.replace(time) {
+            Some(last) if !self.paused => (time - last).max(0.0) as f32,
+            _ => return,
+        };
+
+        if let Some(mut track) = self.track {
+            self.advance(&mut track, dt, clips);
+            self.track = Some(track);
+        }
+        if let Some(mut track) = self.blend {
+            self.advance(&mut track, dt, clips);
+            self.blend = Some(track);
+        }
+
+        if self.fade_rate > 0.0 {
+            self.blend_weight += self.fade_rate * dt;
+            if self.blend_weight >= 1.0 {
+                self.track = self.blend.take();
+                self.blend_weight = 0.0;
+                self.fade_rate = 0.0;
+            }
+        }
+    }
+
+    fn advance(&mut self, track: &mut Track, dt: f32, clips: &[AnimationData]) {
+        let settings = self.settings[track.clip];
+        let duration = clips[track.clip].duration;
+        let from = track.time;
+        let mut to = from + dt * settings.speed;
+
+        if settings.looping && duration > 0.0 {
+            // wrapped around, markers after `from` and before the new time
+            // both fire
+            if to >= duration || to < 0.0 {
+                to = to.rem_euclid(duration);
+                if settings.speed >= 0.0 {
+                    self.fire_markers(track.clip, from, duration);
+                    self.fire_markers(track.clip, -f32::EPSILON, to);
+                } else {
+                    self.fire_markers(track.clip, -f32::EPSILON, from);
+                    self.fire_markers(track.clip, to, duration);
+                }
+                track.time = to;
+                return;
+            }
+        } else {
+            to = to.clamp(0.0, duration);
+        }
+
+        if to >= from {
+            self.fire_markers(track.clip, from, to);
+        } else {
+            self.fire_markers(track.clip, to, from);
+        }
+        track.time = to;
+    }
+
+    // markers in (from, to]
+    fn fire_markers(&mut self, clip: usize, from: f32, to: f32) {
+        for marker in &self.markers {
+            if marker.clip == clip && marker.time > from && marker.time <= to {
+                self.events.push_back(AnimationEvent {
+                    clip,
+                    name: marker.name.clone(),
+                    time: marker.time,
+                });
+            }
+        }
+    }
+
+    // Pose for the current state of both tracks, None when nothing plays
+    pub fn pose(&self, nodes: &[NodeData], clips: &[AnimationData]) -> Option<Pose> {
+        let track = self.track?;
+
+        let mut pose = Pose::rest(nodes);
+        pose.apply(&clips[track.clip], track.time);
+
+        if let Some(blend) = self.blend
+            && self.blend_weight > 0.0
+        {
+            let mut other = Pose::rest(nodes);
+            other.apply(&clips[blend.clip], blend.time);
+            pose.blend(&other, self.blend_weight);
+        }
+
+        pose.update_world(nodes);
+        Some(pose)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::animation::{Channel, Interpolation, Property};
+
+    fn clip() -> AnimationData {
+        AnimationData {
+            name: String::from("wave"),
+            channels: vec![Channel {
+                node: 0,
+                property: Property::Translation,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0, 0.5, 1.0],
+                values: vec![0.0; 9],
+                components: 3,
+            }],
+            duration: 1.0,
+        }
+    }
+
+    fn events(player: &mut AnimationPlayer) -> Vec<String> {
+        std::iter::from_fn(|| player.poll_event())
+            .map(|event| event.name)
+            .collect()
+    }
+
+    #[test]
+    fn keyframe_markers_fire_while_playing() {
+        let clips = [clip()];
+        let mut player = AnimationPlayer::new(1);
+        player.add_keyframe_markers(0, &clips[0]);
+        player.play(0);
+
+        player.update(0.0, &clips);
+        player.update(0.6, &clips);
+        assert_eq!(events(&mut player), ["wave:0.500"]);
+
+        // wraps around, the key at 0 fires with the one at the end
+        player.update(1.1, &clips);
+        assert_eq!(events(&mut player), ["wave:1.000", "wave:0.000"]);
+    }
+
+    #[test]
+    fn seek_speed_and_looping() {
+        let clips = [clip()];
+        let mut player = AnimationPlayer::new(1);
+        player.add_keyframe_markers(0, &clips[0]);
+        player.play(0);
+        player.set_speed(0, 2.0);
+        player.set_looping(0, false);
+
+        player.update(0.0, &clips);
+        player.update(0.3, &clips);
+        assert_eq!(events(&mut player), ["wave:0.500"]);
+        // clamped at the end instead of wrapping
+        player.update(2.0, &clips);
+        assert_eq!(events(&mut player), ["wave:1.000"]);
+        player.update(3.0, &clips);
+        assert!(events(&mut player).is_empty());
+
+        player.seek(0.25);
+        player.update(3.2, &clips);
+        assert_eq!(events(&mut player), ["wave:0.500"]);
+    }
+}
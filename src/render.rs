@@ -1,11 +1,21 @@
-use glam::{Mat4, Vec3};
+use glam::{Mat3, Mat3A, Mat4, Vec3, Vec3A};
 use objc2::rc::Retained;
 use objc2::runtime::ProtocolObject;
 use objc2_foundation::{ns_string, NSString, NSUInteger, NSURL};
 use objc2_metal::*;
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
 use std::ptr::NonNull;
 
-use crate::resource::{Buffer, BufferKind};
+use crate::animation::Pose;
+use crate::cook::CookedModel;
+use crate::ecs::Entity;
+use crate::model::{
+    MaterialData, MaterialOverride, MeshData, ModelData, SamplerData, TextureRef, Topology,
+};
+use crate::{morph, skin};
+use crate::resource::{Buffer, BufferKind, Device, Sampler, SamplerCache, Texture, TextureCache};
+use crate::scene::Scene;
 
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -16,16 +26,46 @@ pub struct Uniforms {
 }
 
 pub trait RenderPass {
-    // TODO: make Generic
     fn render(
         &self,
         encoder: &ProtocolObject<dyn MTLRenderCommandEncoder>,
         uniforms: &Uniforms,
-        model: &Asset,
+        draws: &[Draw],
         time: f32,
     );
 }
 
+// Component of an entity that draws one of an asset's meshes at its world
+// transform. `asset` is the entity of the scene object holding the Asset
+#[derive(Copy, Clone, Debug)]
+pub struct MeshRenderer {
+    pub asset: Entity,
+    pub mesh: usize,
+}
+
+pub struct Draw<'a> {
+    pub mesh: &'a Mesh,
+    pub model: Mat4,
+}
+
+// Everything with a MeshRenderer whose asset is still in the scene, where
+// it is this frame. Deformed meshes get their vertices into this frame's
+// buffer if no tick put them there
+pub fn draw_list(scene: &Scene) -> Vec<Draw<'_>> {
+    scene
+        .world
+        .query::<MeshRenderer>()
+        .filter_map(|(entity, renderer)| {
+            let mesh = scene.asset(renderer.asset)?.meshes.get(renderer.mesh)?;
+            mesh.carry_vertices(scene.frame);
+            Some(Draw {
+                mesh,
+                model: scene.render_transform(entity)?,
+            })
+        })
+        .collect()
+}
+
 // The pass owns the resources
 pub struct SinglePass {
     pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>,
@@ -42,6 +82,11 @@ impl SinglePass {
             depth_stencil_state,
         }
     }
+
+    // e.g. after the shaders were recompiled
+    pub fn set_pipeline(&mut self, pipeline: Retained<ProtocolObject<dyn MTLRenderPipelineState>>) {
+        self.pipeline = pipeline;
+    }
 }
 
 impl RenderPass for SinglePass {
@@ -49,19 +94,19 @@ impl RenderPass for SinglePass {
         &self,
         encoder: &ProtocolObject<dyn MTLRenderCommandEncoder>,
         uniforms: &Uniforms,
-        model: &Asset,
+        draws: &[Draw],
         time: f32,
     ) {
         encoder.setRenderPipelineState(&self.pipeline);
         encoder.setDepthStencilState(Some(&self.depth_stencil_state));
 
-        for mesh in &model.meshes {
+        for &Draw { mesh, model } in draws {
             unsafe {
                 // uplaod uniforms
                 let m_uniforms = Uniforms {
                     view_proj: uniforms.view_proj,
                     time: uniforms.time,
-                    model: mesh.model,
+                    model,
                 };
                 encoder.setVertexBytes_length_atIndex(
                     NonNull::from(&m_uniforms).cast(),
@@ -70,12 +115,15 @@ impl RenderPass for SinglePass {
                 );
             }
             unsafe {
-                for material in &mesh.materials {
-                    if let Some(texture) = material {
-                        encoder.setFragmentTexture_atIndex(Some(texture), 0);
-                    } else {
-                        encoder.setFragmentTexture_atIndex(None, 0);
-                    }
+                let material = mesh.material.uniforms();
+                encoder.setFragmentBytes_length_atIndex(
+                    NonNull::from(&material).cast(),
+                    std::mem::size_of_val(&material),
+                    BufferKind::MATERIAL as NSUInteger,
+                );
+                for (index, texture) in mesh.material.textures().into_iter().enumerate() {
+                    encoder.setFragmentTexture_atIndex(Some(&texture.texture), index);
+                    encoder.setFragmentSamplerState_atIndex(Some(&texture.sampler), index);
                 }
             }
             mesh.draw(encoder);
@@ -83,39 +131,220 @@ impl RenderPass for SinglePass {
     }
 }
 
+// Same layout as the TextureInfo struct in the shader, Mat3A matches the
+// 16 byte columns of a float3x3
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct TextureUniforms {
+    pub uv_transform: Mat3A,
+    pub tex_coord: u32,
+}
+
+// Same layout as the Material struct in the shader, Vec3A matches float3
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct MaterialUniforms {
+    pub base_color: [f32; 4],
+    pub emissive: Vec3A,
+    pub occlusion_strength: f32,
+    pub base_color_texture: TextureUniforms,
+    pub emissive_texture: TextureUniforms,
+    pub occlusion_texture: TextureUniforms,
+}
+
+#[derive(Clone)]
+pub struct MaterialTexture {
+    // white when the material has no texture
+    pub texture: Texture,
+    pub sampler: Sampler,
+    // the texture's uv set, 0 or 1, and the transform applied to it
+    pub tex_coord: u32,
+    pub uv_transform: Mat3,
+}
+
+impl MaterialTexture {
+    fn uniforms(&self) -> TextureUniforms {
+        TextureUniforms {
+            uv_transform: Mat3A::from(self.uv_transform),
+            tex_coord: self.tex_coord,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    pub emissive: [f32; 3],
+    pub occlusion_strength: f32,
+    pub base_color_texture: MaterialTexture,
+    pub emissive_texture: MaterialTexture,
+    pub occlusion_texture: MaterialTexture,
+}
+
+impl Material {
+    pub fn uniforms(&self) -> MaterialUniforms {
+        MaterialUniforms {
+            base_color: self.base_color,
+            emissive: Vec3A::from_array(self.emissive),
+            occlusion_strength: self.occlusion_strength,
+            base_color_texture: self.base_color_texture.uniforms(),
+            emissive_texture: self.emissive_texture.uniforms(),
+            occlusion_texture: self.occlusion_texture.uniforms(),
+        }
+    }
+
+    // in binding order, texture and sampler n go to slot n
+    pub fn textures(&self) -> [&MaterialTexture; 3] {
+        [
+            &self.base_color_texture,
+            &self.emissive_texture,
+            &self.occlusion_texture,
+        ]
+    }
+}
+
+// Frames the CPU may be ahead of the GPU. Vertices deformed on the CPU
+// get one buffer each, so a frame never writes what an earlier one is
+// still drawing. The frame loop waits on the command buffer from
+// FRAMES_IN_FLIGHT frames ago before reusing its buffers, and every frame
+// draws from its own, see Mesh::carry_vertices
+pub const FRAMES_IN_FLIGHT: usize = 3;
+
 // Mesh, Asset, should be omved somewhere else. leave this file for MTL resources
 pub struct Mesh {
     pub buffers: Vec<Buffer>,
+    // copies of the vertex buffer for skinned and morphed meshes, one per
+    // frame in flight. Drawn instead of buffers[0], empty for static meshes
+    frames: Vec<Buffer>,
+    // which of `frames` was written last
+    current: Cell<usize>,
     pub index_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
-    // TODO: Type alias or whatever its called again
-    pub materials: Vec<Option<Retained<ProtocolObject<dyn MTLTexture>>>>,
+    pub material: Material,
     pub index_count: usize,
     pub primitive: MTLPrimitiveType,
-    pub model: Mat4,
+    // current morph target weights, see Asset::set_morph_weights
+    pub morph_weights: Vec<f32>,
 }
 
 impl Mesh {
     pub fn new(
         buffers: Vec<Buffer>,
         index_buffer: Retained<ProtocolObject<dyn MTLBuffer>>,
-        materials: Vec<Option<Retained<ProtocolObject<dyn MTLTexture>>>>,
+        material: Material,
         index_count: usize,
         primitive: MTLPrimitiveType,
-        model: Mat4,
     ) -> Self {
         Self {
             buffers,
+            frames: Vec::new(),
+            current: Cell::new(0),
             index_buffer,
-            materials,
+            material,
             index_count,
             primitive,
-            model,
+            morph_weights: Vec::new(),
+        }
+    }
+
+    pub fn from_data(
+        device: &Retained<ProtocolObject<dyn MTLDevice>>,
+        data: &MeshData,
+        material: Material,
+    ) -> Self {
+        // interleave all attributes into a single buffer
+        let vertices: Vec<[f32; 14]> = (0..data.positions.len()).map(|i| data.vertex(i)).collect();
+
+        let mut mesh =
+            Self::from_vertices(device, &vertices, &data.indices, data.topology, material);
+        mesh.morph_weights = data.morph_weights.clone();
+        if !data.targets.is_empty() || data.skin.is_some() {
+            mesh.frames = (0..FRAMES_IN_FLIGHT)
+                .map(|_| vertex_buffer(device, &vertices))
+                .collect();
+        }
+        mesh
+    }
+
+    // Already interleaved vertices, see MeshData::vertex for the layout
+    pub fn from_vertices(
+        device: &Retained<ProtocolObject<dyn MTLDevice>>,
+        vertices: &[[f32; 14]],
+        indices: &[u32],
+        topology: Topology,
+        material: Material,
+    ) -> Self {
+        let buffer = vertex_buffer(device, vertices);
+
+        // TODO: more generic buffer create?
+        let index_buffer = device
+            .newBufferWithLength_options(
+                (indices.len() * std::mem::size_of::<u32>()) as NSUInteger,
+                MTLResourceOptions::StorageModeShared,
+            )
+            .expect("Failed to create index buffer");
+
+        unsafe {
+            let contents = index_buffer.contents().as_ptr() as *mut u32;
+            std::ptr::copy_nonoverlapping(indices.as_ptr(), contents, indices.len());
+        }
+
+        let primitive = match topology {
+            Topology::Points => MTLPrimitiveType::Point,
+            Topology::Lines => MTLPrimitiveType::Line,
+            Topology::LineStrip => MTLPrimitiveType::LineStrip,
+            Topology::Triangles => MTLPrimitiveType::Triangle,
+        };
+
+        Self::new(
+            vec![buffer],
+            index_buffer,
+            material,
+            indices.len(),
+            primitive,
+        )
+    }
+
+    // Overwrites the vertex buffer of `frame` after skinning or morphing,
+    // it's drawn from then on. Only for meshes from_data made deformable
+    pub fn write_vertices(&self, frame: usize, vertices: &[[f32; 14]]) {
+        let slot = frame % FRAMES_IN_FLIGHT;
+        let buffer = &self.frames[slot].buffer;
+        assert!(std::mem::size_of_val(vertices) <= buffer.length() as usize);
+        unsafe {
+            let contents = buffer.contents().as_ptr() as *mut [f32; 14];
+            std::ptr::copy_nonoverlapping(vertices.as_ptr(), contents, vertices.len());
         }
+        self.current.set(slot);
+    }
+
+    // Copies the last deformed vertices into the buffer of `frame` when
+    // nothing wrote them this frame, e.g. a frame without a tick. Drawing
+    // the older buffer again would let a later frame overwrite it while
+    // this one still reads it
+    pub fn carry_vertices(&self, frame: usize) {
+        let slot = frame % FRAMES_IN_FLIGHT;
+        let last = self.current.get();
+        if self.frames.is_empty() || slot == last {
+            return;
+        }
+        let (from, to) = (&self.frames[last].buffer, &self.frames[slot].buffer);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                from.contents().as_ptr() as *const u8,
+                to.contents().as_ptr() as *mut u8,
+                from.length() as usize,
+            );
+        }
+        self.current.set(slot);
     }
 
     pub fn draw(&self, encoder: &ProtocolObject<dyn MTLRenderCommandEncoder>) {
         unsafe {
-            for buffer in &self.buffers {
+            for (i, buffer) in self.buffers.iter().enumerate() {
+                let buffer = match self.frames.get(self.current.get()) {
+                    Some(frame) if i == 0 => frame,
+                    _ => buffer,
+                };
                 encoder.setVertexBuffer_offset_atIndex(
                     Some(&buffer.buffer),
                     0,
@@ -133,10 +362,292 @@ impl Mesh {
     }
 }
 
-// i.e. glTF
+// Interleaved vertices, see MeshData::vertex for the layout
+fn vertex_buffer(
+    device: &Retained<ProtocolObject<dyn MTLDevice>>,
+    vertices: &[[f32; 14]],
+) -> Buffer {
+    let buffer = Buffer::new(
+        device,
+        vertices.len(),
+        std::mem::size_of::<[f32; 14]>(),
+        MTLResourceOptions::StorageModeShared,
+        BufferKind::POSITIONS,
+    );
+
+    unsafe {
+        let contents = buffer.buffer.contents().as_ptr() as *mut [f32; 14];
+        std::ptr::copy_nonoverlapping(vertices.as_ptr(), contents, vertices.len());
+    }
+    buffer
+}
+
+// i.e. glTF. Where its meshes are drawn is up to the entities rendering
+// them, see MeshRenderer
 pub struct Asset {
-    // TODO: constructors
     pub meshes: Vec<Mesh>,
-    // TODO: materials
     pub name: String,
+    // per skin, from the last applied pose
+    joint_matrices: Vec<Vec<Mat4>>,
+    // what the meshes' materials are built from, kept to rebuild them when
+    // one of their textures changes
+    materials: Vec<MaterialData>,
+    // index into `materials` for every mesh
+    mesh_materials: Vec<Option<usize>>,
+    // applied on top of `materials`, kept across reloads
+    overrides: Vec<MaterialOverride>,
+    textures: TextureCache,
+    samplers: SamplerCache,
+}
+
+impl Asset {
+    // Empty until a model is set
+    pub fn new(device: &Device) -> Self {
+        Self {
+            meshes: Vec::new(),
+            name: String::new(),
+            joint_matrices: Vec::new(),
+            materials: Vec::new(),
+            mesh_materials: Vec::new(),
+            overrides: Vec::new(),
+            textures: TextureCache::new(&device.device),
+            samplers: SamplerCache::new(&device.device),
+        }
+    }
+
+    // Uploads a loaded model in place of the current one. Textures both use
+    // aren't loaded again
+    pub fn set_model(&mut self, device: &Device, model: &ModelData) {
+        self.mesh_materials = model.meshes.iter().map(|mesh| mesh.material).collect();
+        let (materials, default_material) = self.set_materials(&model.materials);
+
+        self.meshes = model
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let material = mesh
+                    .material
+                    .and_then(|i| materials.get(i))
+                    .unwrap_or(&default_material)
+                    .clone();
+                Mesh::from_data(&device.device, mesh, material)
+            })
+            .collect();
+        self.name = model.name.clone();
+        self.joint_matrices.clear();
+    }
+
+    // Same as `set_model` for a model read back from its cooked file.
+    // Vertices and indices are copied straight out of the mapping
+    pub fn set_cooked(&mut self, device: &Device, cooked: &CookedModel) {
+        self.mesh_materials = cooked.meshes.iter().map(|mesh| mesh.material).collect();
+        let (materials, default_material) = self.set_materials(&cooked.data.materials);
+
+        self.meshes = cooked
+            .meshes
+            .iter()
+            .map(|mesh| {
+                let material = mesh
+                    .material
+                    .and_then(|i| materials.get(i))
+                    .unwrap_or(&default_material)
+                    .clone();
+                Mesh::from_vertices(
+                    &device.device,
+                    cooked.vertices(mesh),
+                    cooked.indices(mesh),
+                    mesh.topology,
+                    material,
+                )
+            })
+            .collect();
+        self.name = cooked.data.name.clone();
+        self.joint_matrices.clear();
+    }
+
+    // Reloads textures that came from any of `paths` and rebuilds the
+    // materials using them. False when none of them is used
+    pub fn reload_textures(&mut self, paths: &[PathBuf]) -> bool {
+        let mut reload = false;
+        for path in paths {
+            reload |= self.textures.invalidate(path);
+        }
+        if !reload {
+            return false;
+        }
+        self.rebuild_materials();
+        true
+    }
+
+    pub fn overrides(&self) -> &[MaterialOverride] {
+        &self.overrides
+    }
+
+    // Replaces the previous overrides. They stay through reloads of the model
+    pub fn set_overrides(&mut self, overrides: Vec<MaterialOverride>) {
+        for o in &overrides {
+            if !self
+                .materials
+                .iter()
+                .any(|material| material.name == o.material)
+            {
+                log::warn!("{}: no material {:?} to override", self.name, o.material);
+            }
+        }
+        self.overrides = overrides;
+        self.rebuild_materials();
+    }
+
+    // Image files the materials use, including ones that failed to load
+    pub fn texture_sources(&self) -> impl Iterator<Item = &Path> {
+        self.textures.sources()
+    }
+
+    fn set_materials(&mut self, materials: &[MaterialData]) -> (Vec<Material>, Material) {
+        self.materials = materials.to_vec();
+        self.textures
+            .keep_only(self.materials.iter().flat_map(MaterialData::textures));
+        self.build_materials()
+    }
+
+    // Gives every mesh its material again, after textures or overrides changed
+    fn rebuild_materials(&mut self) {
+        let (materials, default_material) = self.build_materials();
+        for (mesh, material) in self.meshes.iter_mut().zip(&self.mesh_materials) {
+            mesh.material = material
+                .and_then(|i| materials.get(i))
+                .unwrap_or(&default_material)
+                .clone();
+        }
+    }
+
+    // Every material with the overrides applied, plus the one meshes without
+    // a material get
+    fn build_materials(&mut self) -> (Vec<Material>, Material) {
+        let mut material_texture = |name: &str, texture_ref: Option<&TextureRef>| {
+            // the vertex only has room for two uv sets
+            let mut tex_coord = texture_ref.map_or(0, |tex| tex.tex_coord);
+            if tex_coord > 1 {
+                log::warn!(
+                    "{}: uv set {} isn't uploaded, using the first",
+                    name,
+                    tex_coord
+                );
+                tex_coord = 0;
+            }
+
+            MaterialTexture {
+                texture: self.textures.get(texture_ref),
+                sampler: self
+                    .samplers
+                    .get(&texture_ref.map_or_else(SamplerData::default, |tex| tex.sampler)),
+                tex_coord: tex_coord as u32,
+                uv_transform: texture_ref.map_or(Mat3::IDENTITY, |tex| tex.transform),
+            }
+        };
+
+        let materials: Vec<Material> = self
+            .materials
+            .iter()
+            .map(|material| {
+                let mut factors = material.clone();
+                for o in self
+                    .overrides
+                    .iter()
+                    .filter(|o| o.material == material.name)
+                {
+                    o.apply(&mut factors);
+                }
+                Material {
+                    base_color: factors.base_color,
+                    emissive: factors.emissive,
+                    occlusion_strength: material.occlusion_strength,
+                    base_color_texture: material_texture(
+                        &material.name,
+                        material.base_color_texture.as_ref(),
+                    ),
+                    emissive_texture: material_texture(
+                        &material.name,
+                        material.emissive_texture.as_ref(),
+                    ),
+                    occlusion_texture: material_texture(
+                        &material.name,
+                        material.occlusion_texture.as_ref(),
+                    ),
+                }
+            })
+            .collect();
+
+        let untextured = material_texture("default", None);
+        let default_material = Material {
+            base_color: [1.0; 4],
+            emissive: [0.0; 3],
+            occlusion_strength: 1.0,
+            base_color_texture: untextured.clone(),
+            emissive_texture: untextured.clone(),
+            occlusion_texture: untextured,
+        };
+
+        (materials, default_material)
+    }
+
+    // Takes animated morph weights and re-deforms skinned and morphed
+    // meshes. Meshes line up 1:1 with the ModelData this asset was created
+    // from. Moving them is up to their entities' transforms. `frame` picks
+    // the vertex buffers written, see FRAMES_IN_FLIGHT
+    pub fn apply_pose(&mut self, model: &ModelData, pose: &Pose, frame: usize) {
+        for (mesh, data) in self.meshes.iter_mut().zip(&model.meshes) {
+            let Some(node) = data.node else {
+                continue;
+            };
+            if !pose.weights[node].is_empty() {
+                mesh.morph_weights.clone_from(&pose.weights[node]);
+            }
+        }
+
+        self.joint_matrices = model
+            .skins
+            .iter()
+            .map(|skin| skin::joint_matrices(skin, pose))
+            .collect();
+
+        let mut vertices = Vec::new();
+        for i in 0..self.meshes.len() {
+            self.update_vertices(model, i, frame, &mut vertices);
+        }
+    }
+
+    // Overrides the weights of one mesh until an animation sets them again
+    pub fn set_morph_weights(
+        &mut self,
+        model: &ModelData,
+        mesh: usize,
+        weights: &[f32],
+        frame: usize,
+    ) {
+        self.meshes[mesh].morph_weights = weights.to_vec();
+        self.update_vertices(model, mesh, frame, &mut Vec::new());
+    }
+
+    // Morph first, then skin, like the glTF spec says. Static meshes keep
+    // what was uploaded
+    fn update_vertices(
+        &self,
+        model: &ModelData,
+        i: usize,
+        frame: usize,
+        vertices: &mut Vec<[f32; 14]>,
+    ) {
+        let (mesh, data) = (&self.meshes[i], &model.meshes[i]);
+        let skin = data.skin.and_then(|skin| self.joint_matrices.get(skin));
+        if data.targets.is_empty() && skin.is_none() {
+            return;
+        }
+
+        morph::morph_vertices(data, &mesh.morph_weights, vertices);
+        if let Some(joint_matrices) = skin {
+            skin::skin_vertices(data, joint_matrices, vertices);
+        }
+        mesh.write_vertices(frame, vertices);
+    }
 }
@@ -2,16 +2,43 @@ use objc2::rc::Retained;
 use objc2::runtime::ProtocolObject;
 use objc2_foundation::{ns_string, NSString, NSUInteger, NSURL};
 use objc2_metal::*;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+
+use crate::model::{ColorSpace, Filter, SamplerData, TextureRef, Wrap};
+use crate::texture::{self, TextureData, TextureFormat, TextureOptions};
+
+pub type Texture = Retained<ProtocolObject<dyn MTLTexture>>;
+pub type Sampler = Retained<ProtocolObject<dyn MTLSamplerState>>;
 
 pub struct Device {
     pub device: Retained<ProtocolObject<dyn MTLDevice>>,
     pub command_queue: Retained<ProtocolObject<dyn MTLCommandQueue>>,
 }
 
+impl Device {
+    // The default GPU and a queue, for when there's no view to set up
+    pub fn system_default() -> Self {
+        let device = MTLCreateSystemDefaultDevice().expect("No Metal device");
+        let command_queue = device
+            .newCommandQueue()
+            .expect("Failed to create command queue");
+        Self {
+            device,
+            command_queue,
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
 pub enum BufferKind {
     POSITIONS = 1,
     UV = 2,
+    // fragment stage
+    MATERIAL = 3,
 }
 
 pub struct Buffer {
@@ -71,4 +98,375 @@ impl ShaderLibrary {
             name,
         }
     }
+
+    // Compiles at runtime instead of loading what build.rs compiled, for
+    // picking up edits while running. Errors are the compiler's messages
+    pub fn compile(
+        name: String,
+        source: &ShaderSource,
+        device: &Retained<ProtocolObject<dyn MTLDevice>>,
+    ) -> Result<Self, String> {
+        let library = device
+            .newLibraryWithSource_options_error(&NSString::from_str(&source.text), None)
+            .map_err(|e| e.localizedDescription().to_string())?;
+
+        let function = |function_name: &NSString| {
+            library
+                .newFunctionWithName(function_name)
+                .ok_or_else(|| format!("no function named {}", function_name))
+        };
+
+        Ok(Self {
+            vertex: function(ns_string!("vertex_main"))?,
+            fragment: function(ns_string!("fragment_main"))?,
+            name,
+        })
+    }
+}
+
+// A shader with the files its quoted #includes name pasted in, the runtime
+// compiler has no include path to find them on. #line directives keep
+// error messages pointing at the original files
+pub struct ShaderSource {
+    pub text: String,
+    // the shader and every header it pulled in
+    pub files: Vec<PathBuf>,
+}
+
+impl ShaderSource {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut source = Self {
+            text: String::new(),
+            files: Vec::new(),
+        };
+        source.append(path)?;
+        Ok(source)
+    }
+
+    // every file only once, the headers have no include guards
+    fn append(&mut self, path: &Path) -> io::Result<()> {
+        if self.files.iter().any(|file| file == path) {
+            return Ok(());
+        }
+        self.files.push(path.to_path_buf());
+
+        let dir = path.parent().unwrap_or(Path::new("."));
+        self.text
+            .push_str(&format!("#line 1 \"{}\"\n", path.display()));
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let include = line
+                .trim()
+                .strip_prefix("#include")
+                .and_then(|name| name.trim().strip_prefix('"')?.strip_suffix('"'));
+            match include {
+                Some(name) => {
+                    self.append(&dir.join(name))?;
+                    self.text
+                        .push_str(&format!("#line {} \"{}\"\n", i + 2, path.display()));
+                }
+                None => {
+                    self.text.push_str(line);
+                    self.text.push('\n');
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Images are decoded and mipmapped on the CPU by crate::texture, or come
+// block compressed from KTX2 and DDS files. This only copies the levels in
+pub struct TextureLoader {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+}
+
+impl TextureLoader {
+    pub fn new(device: &Retained<ProtocolObject<dyn MTLDevice>>) -> Self {
+        Self {
+            device: device.clone(),
+        }
+    }
+
+    pub fn load(&self, path: &Path, color_space: ColorSpace) -> Option<Texture> {
+        let options = TextureOptions {
+            color_space,
+            ..TextureOptions::default()
+        };
+
+        match texture::load(path, options) {
+            Ok(data) => self.upload(&data),
+            Err(e) => {
+                log::error!("Failed to load texture {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    // sRGB data goes in an _sRGB format so sampling decodes it to linear.
+    // None when the GPU can't sample the format
+    pub fn upload(&self, data: &TextureData) -> Option<Texture> {
+        let Some(format) = pixel_format(data.format, data.color_space) else {
+            log::error!("no Metal pixel format for {:?}", data.format);
+            return None;
+        };
+        let supported = match data.format {
+            TextureFormat::Rgba8 => true,
+            // Apple GPUs only
+            TextureFormat::Astc { .. } => self.device.supportsFamily(MTLGPUFamily::Apple2),
+            _ => self.device.supportsBCTextureCompression(),
+        };
+        if !supported {
+            log::error!("{:?} textures aren't supported by this GPU", data.format);
+            return None;
+        }
+
+        let descriptor = unsafe {
+            let descriptor =
+                MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+                    format,
+                    data.width() as usize,
+                    data.height() as usize,
+                    data.levels.len() > 1,
+                );
+            descriptor.setMipmapLevelCount(data.levels.len());
+            descriptor
+        };
+        let texture = self
+            .device
+            .newTextureWithDescriptor(&descriptor)
+            .expect("Failed to create texture");
+
+        for (level, mip) in data.levels.iter().enumerate() {
+            let region = MTLRegion {
+                origin: MTLOrigin { x: 0, y: 0, z: 0 },
+                size: MTLSize {
+                    width: mip.width as usize,
+                    height: mip.height as usize,
+                    depth: 1,
+                },
+            };
+            // block formats count rows of blocks
+            unsafe {
+                texture.replaceRegion_mipmapLevel_withBytes_bytesPerRow(
+                    region,
+                    level,
+                    NonNull::from(mip.data.as_slice()).cast(),
+                    data.format.row_bytes(mip.width),
+                );
+            }
+        }
+
+        Some(texture)
+    }
+}
+
+// Textures by the image they came from, each loaded once per color space and
+// shared by every material using it. Assets keep theirs around, so a changed
+// file only reloads what came from it
+pub struct TextureCache {
+    loader: TextureLoader,
+    white: Texture,
+    // None for images that failed to load, they're retried once they change
+    textures: HashMap<(PathBuf, Option<PathBuf>, ColorSpace), Option<Texture>>,
+}
+
+impl TextureCache {
+    pub fn new(device: &Retained<ProtocolObject<dyn MTLDevice>>) -> Self {
+        Self {
+            loader: TextureLoader::new(device),
+            white: white_texture(device),
+            textures: HashMap::new(),
+        }
+    }
+
+    // White for no texture or one that can't be loaded
+    pub fn get(&mut self, texture: Option<&TextureRef>) -> Texture {
+        let Some(tex) = texture else {
+            return self.white.clone();
+        };
+        let loader = &self.loader;
+        self.textures
+            .entry((tex.path.clone(), tex.fallback.clone(), tex.color_space))
+            .or_insert_with(|| {
+                loader.load(&tex.path, tex.color_space).or_else(|| {
+                    let fallback = tex.fallback.as_ref()?;
+                    loader.load(fallback, tex.color_space)
+                })
+            })
+            .clone()
+            .unwrap_or_else(|| self.white.clone())
+    }
+
+    // Forgets everything loaded from `path`, or from images `path` is the
+    // compressed copy of. True if there was anything
+    pub fn invalidate(&mut self, path: &Path) -> bool {
+        let loads_from = |image: &Path| {
+            image == path || texture::compressed_path(image).as_deref() == Some(path)
+        };
+        let count = self.textures.len();
+        self.textures.retain(|(source, fallback, _), _| {
+            !loads_from(source) && !fallback.as_deref().is_some_and(loads_from)
+        });
+        self.textures.len() != count
+    }
+
+    // Drops the textures none of `used` would load
+    pub fn keep_only<'a>(&mut self, used: impl IntoIterator<Item = &'a TextureRef>) {
+        let used: Vec<_> = used
+            .into_iter()
+            .map(|tex| (&tex.path, tex.fallback.as_ref(), tex.color_space))
+            .collect();
+        self.textures.retain(|(source, fallback, color_space), _| {
+            used.contains(&(source, fallback.as_ref(), *color_space))
+        });
+    }
+
+    // Every image file textures were (or would have been) loaded from
+    pub fn sources(&self) -> impl Iterator<Item = &Path> {
+        self.textures.keys().flat_map(|(source, fallback, _)| {
+            std::iter::once(source.as_path()).chain(fallback.as_deref())
+        })
+    }
+}
+
+fn pixel_format(format: TextureFormat, color_space: ColorSpace) -> Option<MTLPixelFormat> {
+    let srgb = color_space == ColorSpace::Srgb;
+    Some(match format {
+        TextureFormat::Rgba8 if srgb => MTLPixelFormat::RGBA8Unorm_sRGB,
+        TextureFormat::Rgba8 => MTLPixelFormat::RGBA8Unorm,
+        TextureFormat::Bc1 if srgb => MTLPixelFormat::BC1_RGBA_sRGB,
+        TextureFormat::Bc1 => MTLPixelFormat::BC1_RGBA,
+        TextureFormat::Bc2 if srgb => MTLPixelFormat::BC2_RGBA_sRGB,
+        TextureFormat::Bc2 => MTLPixelFormat::BC2_RGBA,
+        TextureFormat::Bc3 if srgb => MTLPixelFormat::BC3_RGBA_sRGB,
+        TextureFormat::Bc3 => MTLPixelFormat::BC3_RGBA,
+        TextureFormat::Bc4 => MTLPixelFormat::BC4_RUnorm,
+        TextureFormat::Bc5 => MTLPixelFormat::BC5_RGUnorm,
+        TextureFormat::Bc6h => MTLPixelFormat::BC6H_RGBUfloat,
+        TextureFormat::Bc7 if srgb => MTLPixelFormat::BC7_RGBAUnorm_sRGB,
+        TextureFormat::Bc7 => MTLPixelFormat::BC7_RGBAUnorm,
+        TextureFormat::Astc { width, height } => {
+            let (ldr, srgb_format) = match (width, height) {
+                (4, 4) => (MTLPixelFormat::ASTC_4x4_LDR, MTLPixelFormat::ASTC_4x4_sRGB),
+                (5, 4) => (MTLPixelFormat::ASTC_5x4_LDR, MTLPixelFormat::ASTC_5x4_sRGB),
+                (5, 5) => (MTLPixelFormat::ASTC_5x5_LDR, MTLPixelFormat::ASTC_5x5_sRGB),
+                (6, 5) => (MTLPixelFormat::ASTC_6x5_LDR, MTLPixelFormat::ASTC_6x5_sRGB),
+                (6, 6) => (MTLPixelFormat::ASTC_6x6_LDR, MTLPixelFormat::ASTC_6x6_sRGB),
+                (8, 5) => (MTLPixelFormat::ASTC_8x5_LDR, MTLPixelFormat::ASTC_8x5_sRGB),
+                (8, 6) => (MTLPixelFormat::ASTC_8x6_LDR, MTLPixelFormat::ASTC_8x6_sRGB),
+                (8, 8) => (MTLPixelFormat::ASTC_8x8_LDR, MTLPixelFormat::ASTC_8x8_sRGB),
+                (10, 5) => (
+                    MTLPixelFormat::ASTC_10x5_LDR,
+                    MTLPixelFormat::ASTC_10x5_sRGB,
+                ),
+                (10, 6) => (
+                    MTLPixelFormat::ASTC_10x6_LDR,
+                    MTLPixelFormat::ASTC_10x6_sRGB,
+                ),
+                (10, 8) => (
+                    MTLPixelFormat::ASTC_10x8_LDR,
+                    MTLPixelFormat::ASTC_10x8_sRGB,
+                ),
+                (10, 10) => (
+                    MTLPixelFormat::ASTC_10x10_LDR,
+                    MTLPixelFormat::ASTC_10x10_sRGB,
+                ),
+                (12, 10) => (
+                    MTLPixelFormat::ASTC_12x10_LDR,
+                    MTLPixelFormat::ASTC_12x10_sRGB,
+                ),
+                (12, 12) => (
+                    MTLPixelFormat::ASTC_12x12_LDR,
+                    MTLPixelFormat::ASTC_12x12_sRGB,
+                ),
+                _ => return None,
+            };
+            if srgb { srgb_format } else { ldr }
+        }
+    })
+}
+
+// Materials mostly use the same handful of samplers, each distinct one is
+// created once and shared
+pub struct SamplerCache {
+    device: Retained<ProtocolObject<dyn MTLDevice>>,
+    samplers: HashMap<SamplerData, Sampler>,
+}
+
+impl SamplerCache {
+    pub fn new(device: &Retained<ProtocolObject<dyn MTLDevice>>) -> Self {
+        Self {
+            device: device.clone(),
+            samplers: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, data: &SamplerData) -> Sampler {
+        self.samplers
+            .entry(*data)
+            .or_insert_with(|| {
+                let filter = |filter| match filter {
+                    Filter::Nearest => MTLSamplerMinMagFilter::Nearest,
+                    Filter::Linear => MTLSamplerMinMagFilter::Linear,
+                };
+                let address = |wrap| match wrap {
+                    Wrap::Repeat => MTLSamplerAddressMode::Repeat,
+                    Wrap::MirroredRepeat => MTLSamplerAddressMode::MirrorRepeat,
+                    Wrap::ClampToEdge => MTLSamplerAddressMode::ClampToEdge,
+                };
+
+                let descriptor = MTLSamplerDescriptor::new();
+                descriptor.setMagFilter(filter(data.mag_filter));
+                descriptor.setMinFilter(filter(data.min_filter));
+                descriptor.setMipFilter(match data.mip_filter {
+                    None => MTLSamplerMipFilter::NotMipmapped,
+                    Some(Filter::Nearest) => MTLSamplerMipFilter::Nearest,
+                    Some(Filter::Linear) => MTLSamplerMipFilter::Linear,
+                });
+                descriptor.setSAddressMode(address(data.wrap_s));
+                descriptor.setTAddressMode(address(data.wrap_t));
+
+                log::info!("created sampler {:?}", data);
+                self.device
+                    .newSamplerStateWithDescriptor(&descriptor)
+                    .expect("Failed to create sampler state")
+            })
+            .clone()
+    }
+}
+
+// 1x1 white, bound in place of missing textures so untextured materials
+// just show their base color
+pub fn white_texture(device: &Retained<ProtocolObject<dyn MTLDevice>>) -> Texture {
+    let descriptor = unsafe {
+        MTLTextureDescriptor::texture2DDescriptorWithPixelFormat_width_height_mipmapped(
+            MTLPixelFormat::RGBA8Unorm,
+            1,
+            1,
+            false,
+        )
+    };
+    let texture = device
+        .newTextureWithDescriptor(&descriptor)
+        .expect("Failed to create texture");
+
+    let white = [255u8; 4];
+    let region = MTLRegion {
+        origin: MTLOrigin { x: 0, y: 0, z: 0 },
+        size: MTLSize {
+            width: 1,
+            height: 1,
+            depth: 1,
+        },
+    };
+    unsafe {
+        texture.replaceRegion_mipmapLevel_withBytes_bytesPerRow(
+            region,
+            0,
+            NonNull::from(&white).cast(),
+            4,
+        );
+    }
+
+    texture
 }
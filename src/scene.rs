@@ -0,0 +1,388 @@
+// Everything that gets drawn. Any number of assets, each placed with its own
+// root transform. The first one is the level, its cameras come first and
+// the bookmarks are kept with it.
+//
+// Every asset is an entity in the world, with one child entity per mesh
+// and per glTF camera. Lights and whatever gameplay spawns are entities too
+
+use std::path::{Path, PathBuf};
+
+use glam::{Mat4, Vec3};
+
+use crate::animation::Pose;
+use crate::camera::{Camera, Projection};
+use crate::catalog::AssetEntry;
+use crate::cook::{self, CookError};
+use crate::ecs::{Entity, System, World};
+use crate::game_loop::Simulation;
+use crate::gameplay;
+use crate::model::{self, LoadError, ModelData};
+use crate::player::AnimationPlayer;
+use crate::render::{Asset, MeshRenderer};
+use crate::resource::Device;
+use crate::texture;
+use crate::transform::{self, Transform};
+use crate::watch::FileWatcher;
+
+pub struct SceneObject {
+    pub entry: AssetEntry,
+    // carries the root transform, the parent of everything below
+    pub entity: Entity,
+    // one per mesh of the asset, in the same order
+    pub meshes: Vec<Entity>,
+    pub cameras: Vec<Entity>,
+    pub asset: Asset,
+    // CPU copy of the model, skinning starts from its bind pose every frame.
+    // No meshes when it came from a cooked file, those are static
+    pub data: ModelData,
+    pub player: AnimationPlayer,
+}
+
+// A component. The shader doesn't light anything yet, levels carry them
+// already so they're there once it does
+#[derive(Clone, Debug, PartialEq)]
+pub enum Light {
+    // `direction` is the way the light travels
+    Directional {
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+    // no range is infinite
+    Point {
+        position: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: Option<f32>,
+    },
+}
+
+pub struct Scene {
+    pub objects: Vec<SceneObject>,
+    pub world: World,
+    // run in order every tick, before animation
+    pub systems: Vec<Box<dyn System>>,
+    // how far the frame is between the last two ticks, 0..1. Everything is
+    // drawn there
+    pub alpha: f32,
+    // counts drawn frames, animation writes the deformed vertex buffers of
+    // this one (see render::FRAMES_IN_FLIGHT)
+    pub frame: usize,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            objects: Vec::new(),
+            world: World::default(),
+            systems: gameplay::systems(),
+            alpha: 1.0,
+            frame: 0,
+        }
+    }
+}
+
+impl Scene {
+    // Loads the asset's model with `root` on top of it. The scene is left as
+    // it was when that fails
+    pub fn add(
+        &mut self,
+        device: &Device,
+        entry: AssetEntry,
+        root: Mat4,
+    ) -> Result<&mut SceneObject, LoadError> {
+        let mut asset = Asset::new(device);
+        let (data, mesh_transforms) = load_model(device, &mut asset, &entry.model)?;
+        log::info!("added {} to the scene", entry.name);
+
+        let entity = self.world.spawn();
+        self.world.insert(entity, Transform::from_matrix(root));
+        let mut object = SceneObject {
+            entry,
+            entity,
+            meshes: Vec::new(),
+            cameras: Vec::new(),
+            player: animation_player(&data),
+            asset,
+            data,
+        };
+        spawn_parts(&mut self.world, &mut object, &mesh_transforms);
+        transform::propagate(&mut self.world);
+        self.objects.push(object);
+        Ok(self.objects.last_mut().unwrap())
+    }
+
+    // Its entities, GPU buffers and textures go with it. Assets parented to
+    // it stay where they are, under its parent instead
+    pub fn remove(&mut self, i: usize) {
+        let object = self.objects.remove(i);
+        let parent = transform::parent(&self.world, object.entity);
+        for other in &self.objects {
+            if transform::parent(&self.world, other.entity) == Some(object.entity) {
+                transform::reparent(&mut self.world, other.entity, parent);
+            }
+        }
+        transform::despawn_recursive(&mut self.world, object.entity);
+        transform::propagate(&mut self.world);
+        log::info!("removed {} from the scene", object.entry.name);
+    }
+
+    pub fn add_light(&mut self, light: Light) -> Entity {
+        let entity = self.world.spawn();
+        self.world.insert(entity, light);
+        entity
+    }
+
+    // The asset of the scene object with that entity
+    pub fn asset(&self, entity: Entity) -> Option<&Asset> {
+        self.objects
+            .iter()
+            .find(|object| object.entity == entity)
+            .map(|object| &object.asset)
+    }
+
+    // Where the entity is drawn this frame, between its last two ticks
+    pub fn render_transform(&self, entity: Entity) -> Option<Mat4> {
+        let transform = self.world.get::<Transform>(entity)?;
+        Some(transform.interpolated(self.alpha))
+    }
+
+    // Every glTF camera of every asset, in the order they were added
+    pub fn cameras(&self) -> Vec<Camera> {
+        self.objects
+            .iter()
+            .flat_map(|object| &object.cameras)
+            .filter_map(|&entity| {
+                let projection = self.world.get::<Projection>(entity)?;
+                Some(Camera::from_transform(
+                    self.render_transform(entity)?,
+                    *projection,
+                ))
+            })
+            .collect()
+    }
+
+    // e.g. "Sponza + Damaged Helmet"
+    pub fn title(&self) -> String {
+        let names: Vec<&str> = self
+            .objects
+            .iter()
+            .map(|object| object.entry.name.as_str())
+            .collect();
+        names.join(" + ")
+    }
+
+    // The models' own files plus every image their materials use, and the
+    // .dds next to it that texture::load would pick instead
+    pub fn watch(&self, watcher: &mut FileWatcher) {
+        let mut files = Vec::new();
+        for object in &self.objects {
+            let path = &object.entry.model;
+            files.extend(cook::sources(path).unwrap_or_else(|e| {
+                log::warn!("{}: {}, only watching the file itself", path.display(), e);
+                vec![path.to_path_buf()]
+            }));
+            for image in object.asset.texture_sources() {
+                files.push(image.to_path_buf());
+                files.extend(texture::compressed_path(image));
+            }
+        }
+        watcher.watch_only(files.iter().map(PathBuf::as_path));
+    }
+
+    // Everything as one static model in world space, e.g. to export it. The
+    // GPU copy of a cooked model can't be read back, so every asset is
+    // imported again from its files
+    pub fn flatten(&self) -> Result<ModelData, LoadError> {
+        let mut model = ModelData {
+            name: self.title(),
+            ..ModelData::default()
+        };
+        for object in &self.objects {
+            model.append(
+                model::load(&object.entry.model)?,
+                self.world
+                    .get::<Transform>(object.entity)
+                    .map_or(Mat4::IDENTITY, Transform::world),
+                object.asset.overrides(),
+            );
+        }
+        Ok(model)
+    }
+
+    // Any of a glTF's files (or the OBJ and its .mtl) in `changed` reloads
+    // that whole model, an image only the materials using it. A model that
+    // fails to load keeps the old one on screen
+    pub fn reload_changed(&mut self, device: &Device, changed: &[PathBuf]) {
+        for object in &mut self.objects {
+            let path = &object.entry.model;
+            let sources = cook::sources(path).unwrap_or_default();
+            if changed
+                .iter()
+                .any(|changed| changed == path || sources.contains(changed))
+            {
+                match load_model(device, &mut object.asset, path) {
+                    Ok((data, mesh_transforms)) => {
+                        log::info!("reloaded {}", path.display());
+                        object.player = animation_player(&data);
+                        object.data = data;
+                        spawn_parts(&mut self.world, object, &mesh_transforms);
+                        transform::propagate(&mut self.world);
+                    }
+                    Err(e) => log::error!(
+                        "could not reload {}, keeping the old one: {}",
+                        path.display(),
+                        e
+                    ),
+                }
+            }
+
+            if object.asset.reload_textures(changed) {
+                log::info!("reloaded textures of {}", object.entry.name);
+            }
+        }
+    }
+}
+
+impl Simulation for Scene {
+    // Runs the systems, then moves and deforms animated meshes and brings
+    // world transforms up to date. Skinned and morphed vertices only change
+    // here, they aren't interpolated
+    fn tick(&mut self, time: f64, dt: f32) {
+        transform::snapshot(&mut self.world);
+        for system in &mut self.systems {
+            system.run(&mut self.world, dt);
+        }
+
+        for object in &mut self.objects {
+            let clips = &object.data.animations;
+            let player = &mut object.player;
+            player.update(time, clips);
+            while let Some(event) = player.poll_event() {
+                log::debug!("animation event {:?} at {:.3}s", event.name, event.time);
+            }
+
+            let Some(pose) = player.pose(&object.data.nodes, clips) else {
+                continue;
+            };
+            object.asset.apply_pose(&object.data, &pose, self.frame);
+            // skinned meshes stay where they are, their joints move
+            for (data, &entity) in object.data.meshes.iter().zip(&object.meshes) {
+                if let Some(node) = data.node
+                    && data.skin.is_none()
+                    && let Some(transform) = self.world.get_mut::<Transform>(entity)
+                {
+                    transform.set_matrix(pose.world[node]);
+                }
+            }
+        }
+
+        transform::propagate(&mut self.world);
+    }
+}
+
+// Puts the model at `path` into the asset, from the cooked file when it's up
+// to date. Otherwise the source gets imported and cooked for the next
+// launch. Also returns where each mesh sits in the model, the ModelData of
+// a cooked one has no meshes. The asset is left as it was when loading fails
+fn load_model(
+    device: &Device,
+    asset: &mut Asset,
+    path: &Path,
+) -> Result<(ModelData, Vec<Mat4>), LoadError> {
+    match cook::load(path) {
+        Ok(cooked) => {
+            let bounds = cooked.bounds();
+            log::info!(
+                "loaded cooked {}, {} meshes within {:?}..{:?}",
+                cooked.data.name,
+                cooked.meshes.len(),
+                bounds.min,
+                bounds.max
+            );
+            asset.set_cooked(device, &cooked);
+            let mesh_transforms = cooked.meshes.iter().map(|mesh| mesh.transform).collect();
+            return Ok((cooked.data, mesh_transforms));
+        }
+        Err(CookError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => log::info!("not using {}: {}", cook::cooked_path(path).display(), e),
+    }
+
+    let model_data = model::load(path)?;
+    if cook::is_cookable(&model_data) {
+        match cook::write(&model_data, path) {
+            Ok(cooked) => log::info!("cooked {}", cooked.display()),
+            Err(e) => log::warn!("could not cook {}: {}", path.display(), e),
+        }
+    }
+    asset.set_model(device, &model_data);
+
+    // skinned meshes are uploaded in bind pose, which isn't necessarily the
+    // rest pose, and morphed ones without their default weights
+    let deformed = model_data
+        .meshes
+        .iter()
+        .any(|mesh| !mesh.targets.is_empty());
+    let mut mesh_transforms: Vec<Mat4> = model_data
+        .meshes
+        .iter()
+        .map(|mesh| mesh.transform)
+        .collect();
+    if !model_data.skins.is_empty() || deformed {
+        let pose = Pose::rest(&model_data.nodes);
+        // into buffers the GPU hasn't seen yet, any frame will do
+        asset.apply_pose(&model_data, &pose, 0);
+        for (transform, mesh) in mesh_transforms.iter_mut().zip(&model_data.meshes) {
+            if let Some(node) = mesh.node
+                && mesh.skin.is_none()
+            {
+                *transform = pose.world[node];
+            }
+        }
+    }
+    Ok((model_data, mesh_transforms))
+}
+
+// An entity for every mesh and glTF camera, relative to the object's own.
+// Replaces the ones from before a reload
+fn spawn_parts(world: &mut World, object: &mut SceneObject, mesh_transforms: &[Mat4]) {
+    for entity in object.meshes.drain(..).chain(object.cameras.drain(..)) {
+        transform::despawn_recursive(world, entity);
+    }
+    for (mesh, &matrix) in mesh_transforms.iter().enumerate() {
+        let entity = spawn_child(world, object.entity, matrix);
+        world.insert(
+            entity,
+            MeshRenderer {
+                asset: object.entity,
+                mesh,
+            },
+        );
+        object.meshes.push(entity);
+    }
+    for &(matrix, projection) in &object.data.cameras {
+        let entity = spawn_child(world, object.entity, matrix);
+        world.insert(entity, projection);
+        object.cameras.push(entity);
+    }
+}
+
+fn spawn_child(world: &mut World, parent: Entity, matrix: Mat4) -> Entity {
+    let entity = world.spawn();
+    world.insert(entity, Transform::from_matrix(matrix));
+    transform::set_parent(world, entity, Some(parent));
+    entity
+}
+
+// Starts on the first clip, if there is one. Every keyframe of every clip
+// fires an event whenever that clip plays
+fn animation_player(model: &ModelData) -> AnimationPlayer {
+    let mut player = AnimationPlayer::new(model.animations.len());
+    for (clip, animation) in model.animations.iter().enumerate() {
+        player.add_keyframe_markers(clip, animation);
+    }
+    if !model.animations.is_empty() {
+        player.play(0);
+    }
+    player
+}
@@ -12,11 +12,33 @@ struct VertexIn {
     float3 position [[attribute(0)]];
     float3 normals [[attribute(1)]];
     float2 texCoord [[attribute(2)]];
+    float4 tangent [[attribute(3)]];
+    float2 texCoord1 [[attribute(4)]];
+};
+
+// see TextureUniforms in render.rs
+struct TextureInfo {
+    float3x3 uvTransform;
+    // which uv set the texture uses, 0 or 1
+    uint texCoord;
+};
+
+// see MaterialUniforms in render.rs
+struct Material {
+    float4 baseColor;
+    float3 emissive;
+    float occlusionStrength;
+    TextureInfo baseColorTexture;
+    TextureInfo emissiveTexture;
+    TextureInfo occlusionTexture;
 };
 
 struct VSOut {
     float4 position [[position]];
     float2 texCoord;
+    float2 texCoord1;
+    // only used when drawing points
+    float pointSize [[point_size]];
 };
 
 vertex VSOut vertex_main(
@@ -26,19 +48,39 @@ vertex VSOut vertex_main(
       VSOut out;
       out.position =  uniforms.view_proj * uniforms.model * float4(in.position, 1.0);
       out.texCoord = in.texCoord;
+      out.texCoord1 = in.texCoord1;
+      out.pointSize = 4.0;
       return out;
   }
 
 
+float2 textureUV(VSOut in, TextureInfo info) {
+    float2 uv = info.texCoord == 1 ? in.texCoord1 : in.texCoord;
+    return (info.uvTransform * float3(uv, 1.0)).xy;
+}
+
+// sRGB textures are decoded on sampling and the drawable encodes on write,
+// so everything in between is linear
 fragment float4 fragment_main(
     VSOut in [[stage_in]],
-    texture2d<float> colorTexture [[texture(0)]]
+    constant Material& material [[buffer(BufferKind_Material)]],
+    texture2d<float> colorTexture [[texture(0)]],
+    texture2d<float> emissiveTexture [[texture(1)]],
+    texture2d<float> occlusionTexture [[texture(2)]],
+    // each texture's own, see SamplerCache
+    sampler colorSampler [[sampler(0)]],
+    sampler emissiveSampler [[sampler(1)]],
+    sampler occlusionSampler [[sampler(2)]]
 ) {
-    constexpr sampler textureSampler(
-        mag_filter::linear,
-        min_filter::linear,
-        mip_filter::linear,
-        address::repeat
-    );
-    return colorTexture.sample(textureSampler, in.texCoord);
+    float4 color = material.baseColor
+        * colorTexture.sample(colorSampler, textureUV(in, material.baseColorTexture));
+
+    // occlusion lives in the red channel
+    float occlusion =
+        occlusionTexture.sample(occlusionSampler, textureUV(in, material.occlusionTexture)).r;
+    color.rgb *= mix(1.0, occlusion, material.occlusionStrength);
+
+    color.rgb += material.emissive
+        * emissiveTexture.sample(emissiveSampler, textureUV(in, material.emissiveTexture)).rgb;
+    return color;
 }
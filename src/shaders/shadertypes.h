@@ -4,5 +4,6 @@ enum BufferKinds
     BufferKind_Positions    = 1,
     //AAPLBufferIndexNormals      = 1,
     BufferKind_Texcoords    = 2,
+    BufferKind_Material     = 3,
     //AAPLBufferIndexObjectParams = 3,
 };
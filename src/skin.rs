@@ -0,0 +1,135 @@
+use glam::{Mat4, Vec3};
+
+use crate::animation::{Pose, SkinData};
+use crate::model::MeshData;
+
+// Joint matrices take a vertex from bind pose to the posed model space
+pub fn joint_matrices(skin: &SkinData, pose: &Pose) -> Vec<Mat4> {
+    skin.joints
+        .iter()
+        .zip(&skin.inverse_bind_matrices)
+        .map(|(&joint, inverse_bind)| pose.world[joint] * *inverse_bind)
+        .collect()
+}
+
+// Linear blend skinning on the CPU, in place on vertices with the interleaved
+// layout of `MeshData::vertex`. Joints and weights come from `mesh`, the
+// vertices may already be morphed. Vertices without joints and joint indices
+// past the end of the skin (broken files) are left alone
+pub fn skin_vertices(mesh: &MeshData, joint_matrices: &[Mat4], vertices: &mut [[f32; 14]]) {
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let (Some(joints), Some(weights)) = (mesh.joints.get(i), mesh.weights.get(i)) else {
+            continue;
+        };
+
+        let mut skin = Mat4::ZERO;
+        for k in 0..4 {
+            if weights[k] != 0.0
+                && let Some(joint) = joint_matrices.get(joints[k] as usize)
+            {
+                skin += *joint * weights[k];
+            }
+        }
+        if skin == Mat4::ZERO {
+            continue;
+        }
+
+        let position = skin.transform_point3(Vec3::from_slice(&vertex[0..3]));
+        // no non-uniform scale on joints in practice, so skip the inverse transpose
+        let normal = skin
+            .transform_vector3(Vec3::from_slice(&vertex[3..6]))
+            .normalize_or_zero();
+        let tangent = skin
+            .transform_vector3(Vec3::from_slice(&vertex[8..11]))
+            .normalize_or_zero();
+
+        vertex[0..3].copy_from_slice(&position.to_array());
+        vertex[3..6].copy_from_slice(&normal.to_array());
+        vertex[8..11].copy_from_slice(&tangent.to_array());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::model::Topology;
+
+    fn mesh() -> MeshData {
+        MeshData {
+            name: String::from("test"),
+            positions: vec![
+                [1.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [2.0, 0.0, 0.0],
+                [1.0, 1.0, 1.0],
+            ],
+            normals: vec![
+                [0.0, 0.0, 1.0],
+                [1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0],
+            ],
+            uvs: vec![[0.0; 2]; 4],
+            extra_uvs: Vec::new(),
+            tangents: vec![[1.0, 0.0, 0.0, 1.0]; 4],
+            indices: Vec::new(),
+            topology: Topology::Points,
+            material: None,
+            transform: Mat4::IDENTITY,
+            node: None,
+            skin: Some(0),
+            // the last one points past the end of the skin
+            joints: vec![[0, 0, 0, 0], [1, 0, 0, 0], [0, 1, 0, 0], [7, 0, 0, 0]],
+            weights: vec![
+                [1.0, 0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0, 0.0],
+                [0.5, 0.5, 0.0, 0.0],
+                [1.0, 0.0, 0.0, 0.0],
+            ],
+            targets: Vec::new(),
+            morph_weights: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn blends_joint_matrices() {
+        let mesh = mesh();
+        let joint_matrices = [
+            Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0)),
+            // 90 degrees around z, spelled out so it's exact
+            Mat4::from_cols_array(&[
+                0.0, 1.0, 0.0, 0.0, //
+                -1.0, 0.0, 0.0, 0.0, //
+                0.0, 0.0, 1.0, 0.0, //
+                0.0, 0.0, 0.0, 1.0,
+            ]),
+        ];
+        let mut vertices: Vec<[f32; 14]> = (0..4).map(|i| mesh.vertex(i)).collect();
+        skin_vertices(&mesh, &joint_matrices, &mut vertices);
+
+        let positions: Vec<[f32; 3]> = vertices.iter().map(|v| [v[0], v[1], v[2]]).collect();
+        let normals: Vec<[f32; 3]> = vertices.iter().map(|v| [v[3], v[4], v[5]]).collect();
+        let tangents: Vec<[f32; 3]> = vertices.iter().map(|v| [v[8], v[9], v[10]]).collect();
+        assert_eq!(
+            positions,
+            [
+                [2.0, 2.0, 3.0],
+                [0.0, 1.0, 0.0],
+                [1.5, 2.0, 1.5],
+                [1.0, 1.0, 1.0]
+            ]
+        );
+        assert_eq!(
+            normals,
+            [
+                [0.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [0.0, 1.0, 0.0]
+            ]
+        );
+        assert_eq!(tangents[1], [0.0, 1.0, 0.0]);
+        assert_eq!(tangents[3], [1.0, 0.0, 0.0]);
+    }
+}
@@ -0,0 +1,862 @@
+// Backend independent image loading: decodes PNG and JPEG into RGBA8 mip
+// chains that can be copied straight into a texture. Mips are box filtered
+// on linear, premultiplied values, so sRGB images don't darken as they
+// shrink and fully transparent texels don't bleed their color into the
+// visible ones.
+// KTX2 and DDS files are read as they are, block compressed levels included
+
+use ddsfile::{AlphaMode, Caps2, D3D10ResourceDimension, Dds, DxgiFormat, FourCC, NewDxgiParams};
+use glam::Vec4;
+use image::ImageReader;
+use std::fs::File;
+use std::io::{BufWriter, Read};
+use std::path::{Path, PathBuf};
+
+use crate::basis;
+use crate::model::ColorSpace;
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Ktx2(ktx2::ParseError),
+    Dds(ddsfile::Error),
+    Invalid(String),
+    Unsupported(String),
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "{}", e),
+            TextureError::Image(e) => write!(f, "{}", e),
+            TextureError::Ktx2(e) => write!(f, "ktx2: {:?}", e),
+            TextureError::Dds(e) => write!(f, "dds: {}", e),
+            TextureError::Invalid(what) => write!(f, "invalid: {}", what),
+            TextureError::Unsupported(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl From<std::io::Error> for TextureError {
+    fn from(e: std::io::Error) -> Self {
+        TextureError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(e: image::ImageError) -> Self {
+        TextureError::Image(e)
+    }
+}
+
+impl From<ktx2::ParseError> for TextureError {
+    fn from(e: ktx2::ParseError) -> Self {
+        TextureError::Ktx2(e)
+    }
+}
+
+impl From<ddsfile::Error> for TextureError {
+    fn from(e: ddsfile::Error) -> Self {
+        TextureError::Dds(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, TextureError>;
+
+// How a level's bytes are laid out. Everything but Rgba8 is stored in
+// blocks, rows of blocks are tightly packed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Rgba8,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    // unsigned half floats
+    Bc6h,
+    Bc7,
+    // block footprint in texels, LDR only
+    Astc { width: u32, height: u32 },
+}
+
+impl TextureFormat {
+    // in texels
+    pub fn block_size(self) -> (u32, u32) {
+        match self {
+            TextureFormat::Rgba8 => (1, 1),
+            TextureFormat::Astc { width, height } => (width, height),
+            _ => (4, 4),
+        }
+    }
+
+    pub fn block_bytes(self) -> usize {
+        match self {
+            TextureFormat::Rgba8 => 4,
+            TextureFormat::Bc1 | TextureFormat::Bc4 => 8,
+            _ => 16,
+        }
+    }
+
+    // bytes in one row of blocks
+    pub fn row_bytes(self, width: u32) -> usize {
+        width.div_ceil(self.block_size().0) as usize * self.block_bytes()
+    }
+
+    pub fn level_bytes(self, width: u32, height: u32) -> usize {
+        self.row_bytes(width) * height.div_ceil(self.block_size().1) as usize
+    }
+
+    // the others only come in a linear variant
+    pub fn has_srgb(self) -> bool {
+        !matches!(
+            self,
+            TextureFormat::Bc4 | TextureFormat::Bc5 | TextureFormat::Bc6h
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    // how the stored values are encoded, see ColorSpace
+    pub color_space: ColorSpace,
+    pub mipmaps: bool,
+    // store color multiplied by alpha, for One/OneMinusSrcAlpha blending
+    pub premultiply_alpha: bool,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        Self {
+            color_space: ColorSpace::Srgb,
+            mipmaps: true,
+            premultiply_alpha: false,
+        }
+    }
+}
+
+// Tightly packed rows, of texels or blocks depending on the format
+#[derive(Clone, Debug)]
+pub struct MipLevel {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TextureData {
+    pub format: TextureFormat,
+    pub color_space: ColorSpace,
+    pub premultiplied: bool,
+    // full size first, then halving down to 1x1 when mipmapped
+    pub levels: Vec<MipLevel>,
+}
+
+impl TextureData {
+    pub fn width(&self) -> u32 {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.levels[0].height
+    }
+}
+
+// KTX2 and DDS go by extension, any other image by its contents. For a PNG
+// or JPEG, a .dds next to it that's newer (see compress.rs) is used instead
+pub fn load(path: &Path, options: TextureOptions) -> Result<TextureData> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    let texture = match extension.as_deref() {
+        Some("ktx2") => load_ktx2(&std::fs::read(path)?, options)?,
+        Some("dds") => load_dds(&std::fs::read(path)?, options)?,
+        _ => match load_compressed(path, options) {
+            Some(texture) => texture,
+            None => {
+                let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+                let (width, height) = (image.width(), image.height());
+                let data = image.into_rgba8().into_raw();
+                from_rgba8(width, height, data, options)
+            }
+        },
+    };
+
+    if texture.color_space != options.color_space && texture.format.has_srgb() {
+        log::warn!(
+            "{} is stored as {:?} but used as {:?}",
+            path.display(),
+            texture.color_space,
+            options.color_space
+        );
+    }
+    Ok(texture)
+}
+
+// The .dds `bs compress` writes next to a PNG or JPEG, which load prefers
+// while it's newer. KTX2 and DDS files are compressed already
+pub fn compressed_path(path: &Path) -> Option<PathBuf> {
+    let extension = path.extension().and_then(|e| e.to_str());
+    if extension.is_some_and(|e| e.eq_ignore_ascii_case("ktx2") || e.eq_ignore_ascii_case("dds")) {
+        return None;
+    }
+    Some(path.with_extension("dds"))
+}
+
+fn load_compressed(path: &Path, options: TextureOptions) -> Option<TextureData> {
+    let compressed = compressed_path(path)?;
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    if modified(&compressed)? < modified(path)? {
+        log::warn!(
+            "{} is older than its source, ignoring it",
+            compressed.display()
+        );
+        return None;
+    }
+
+    let texture = std::fs::read(&compressed)
+        .map_err(TextureError::from)
+        .and_then(|bytes| load_dds(&bytes, options));
+    match texture {
+        // baked for the other use of a shared image
+        Ok(texture) if texture.color_space != options.color_space => None,
+        Ok(texture) => Some(texture),
+        Err(e) => {
+            log::warn!("{}: {}", compressed.display(), e);
+            None
+        }
+    }
+}
+
+// For images that are already in memory, e.g. embedded in a buffer
+pub fn decode(bytes: &[u8], options: TextureOptions) -> Result<TextureData> {
+    let image = image::load_from_memory(bytes)?;
+    let (width, height) = (image.width(), image.height());
+    let data = image.into_rgba8().into_raw();
+    Ok(from_rgba8(width, height, data, options))
+}
+
+// `data` is straight alpha RGBA8, encoded as `options.color_space` says
+pub fn from_rgba8(width: u32, height: u32, data: Vec<u8>, options: TextureOptions) -> TextureData {
+    assert_eq!(data.len(), width as usize * height as usize * 4);
+
+    let srgb = options.color_space == ColorSpace::Srgb;
+    let premultiply = options.premultiply_alpha;
+    let mut texture = TextureData {
+        format: TextureFormat::Rgba8,
+        color_space: options.color_space,
+        premultiplied: premultiply,
+        levels: Vec::new(),
+    };
+
+    // nothing to compute, the decoded bytes are used as they are
+    if !options.mipmaps && !premultiply {
+        texture.levels.push(MipLevel {
+            width,
+            height,
+            data,
+        });
+        return texture;
+    }
+
+    let mut texels = to_linear(&data, srgb);
+    // keep the decoded bytes when they don't change, a round trip through
+    // float could be off by one
+    let data = if premultiply {
+        to_rgba8(&texels, srgb, true)
+    } else {
+        data
+    };
+    texture.levels.push(MipLevel {
+        width,
+        height,
+        data,
+    });
+
+    let (mut width, mut height) = (width, height);
+    while options.mipmaps && (width > 1 || height > 1) {
+        (width, height, texels) = downsample(width, height, &texels);
+        texture.levels.push(MipLevel {
+            width,
+            height,
+            data: to_rgba8(&texels, srgb, premultiply),
+        });
+    }
+
+    texture
+}
+
+// Containers can hold a single uncompressed level, that one still gets mips
+fn with_mips(mut texture: TextureData, options: TextureOptions) -> TextureData {
+    if texture.format == TextureFormat::Rgba8 && texture.levels.len() == 1 && options.mipmaps {
+        let level = texture.levels.remove(0);
+        let options = TextureOptions {
+            color_space: texture.color_space,
+            ..options
+        };
+        return from_rgba8(level.width, level.height, level.data, options);
+    }
+    texture
+}
+
+// Each level is checked against the size its format needs
+fn level(format: TextureFormat, width: u32, height: u32, data: Vec<u8>) -> Result<MipLevel> {
+    let expected = format.level_bytes(width, height);
+    if data.len() != expected {
+        return Err(TextureError::Invalid(format!(
+            "{}x{} level has {} bytes, expected {}",
+            width,
+            height,
+            data.len(),
+            expected
+        )));
+    }
+    Ok(MipLevel {
+        width,
+        height,
+        data,
+    })
+}
+
+// Footprints of the ASTC formats in the order Vulkan and Metal list them
+const ASTC_BLOCKS: [(u32, u32); 14] = [
+    (4, 4),
+    (5, 4),
+    (5, 5),
+    (6, 5),
+    (6, 6),
+    (8, 5),
+    (8, 6),
+    (8, 8),
+    (10, 5),
+    (10, 6),
+    (10, 8),
+    (10, 10),
+    (12, 10),
+    (12, 12),
+];
+
+// KHR_texture_basisu images are Basis Universal, those are transcoded to
+// BC7 by basis.rs
+pub fn load_ktx2(bytes: &[u8], options: TextureOptions) -> Result<TextureData> {
+    let reader = ktx2::Reader::new(bytes)?;
+    let header = reader.header();
+    if header.pixel_height == 0 || header.pixel_depth > 1 {
+        return Err(TextureError::Unsupported(String::from(
+            "1D and 3D ktx2 textures",
+        )));
+    }
+    if header.layer_count > 1 || header.face_count > 1 {
+        return Err(TextureError::Unsupported(String::from(
+            "ktx2 arrays and cube maps",
+        )));
+    }
+    // Basis Universal is the only thing that leaves the format undefined
+    let Some(vk_format) = header.format else {
+        return load_basis(&reader, options);
+    };
+    let (format, color_space) = ktx2_format(vk_format)
+        .ok_or_else(|| TextureError::Unsupported(format!("ktx2 format {:?}", vk_format)))?;
+
+    let mut levels = Vec::new();
+    for (index, data) in reader.levels().enumerate() {
+        let data = match header.supercompression_scheme {
+            None => data.data.to_vec(),
+            Some(ktx2::SupercompressionScheme::Zstandard) => {
+                let mut decoded = Vec::new();
+                ruzstd::decoding::StreamingDecoder::new(data.data)
+                    .map_err(std::io::Error::other)?
+                    .read_to_end(&mut decoded)?;
+                decoded
+            }
+            Some(scheme) => {
+                return Err(TextureError::Unsupported(format!(
+                    "ktx2 supercompression {:?}",
+                    scheme
+                )));
+            }
+        };
+        let width = (header.pixel_width >> index).max(1);
+        let height = (header.pixel_height >> index).max(1);
+        levels.push(level(format, width, height, data)?);
+
+        if !options.mipmaps {
+            break;
+        }
+    }
+
+    let texture = TextureData {
+        format,
+        color_space,
+        premultiplied: false,
+        levels,
+    };
+    Ok(with_mips(texture, options))
+}
+
+fn load_basis(reader: &ktx2::Reader<&[u8]>, options: TextureOptions) -> Result<TextureData> {
+    let header = reader.header();
+    let count = if options.mipmaps { usize::MAX } else { 1 };
+    let transcoded = basis::transcode_bc7(reader, count)
+        .map_err(|e| TextureError::Invalid(format!("basis: {}", e)))?;
+
+    let mut levels = Vec::new();
+    for (index, data) in transcoded.levels.into_iter().enumerate() {
+        let width = (header.pixel_width >> index).max(1);
+        let height = (header.pixel_height >> index).max(1);
+        levels.push(level(TextureFormat::Bc7, width, height, data)?);
+    }
+    Ok(TextureData {
+        format: TextureFormat::Bc7,
+        color_space: if transcoded.srgb {
+            ColorSpace::Srgb
+        } else {
+            ColorSpace::Linear
+        },
+        premultiplied: false,
+        levels,
+    })
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<(TextureFormat, ColorSpace)> {
+    use ColorSpace::{Linear, Srgb};
+    use TextureFormat::*;
+    use ktx2::Format as Vk;
+
+    // unorm and srgb alternate, one pair per footprint
+    let astc = Vk::ASTC_4x4_UNORM_BLOCK.value()..=Vk::ASTC_12x12_SRGB_BLOCK.value();
+    if astc.contains(&format.value()) {
+        let index = (format.value() - astc.start()) as usize;
+        let (width, height) = ASTC_BLOCKS[index / 2];
+        let color_space = if index % 2 == 1 { Srgb } else { Linear };
+        return Some((Astc { width, height }, color_space));
+    }
+
+    Some(match format {
+        Vk::R8G8B8A8_UNORM => (Rgba8, Linear),
+        Vk::R8G8B8A8_SRGB => (Rgba8, Srgb),
+        Vk::BC1_RGB_UNORM_BLOCK | Vk::BC1_RGBA_UNORM_BLOCK => (Bc1, Linear),
+        Vk::BC1_RGB_SRGB_BLOCK | Vk::BC1_RGBA_SRGB_BLOCK => (Bc1, Srgb),
+        Vk::BC2_UNORM_BLOCK => (Bc2, Linear),
+        Vk::BC2_SRGB_BLOCK => (Bc2, Srgb),
+        Vk::BC3_UNORM_BLOCK => (Bc3, Linear),
+        Vk::BC3_SRGB_BLOCK => (Bc3, Srgb),
+        Vk::BC4_UNORM_BLOCK => (Bc4, Linear),
+        Vk::BC5_UNORM_BLOCK => (Bc5, Linear),
+        Vk::BC6H_UFLOAT_BLOCK => (Bc6h, Linear),
+        Vk::BC7_UNORM_BLOCK => (Bc7, Linear),
+        Vk::BC7_SRGB_BLOCK => (Bc7, Srgb),
+        _ => return None,
+    })
+}
+
+// Only DX10 headers say whether the data is sRGB, older files are taken to
+// be what they're used as
+pub fn load_dds(bytes: &[u8], options: TextureOptions) -> Result<TextureData> {
+    let dds = Dds::read(bytes)?;
+    if dds.get_depth() > 1 {
+        return Err(TextureError::Unsupported(String::from("3D dds textures")));
+    }
+    if dds.get_num_array_layers() > 1 || dds.header.caps2.contains(Caps2::CUBEMAP) {
+        return Err(TextureError::Unsupported(String::from(
+            "dds arrays and cube maps",
+        )));
+    }
+
+    let (format, color_space, premultiplied) = match &dds.header10 {
+        Some(header10) => {
+            let (format, color_space) = dxgi_format(header10.dxgi_format).ok_or_else(|| {
+                TextureError::Unsupported(format!("dds format {:?}", header10.dxgi_format))
+            })?;
+            (
+                format,
+                color_space,
+                header10.alpha_mode == AlphaMode::PreMultiplied,
+            )
+        }
+        None => {
+            let fourcc = dds.header.spf.fourcc.as_ref().map(|fourcc| fourcc.0);
+            let format = match fourcc {
+                Some(FourCC::DXT1) => TextureFormat::Bc1,
+                Some(FourCC::DXT2 | FourCC::DXT3) => TextureFormat::Bc2,
+                Some(FourCC::DXT4 | FourCC::DXT5) => TextureFormat::Bc3,
+                Some(FourCC::ATI1 | FourCC::BC4_UNORM) => TextureFormat::Bc4,
+                Some(FourCC::ATI2) => TextureFormat::Bc5,
+                _ => {
+                    return Err(TextureError::Unsupported(format!(
+                        "dds pixel format {:?}",
+                        dds.header.spf
+                    )));
+                }
+            };
+            let color_space = if format.has_srgb() {
+                options.color_space
+            } else {
+                ColorSpace::Linear
+            };
+            // DXT2 and DXT4 are the premultiplied DXT3 and DXT5
+            let premultiplied = matches!(fourcc, Some(FourCC::DXT2 | FourCC::DXT4));
+            (format, color_space, premultiplied)
+        }
+    };
+
+    let count = if options.mipmaps {
+        dds.get_num_mipmap_levels().max(1)
+    } else {
+        1
+    };
+    let mut levels = Vec::new();
+    let mut offset = 0;
+    for index in 0..count {
+        let width = (dds.get_width() >> index).max(1);
+        let height = (dds.get_height() >> index).max(1);
+        let size = format.level_bytes(width, height);
+        let data = dds
+            .data
+            .get(offset..offset + size)
+            .ok_or_else(|| TextureError::Invalid(format!("dds level {} is truncated", index)))?;
+        levels.push(level(format, width, height, data.to_vec())?);
+        offset += size;
+    }
+
+    let texture = TextureData {
+        format,
+        color_space,
+        premultiplied,
+        levels,
+    };
+    Ok(with_mips(texture, options))
+}
+
+fn dxgi_format(format: DxgiFormat) -> Option<(TextureFormat, ColorSpace)> {
+    use ColorSpace::{Linear, Srgb};
+    use TextureFormat::*;
+
+    Some(match format {
+        DxgiFormat::R8G8B8A8_UNorm => (Rgba8, Linear),
+        DxgiFormat::R8G8B8A8_UNorm_sRGB => (Rgba8, Srgb),
+        DxgiFormat::BC1_UNorm => (Bc1, Linear),
+        DxgiFormat::BC1_UNorm_sRGB => (Bc1, Srgb),
+        DxgiFormat::BC2_UNorm => (Bc2, Linear),
+        DxgiFormat::BC2_UNorm_sRGB => (Bc2, Srgb),
+        DxgiFormat::BC3_UNorm => (Bc3, Linear),
+        DxgiFormat::BC3_UNorm_sRGB => (Bc3, Srgb),
+        DxgiFormat::BC4_UNorm => (Bc4, Linear),
+        DxgiFormat::BC5_UNorm => (Bc5, Linear),
+        DxgiFormat::BC6H_UF16 => (Bc6h, Linear),
+        DxgiFormat::BC7_UNorm => (Bc7, Linear),
+        DxgiFormat::BC7_UNorm_sRGB => (Bc7, Srgb),
+        _ => return None,
+    })
+}
+
+// Always with a DX10 header, so the format records the color space
+pub fn write_dds(texture: &TextureData, path: &Path) -> Result<()> {
+    let srgb = texture.color_space == ColorSpace::Srgb;
+    let format = match texture.format {
+        TextureFormat::Rgba8 if srgb => DxgiFormat::R8G8B8A8_UNorm_sRGB,
+        TextureFormat::Rgba8 => DxgiFormat::R8G8B8A8_UNorm,
+        TextureFormat::Bc1 if srgb => DxgiFormat::BC1_UNorm_sRGB,
+        TextureFormat::Bc1 => DxgiFormat::BC1_UNorm,
+        TextureFormat::Bc2 if srgb => DxgiFormat::BC2_UNorm_sRGB,
+        TextureFormat::Bc2 => DxgiFormat::BC2_UNorm,
+        TextureFormat::Bc3 if srgb => DxgiFormat::BC3_UNorm_sRGB,
+        TextureFormat::Bc3 => DxgiFormat::BC3_UNorm,
+        TextureFormat::Bc4 => DxgiFormat::BC4_UNorm,
+        TextureFormat::Bc5 => DxgiFormat::BC5_UNorm,
+        TextureFormat::Bc6h => DxgiFormat::BC6H_UF16,
+        TextureFormat::Bc7 if srgb => DxgiFormat::BC7_UNorm_sRGB,
+        TextureFormat::Bc7 => DxgiFormat::BC7_UNorm,
+        TextureFormat::Astc { .. } => {
+            return Err(TextureError::Unsupported(String::from("ASTC in dds")));
+        }
+    };
+
+    let mut dds = Dds::new_dxgi(NewDxgiParams {
+        height: texture.height(),
+        width: texture.width(),
+        depth: None,
+        format,
+        mipmap_levels: Some(texture.levels.len() as u32),
+        array_layers: None,
+        caps2: None,
+        is_cubemap: false,
+        resource_dimension: D3D10ResourceDimension::Texture2D,
+        alpha_mode: if texture.premultiplied {
+            AlphaMode::PreMultiplied
+        } else {
+            AlphaMode::Straight
+        },
+    })?;
+    // ddsfile's own size estimate is off for sizes that aren't a multiple of
+    // the block size, the levels are written as they are instead
+    dds.data = texture
+        .levels
+        .iter()
+        .flat_map(|level| level.data.iter().copied())
+        .collect();
+    dds.write(&mut BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+// Linear, premultiplied
+fn to_linear(data: &[u8], srgb: bool) -> Vec<Vec4> {
+    let table: Vec<f32> = (0..256)
+        .map(|v| {
+            let v = v as f32 / 255.0;
+            if srgb { srgb_to_linear(v) } else { v }
+        })
+        .collect();
+
+    data.chunks_exact(4)
+        .map(|texel| {
+            let alpha = texel[3] as f32 / 255.0;
+            let color = Vec4::new(
+                table[texel[0] as usize],
+                table[texel[1] as usize],
+                table[texel[2] as usize],
+                1.0,
+            );
+            color * alpha
+        })
+        .collect()
+}
+
+fn to_rgba8(texels: &[Vec4], srgb: bool, premultiplied: bool) -> Vec<u8> {
+    let encode = |v: f32| {
+        let v = v.clamp(0.0, 1.0);
+        let v = if srgb { linear_to_srgb(v) } else { v };
+        (v * 255.0).round() as u8
+    };
+
+    texels
+        .iter()
+        .flat_map(|&texel| {
+            let alpha = texel.w;
+            // fully transparent texels have no color left to recover
+            let color = if premultiplied {
+                texel
+            } else if alpha > 0.0 {
+                texel / alpha
+            } else {
+                Vec4::ZERO
+            };
+            [
+                encode(color.x),
+                encode(color.y),
+                encode(color.z),
+                (alpha.clamp(0.0, 1.0) * 255.0).round() as u8,
+            ]
+        })
+        .collect()
+}
+
+fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Halves both sides, down to 1. Separable box filter where every texel
+// averages the source texels its footprint covers, weighted by how much
+// they overlap, so odd sizes don't drop their last row or column
+fn downsample(width: u32, height: u32, texels: &[Vec4]) -> (u32, u32, Vec<Vec4>) {
+    let (src_width, src_height) = (width as usize, height as usize);
+    let (dst_width, dst_height) = ((src_width / 2).max(1), (src_height / 2).max(1));
+    let columns = footprints(src_width, dst_width);
+    let rows = footprints(src_height, dst_height);
+
+    let mut horizontal = Vec::with_capacity(dst_width * src_height);
+    for y in 0..src_height {
+        let row = &texels[y * src_width..(y + 1) * src_width];
+        horizontal.extend(columns.iter().map(|taps| {
+            taps.iter()
+                .map(|&(x, weight)| row[x] * weight)
+                .sum::<Vec4>()
+        }));
+    }
+
+    let mut out = Vec::with_capacity(dst_width * dst_height);
+    for taps in &rows {
+        out.extend((0..dst_width).map(|x| {
+            taps.iter()
+                .map(|&(y, weight)| horizontal[y * dst_width + x] * weight)
+                .sum::<Vec4>()
+        }));
+    }
+
+    (dst_width as u32, dst_height as u32, out)
+}
+
+// For each destination texel, the source texels under it and their weights
+fn footprints(src: usize, dst: usize) -> Vec<Vec<(usize, f32)>> {
+    let scale = src as f32 / dst as f32;
+    (0..dst)
+        .map(|i| {
+            let start = i as f32 * scale;
+            let end = start + scale;
+            (start.floor() as usize..(end.ceil() as usize).min(src))
+                .map(|s| {
+                    let overlap = end.min(s as f32 + 1.0) - start.max(s as f32);
+                    (s, overlap / scale)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(color_space: ColorSpace, premultiply_alpha: bool) -> TextureOptions {
+        TextureOptions {
+            color_space,
+            mipmaps: true,
+            premultiply_alpha,
+        }
+    }
+
+    fn sizes(texture: &TextureData) -> Vec<(u32, u32)> {
+        texture
+            .levels
+            .iter()
+            .map(|level| (level.width, level.height))
+            .collect()
+    }
+
+    const CHECKER: [u8; 16] = [
+        0, 0, 0, 255, 255, 255, 255, 255, //
+        255, 255, 255, 255, 0, 0, 0, 255,
+    ];
+
+    #[test]
+    fn srgb_checker_mips_to_linear_grey() {
+        let texture = from_rgba8(2, 2, CHECKER.to_vec(), options(ColorSpace::Srgb, false));
+        assert_eq!(sizes(&texture), [(2, 2), (1, 1)]);
+        assert_eq!(texture.levels[0].data, CHECKER);
+        // half the light is 188 in sRGB, averaging the bytes would give 128
+        assert_eq!(texture.levels[1].data, [188, 188, 188, 255]);
+
+        let texture = from_rgba8(2, 2, CHECKER.to_vec(), options(ColorSpace::Linear, false));
+        assert_eq!(texture.levels[1].data, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn odd_sizes_get_the_full_chain() {
+        for ((width, height), expected) in [
+            ((5, 3), &[(5, 3), (2, 1), (1, 1)][..]),
+            ((7, 1), &[(7, 1), (3, 1), (1, 1)][..]),
+            ((1, 9), &[(1, 9), (1, 4), (1, 2), (1, 1)][..]),
+        ] {
+            // the same color everywhere has to stay that color
+            let data = [200, 100, 50, 255].repeat((width * height) as usize);
+            let texture = from_rgba8(width, height, data, TextureOptions::default());
+            assert_eq!(sizes(&texture), expected);
+            for level in &texture.levels {
+                assert_eq!(level.data.len(), (level.width * level.height * 4) as usize);
+                for texel in level.data.chunks_exact(4) {
+                    assert_eq!(texel, [200, 100, 50, 255]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn footprints_cover_every_source_texel_once() {
+        for (src, dst) in [(2, 1), (3, 1), (5, 2), (7, 3), (9, 4)] {
+            let taps = footprints(src, dst);
+            assert_eq!(taps.len(), dst);
+
+            let mut coverage = vec![0.0; src];
+            for texel in &taps {
+                let total: f32 = texel.iter().map(|&(_, weight)| weight).sum();
+                assert!((total - 1.0).abs() < 1e-5);
+                for &(s, weight) in texel {
+                    coverage[s] += weight;
+                }
+            }
+            // every source texel counts as much as any other
+            for c in coverage {
+                assert!((c - dst as f32 / src as f32).abs() < 1e-5);
+            }
+        }
+
+        // the last row of an odd size isn't dropped
+        let (width, height, texels) = downsample(1, 3, &[Vec4::ZERO, Vec4::ZERO, Vec4::ONE]);
+        assert_eq!((width, height), (1, 1));
+        assert!(texels[0].abs_diff_eq(Vec4::splat(1.0 / 3.0), 1e-6));
+    }
+
+    #[test]
+    fn srgb_round_trips_every_byte() {
+        let data: Vec<u8> = (0..=255u8).flat_map(|v| [v, v, v, 255]).collect();
+        for srgb in [true, false] {
+            assert_eq!(to_rgba8(&to_linear(&data, srgb), srgb, false), data);
+        }
+    }
+
+    #[test]
+    fn transparent_texels_dont_bleed() {
+        // opaque red next to fully transparent green
+        let data = vec![255, 0, 0, 255, 0, 255, 0, 0];
+        let texture = from_rgba8(2, 1, data.clone(), options(ColorSpace::Srgb, false));
+        assert!(!texture.premultiplied);
+        assert_eq!(texture.levels[0].data, data);
+        assert_eq!(texture.levels[1].data, [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn premultiplies_alpha() {
+        let data = vec![255, 255, 255, 128, 255, 0, 0, 0];
+        let no_mips = TextureOptions {
+            mipmaps: false,
+            ..options(ColorSpace::Srgb, true)
+        };
+        let texture = from_rgba8(2, 1, data, no_mips);
+        assert!(texture.premultiplied);
+        assert_eq!(texture.levels.len(), 1);
+        // white at half alpha is half the light, encoded again
+        assert_eq!(texture.levels[0].data, [188, 188, 188, 128, 0, 0, 0, 0]);
+
+        let data = vec![255, 0, 0, 255, 0, 255, 0, 0];
+        let texture = from_rgba8(2, 1, data, options(ColorSpace::Linear, true));
+        assert_eq!(texture.levels[0].data, [255, 0, 0, 255, 0, 0, 0, 0]);
+        assert_eq!(texture.levels[1].data, [128, 0, 0, 128]);
+    }
+
+    #[test]
+    fn basis_ktx2_loads_as_bc7() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/Basis");
+        for (name, color_space) in [
+            ("gradient_etc1s.ktx2", ColorSpace::Srgb),
+            ("gradient_uastc.ktx2", ColorSpace::Linear),
+        ] {
+            let path = Path::new(dir).join(name);
+            let texture = load(&path, options(color_space, false)).unwrap();
+            assert_eq!(texture.format, TextureFormat::Bc7);
+            assert_eq!(texture.color_space, color_space);
+            let expected: Vec<_> = (0..6).map(|i| (32 >> i, 32 >> i)).collect();
+            assert_eq!(sizes(&texture), expected);
+
+            let no_mips = TextureOptions {
+                mipmaps: false,
+                ..options(color_space, false)
+            };
+            assert_eq!(load(&path, no_mips).unwrap().levels.len(), 1);
+        }
+    }
+}
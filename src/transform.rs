@@ -0,0 +1,435 @@
+// The transform hierarchy. Every entity has a local TRS relative to its
+// parent, or the whole matrix when it has shear a TRS can't hold (a rotated
+// child under a non-uniformly scaled parent, flattened by an importer). World
+// matrices are cached and only recomputed by `propagate` for
+// entities that moved or whose parent did. The world matrix from before the
+// current tick is kept too, frames are drawn between the two
+
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::ecs::{Entity, World};
+
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    translation: Vec3,
+    rotation: Quat,
+    scale: Vec3,
+    // the local matrix when the TRS above leaves out its shear
+    sheared: Option<Mat4>,
+    // as of the last propagate
+    world: Mat4,
+    // as of the tick before, None until there was one
+    previous: Option<Mat4>,
+    dirty: bool,
+}
+
+impl Transform {
+    // A matrix with shear is kept whole, its TRS is only as close as it goes
+    pub fn from_matrix(matrix: Mat4) -> Self {
+        let parts = decompose(matrix);
+        let (scale, rotation, translation) =
+            parts.unwrap_or_else(|| matrix.to_scale_rotation_translation());
+        Self {
+            translation,
+            rotation,
+            scale,
+            sheared: parts.is_none().then_some(matrix),
+            world: matrix,
+            previous: None,
+            dirty: true,
+        }
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        self.translation
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.rotation
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.scale
+    }
+
+    // Drops any shear, the transform is just its TRS from now on
+    pub fn set_rotation(&mut self, rotation: Quat) {
+        self.rotation = rotation;
+        self.sheared = None;
+        self.dirty = true;
+    }
+
+    pub fn set_matrix(&mut self, matrix: Mat4) {
+        let (world, previous) = (self.world, self.previous);
+        *self = Self::from_matrix(matrix);
+        self.world = world;
+        self.previous = previous;
+    }
+
+    // Relative to the parent
+    pub fn local(&self) -> Mat4 {
+        self.sheared.unwrap_or_else(|| {
+            Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+        })
+    }
+
+    pub fn world(&self) -> Mat4 {
+        self.world
+    }
+
+    // Between the world matrix of the tick before (0) and this one (1).
+    // Matrices with shear are blended element by element instead
+    pub fn interpolated(&self, alpha: f32) -> Mat4 {
+        let Some(previous) = self.previous.filter(|&previous| previous != self.world) else {
+            return self.world;
+        };
+        let (Some(from), Some(to)) = (decompose(previous), decompose(self.world)) else {
+            return previous * (1.0 - alpha) + self.world * alpha;
+        };
+        let ((scale0, rotation0, translation0), (scale1, rotation1, translation1)) = (from, to);
+        Mat4::from_scale_rotation_translation(
+            scale0.lerp(scale1, alpha),
+            rotation0.slerp(rotation1, alpha),
+            translation0.lerp(translation1, alpha),
+        )
+    }
+}
+
+// None when the matrix has shear, rebuilding it from the TRS would lose that
+fn decompose(matrix: Mat4) -> Option<(Vec3, Quat, Vec3)> {
+    let (scale, rotation, translation) = matrix.to_scale_rotation_translation();
+    let rebuilt = Mat3::from_quat(rotation) * Mat3::from_diagonal(scale);
+    let tolerance = 1e-4 * scale.abs().max_element().max(1.0);
+    Mat3::from_mat4(matrix)
+        .abs_diff_eq(rebuilt, tolerance)
+        .then_some((scale, rotation, translation))
+}
+
+// The entity moves along with this one. Set with `set_parent` or
+// `reparent`, which keep `Children` in sync
+#[derive(Copy, Clone, Debug)]
+pub struct Parent(pub Entity);
+
+#[derive(Clone, Debug)]
+pub struct Children(pub Vec<Entity>);
+
+// The local transform stays as it is, relative to the new parent from now
+// on. None makes the entity a root. Refused when it would make a loop
+pub fn set_parent(world: &mut World, entity: Entity, parent: Option<Entity>) -> bool {
+    if let Some(parent) = parent
+        && is_ancestor(world, entity, parent)
+    {
+        log::warn!("{:?} is above {:?}, not parenting it there", entity, parent);
+        return false;
+    }
+
+    if let Some(old) = self::parent(world, entity)
+        && let Some(children) = world.get_mut::<Children>(old)
+    {
+        children.0.retain(|&child| child != entity);
+    }
+
+    match parent {
+        Some(parent) => {
+            world.insert(entity, Parent(parent));
+            match world.get_mut::<Children>(parent) {
+                Some(children) => children.0.push(entity),
+                None => world.insert(parent, Children(vec![entity])),
+            }
+        }
+        None => {
+            world.remove::<Parent>(entity);
+        }
+    }
+    if let Some(transform) = world.get_mut::<Transform>(entity) {
+        transform.dirty = true;
+    }
+    true
+}
+
+pub fn parent(world: &World, entity: Entity) -> Option<Entity> {
+    world.get::<Parent>(entity).map(|&Parent(parent)| parent)
+}
+
+// Like `set_parent`, but the entity stays where it is in the world
+pub fn reparent(world: &mut World, entity: Entity, parent: Option<Entity>) -> bool {
+    let current = world_matrix(world, Some(entity));
+    let parent_world = world_matrix(world, parent);
+    if !set_parent(world, entity, parent) {
+        return false;
+    }
+    if let Some(transform) = world.get_mut::<Transform>(entity) {
+        transform.set_matrix(parent_world.inverse() * current);
+    }
+    true
+}
+
+// Takes everything below it along, and out of its parent's children
+pub fn despawn_recursive(world: &mut World, entity: Entity) {
+    set_parent(world, entity, None);
+    despawn_tree(world, entity);
+}
+
+// Brings the cached world matrices up to date. Entities whose parent was
+// despawned become roots, their world matrix still has the parent's in it
+pub fn propagate(world: &mut World) {
+    let orphans: Vec<Entity> = world
+        .query::<Parent>()
+        .filter(|&(_, &Parent(parent))| !world.is_alive(parent))
+        .map(|(entity, _)| entity)
+        .collect();
+    for orphan in orphans {
+        world.remove::<Parent>(orphan);
+        if let Some(transform) = world.get_mut::<Transform>(orphan) {
+            transform.dirty = true;
+        }
+    }
+
+    let roots: Vec<Entity> = world
+        .query::<Transform>()
+        .map(|(entity, _)| entity)
+        .filter(|&entity| parent(world, entity).is_none())
+        .collect();
+    for root in roots {
+        update(world, root, Mat4::IDENTITY, false);
+    }
+}
+
+fn update(world: &mut World, entity: Entity, parent_world: Mat4, parent_changed: bool) {
+    let (matrix, changed) = match world.get_mut::<Transform>(entity) {
+        Some(transform) => {
+            let changed = parent_changed || transform.dirty;
+            if changed {
+                transform.world = parent_world * transform.local();
+                transform.dirty = false;
+            }
+            (transform.world, changed)
+        }
+        None => (parent_world, parent_changed),
+    };
+
+    let count = world
+        .get::<Children>(entity)
+        .map_or(0, |children| children.0.len());
+    for i in 0..count {
+        let child = world.get::<Children>(entity).unwrap().0[i];
+        update(world, child, matrix, changed);
+    }
+}
+
+// Keeps every world matrix as the one before, call at the start of a tick
+pub fn snapshot(world: &mut World) {
+    for (_, transform) in world.query_mut::<Transform>() {
+        transform.previous = Some(transform.world);
+    }
+}
+
+// Computed from the local transforms, for when the cache may be stale
+fn world_matrix(world: &World, entity: Option<Entity>) -> Mat4 {
+    let mut matrix = Mat4::IDENTITY;
+    let mut current = entity;
+    while let Some(entity) = current {
+        if let Some(transform) = world.get::<Transform>(entity) {
+            matrix = transform.local() * matrix;
+        }
+        current = parent(world, entity);
+    }
+    matrix
+}
+
+fn is_ancestor(world: &World, ancestor: Entity, entity: Entity) -> bool {
+    let mut current = Some(entity);
+    while let Some(entity) = current {
+        if entity == ancestor {
+            return true;
+        }
+        current = parent(world, entity);
+    }
+    false
+}
+
+fn despawn_tree(world: &mut World, entity: Entity) {
+    if let Some(Children(children)) = world.get::<Children>(entity).cloned() {
+        for child in children {
+            despawn_tree(world, child);
+        }
+    }
+    world.despawn(entity);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(world: &mut World, translation: Vec3) -> Entity {
+        let entity = world.spawn();
+        world.insert(
+            entity,
+            Transform::from_matrix(Mat4::from_translation(translation)),
+        );
+        entity
+    }
+
+    fn translation(world: &World, entity: Entity) -> Vec3 {
+        world
+            .get::<Transform>(entity)
+            .unwrap()
+            .world()
+            .w_axis
+            .truncate()
+    }
+
+    #[test]
+    fn only_dirty_entities_are_recomputed() {
+        let mut world = World::default();
+        let a = spawn(&mut world, Vec3::X);
+        let b = spawn(&mut world, Vec3::Y);
+        let child = spawn(&mut world, Vec3::Z);
+        set_parent(&mut world, child, Some(a));
+        propagate(&mut world);
+        assert_eq!(translation(&world, child), Vec3::new(1.0, 0.0, 1.0));
+
+        // a stale cache that only a recompute would overwrite
+        let stale = Mat4::from_translation(Vec3::splat(9.0));
+        for entity in [a, b, child] {
+            world.get_mut::<Transform>(entity).unwrap().world = stale;
+        }
+        world
+            .get_mut::<Transform>(a)
+            .unwrap()
+            .set_rotation(Quat::IDENTITY);
+        propagate(&mut world);
+        assert_eq!(translation(&world, a), Vec3::X);
+        assert_eq!(translation(&world, child), Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(world.get::<Transform>(b).unwrap().world(), stale);
+    }
+
+    #[test]
+    fn reparent_keeps_the_world_pose() {
+        let mut world = World::default();
+        let a = spawn(&mut world, Vec3::new(1.0, 2.0, 3.0));
+        let b = spawn(&mut world, Vec3::ZERO);
+        world
+            .get_mut::<Transform>(b)
+            .unwrap()
+            .set_matrix(Mat4::from_scale_rotation_translation(
+                Vec3::splat(2.0),
+                Quat::from_rotation_y(1.0),
+                Vec3::new(-4.0, 0.0, 1.0),
+            ));
+        let child = spawn(&mut world, Vec3::new(0.5, 0.0, 0.0));
+        set_parent(&mut world, child, Some(a));
+        propagate(&mut world);
+        let before = world.get::<Transform>(child).unwrap().world();
+
+        assert!(reparent(&mut world, child, Some(b)));
+        propagate(&mut world);
+        assert_eq!(parent(&world, child), Some(b));
+        assert!(world.get::<Children>(a).unwrap().0.is_empty());
+        assert_eq!(world.get::<Children>(b).unwrap().0, [child]);
+        let after = world.get::<Transform>(child).unwrap().world();
+        assert!(after.abs_diff_eq(before, 1e-5), "{} vs {}", after, before);
+
+        assert!(reparent(&mut world, child, None));
+        propagate(&mut world);
+        assert_eq!(parent(&world, child), None);
+        let root = world.get::<Transform>(child).unwrap().world();
+        assert!(root.abs_diff_eq(before, 1e-5), "{} vs {}", root, before);
+    }
+
+    #[test]
+    fn set_parent_refuses_loops() {
+        let mut world = World::default();
+        let a = spawn(&mut world, Vec3::X);
+        let b = spawn(&mut world, Vec3::Y);
+        let c = spawn(&mut world, Vec3::Z);
+        assert!(set_parent(&mut world, b, Some(a)));
+        assert!(set_parent(&mut world, c, Some(b)));
+
+        assert!(!set_parent(&mut world, a, Some(a)));
+        assert!(!set_parent(&mut world, a, Some(c)));
+        assert!(!reparent(&mut world, a, Some(b)));
+        assert_eq!(parent(&world, a), None);
+        assert!(world.get::<Children>(c).is_none());
+        propagate(&mut world);
+        assert_eq!(translation(&world, c), Vec3::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn sheared_matrices_are_kept_whole() {
+        // what an importer flattens a rotated child of a non-uniformly
+        // scaled node to
+        let parent = Mat4::from_scale(Vec3::new(2.0, 1.0, 1.0));
+        let child = Mat4::from_rotation_translation(
+            Quat::from_rotation_z(std::f32::consts::FRAC_PI_4),
+            Vec3::new(0.0, 3.0, 0.0),
+        );
+        let sheared = parent * child;
+        assert!(decompose(sheared).is_none());
+        assert!(decompose(child).is_some());
+
+        let mut world = World::default();
+        let root = spawn(&mut world, Vec3::new(1.0, 2.0, 3.0));
+        let mesh = world.spawn();
+        world.insert(mesh, Transform::from_matrix(sheared));
+        set_parent(&mut world, mesh, Some(root));
+        propagate(&mut world);
+        let expected = Mat4::from_translation(Vec3::new(1.0, 2.0, 3.0)) * sheared;
+        let matrix = world.get::<Transform>(mesh).unwrap().world();
+        assert!(
+            matrix.abs_diff_eq(expected, 1e-5),
+            "{} vs {}",
+            matrix,
+            expected
+        );
+
+        // the same two as a hierarchy end up in the same place
+        let node = world.spawn();
+        world.insert(node, Transform::from_matrix(parent));
+        let leaf = world.spawn();
+        world.insert(leaf, Transform::from_matrix(child));
+        set_parent(&mut world, leaf, Some(node));
+        propagate(&mut world);
+        let matrix = world.get::<Transform>(leaf).unwrap().world();
+        assert!(
+            matrix.abs_diff_eq(sheared, 1e-5),
+            "{} vs {}",
+            matrix,
+            sheared
+        );
+
+        snapshot(&mut world);
+        let moved = Mat4::from_translation(Vec3::X) * sheared;
+        world.get_mut::<Transform>(mesh).unwrap().set_matrix(moved);
+        propagate(&mut world);
+        let transform = world.get::<Transform>(mesh).unwrap();
+        let halfway = transform.interpolated(0.5);
+        let expected = Mat4::from_translation(Vec3::new(1.5, 2.0, 3.0)) * sheared;
+        assert!(
+            halfway.abs_diff_eq(expected, 1e-5),
+            "{} vs {}",
+            halfway,
+            expected
+        );
+        assert!(
+            transform
+                .interpolated(1.0)
+                .abs_diff_eq(transform.world(), 1e-5)
+        );
+    }
+
+    #[test]
+    fn orphans_become_roots() {
+        let mut world = World::default();
+        let a = spawn(&mut world, Vec3::X);
+        let child = spawn(&mut world, Vec3::Y);
+        set_parent(&mut world, child, Some(a));
+        propagate(&mut world);
+        assert_eq!(translation(&world, child), Vec3::new(1.0, 1.0, 0.0));
+
+        world.despawn(a);
+        propagate(&mut world);
+        assert_eq!(parent(&world, child), None);
+        assert_eq!(translation(&world, child), Vec3::Y);
+    }
+}
@@ -0,0 +1,56 @@
+// Hot reload: a set of files and which of them changed since the last poll.
+// The app keeps two, one Scene::watch fills with the assets' files (changes
+// go to Scene::reload_changed) and one for the shader and its headers
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Polls modification times instead of subscribing to file system events. A
+// model is a few dozen files, checking them twice a second is cheap and
+// needs no platform code
+const POLL_INTERVAL: f64 = 0.5;
+
+#[derive(Default)]
+pub struct FileWatcher {
+    // None while the file doesn't exist
+    files: HashMap<PathBuf, Option<SystemTime>>,
+    last_poll: f64,
+}
+
+impl FileWatcher {
+    // Watches exactly these paths. Ones already watched keep the time they
+    // were last seen with, so a change between polls isn't lost
+    pub fn watch_only<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) {
+        let mut files = HashMap::new();
+        for path in paths {
+            let seen = self.files.remove(path).unwrap_or_else(|| modified(path));
+            files.insert(path.to_path_buf(), seen);
+        }
+        self.files = files;
+    }
+
+    // Files that were written, created or deleted since the last poll.
+    // Empty until POLL_INTERVAL seconds of `time` have passed
+    pub fn poll(&mut self, time: f64) -> Vec<PathBuf> {
+        if time - self.last_poll < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = time;
+
+        let mut changed = Vec::new();
+        for (path, seen) in &mut self.files {
+            let current = modified(path);
+            if current != *seen {
+                *seen = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}